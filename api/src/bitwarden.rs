@@ -0,0 +1,668 @@
+use std::{collections::HashMap, fs};
+
+use aes::cipher::{block_padding::Pkcs7, BlockDecryptMut, BlockEncryptMut, KeyIvInit};
+use argon2::{Algorithm, Argon2, Params, Version};
+use base64::{engine::general_purpose::STANDARD as BASE64, Engine};
+use hkdf::Hkdf;
+use hmac::{Hmac, Mac};
+use sea_orm::prelude::Uuid;
+use serde::{Deserialize, Serialize};
+use serde_json::{Map, Value};
+use sha2::{Digest, Sha256};
+
+use crate::{
+    container::{MAX_ITERATIONS, MAX_MEMORY_KIB},
+    custom_fields::CustomField,
+    entry_transfer::{self, DecryptedEntry, ExportSelection, ImportOptions, ImportSummary},
+    error::Error,
+};
+
+type Aes256CbcEnc = cbc::Encryptor<aes::Aes256>;
+type Aes256CbcDec = cbc::Decryptor<aes::Aes256>;
+
+const LOGIN: u8 = 1;
+const SECURE_NOTE: u8 = 2;
+const CARD: u8 = 3;
+const IDENTITY: u8 = 4;
+const SSH_KEY: u8 = 5;
+
+const FIELD_HIDDEN: u8 = 1;
+const FIELD_LINKED: u8 = 3;
+
+/// Iterations used for password protected exports, the Bitwarden default
+const PBKDF2_ITERATIONS: u32 = 600_000;
+
+/// The upper bounds accepted for the KDF of a password protected export, the most Bitwarden
+/// allows. Argon2 uses the bounds of our own exports (see `container`).
+const MAX_PBKDF2_ITERATIONS: u32 = 2_000_000;
+const MAX_ARGON2_PARALLELISM: u32 = 16;
+
+/// The keys of a card, with their label and whether the value is sensitive
+const CARD_FIELDS: [(&str, &str, bool); 6] = [
+    ("cardholderName", "Cardholder name", false),
+    ("brand", "Brand", false),
+    ("number", "Number", true),
+    ("expMonth", "Expiration month", false),
+    ("expYear", "Expiration year", false),
+    ("code", "Security code", true),
+];
+
+const IDENTITY_FIELDS: [(&str, &str, bool); 17] = [
+    ("title", "Title", false),
+    ("firstName", "First name", false),
+    ("middleName", "Middle name", false),
+    ("lastName", "Last name", false),
+    ("company", "Company", false),
+    ("email", "Email", false),
+    ("phone", "Phone", false),
+    ("address1", "Address 1", false),
+    ("address2", "Address 2", false),
+    ("address3", "Address 3", false),
+    ("city", "City", false),
+    ("state", "State", false),
+    ("postalCode", "Postal code", false),
+    ("country", "Country", false),
+    ("ssn", "Social security number", true),
+    ("passportNumber", "Passport number", true),
+    ("licenseNumber", "License number", true),
+];
+
+/// An unencrypted Bitwarden JSON export
+#[derive(Debug, Default, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+struct Export {
+    #[serde(default)]
+    encrypted: bool,
+    #[serde(default)]
+    folders: Vec<Folder>,
+    #[serde(default)]
+    items: Vec<Item>,
+}
+
+/// A password protected export, wrapping the unencrypted export in `data`
+#[derive(Debug, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+struct ProtectedExport {
+    encrypted: bool,
+    #[serde(default)]
+    password_protected: bool,
+    salt: String,
+    kdf_type: u8,
+    kdf_iterations: u32,
+    kdf_memory: Option<u32>,
+    kdf_parallelism: Option<u32>,
+    #[serde(rename = "encKeyValidation_DO_NOT_EDIT")]
+    enc_key_validation: String,
+    data: String,
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+struct Folder {
+    id: String,
+    name: String,
+}
+
+#[derive(Debug, Default, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+struct Item {
+    id: Option<String>,
+    organization_id: Option<String>,
+    folder_id: Option<String>,
+    #[serde(rename = "type")]
+    kind: u8,
+    #[serde(default)]
+    reprompt: u8,
+    #[serde(default)]
+    name: String,
+    notes: Option<String>,
+    #[serde(default)]
+    favorite: bool,
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    fields: Vec<Field>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    login: Option<Login>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    card: Option<Map<String, Value>>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    identity: Option<Map<String, Value>>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    ssh_key: Option<Map<String, Value>>,
+    collection_ids: Option<Vec<String>>,
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+struct Field {
+    name: Option<String>,
+    value: Option<String>,
+    #[serde(rename = "type")]
+    kind: u8,
+    linked_id: Option<u32>,
+}
+
+#[derive(Debug, Default, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+struct Login {
+    #[serde(default)]
+    uris: Option<Vec<LoginUri>>,
+    username: Option<String>,
+    password: Option<String>,
+    totp: Option<String>,
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+struct LoginUri {
+    #[serde(rename = "match")]
+    match_type: Option<u8>,
+    uri: Option<String>,
+}
+
+/// Whether a Bitwarden export is protected by a password, which must then be given to import it
+pub fn is_password_protected(path: String) -> Result<bool, Error> {
+    let json = read_json(&path)?;
+    Ok(json["encrypted"].as_bool().unwrap_or(false))
+}
+
+/// Imports a Bitwarden JSON export, either unencrypted or password protected.
+/// Folders are kept, cards and identities are imported with their details as custom fields.
+pub async fn import_bitwarden(
+    master_password: String,
+    path: String,
+    password: Option<String>,
+    options: ImportOptions,
+) -> Result<ImportSummary, Error> {
+    let json = read_json(&path)?;
+    let entries = read_export(json, password.as_deref())?;
+    entry_transfer::create_entries(master_password, entries, options).await
+}
+
+/// Exports the selected entries as Bitwarden JSON, password protected when a password is given
+pub async fn export_bitwarden(
+    master_password: String,
+    path: String,
+    password: Option<String>,
+    selection: &ExportSelection,
+) -> Result<usize, Error> {
+    let entries = entry_transfer::decrypt_entries(master_password, selection).await?;
+    let count = entries.len();
+    let json = write_export(entries, password.as_deref())?;
+    entry_transfer::write_private_file(&path, json.as_bytes())?;
+    Ok(count)
+}
+
+/// The entries of a Bitwarden export, decrypted with the password when it is protected
+fn read_export(json: Value, password: Option<&str>) -> Result<Vec<DecryptedEntry>, Error> {
+    let export: Export = if json["encrypted"].as_bool().unwrap_or(false) {
+        let protected: ProtectedExport =
+            serde_json::from_value(json).map_err(|e| format!("Invalid Bitwarden export: {}", e))?;
+        let password = password.ok_or("The export is password protected")?;
+        let data = decrypt_export(&protected, password)?;
+        serde_json::from_slice(&data).map_err(|e| format!("Invalid Bitwarden export: {}", e))?
+    } else {
+        serde_json::from_value(json).map_err(|e| format!("Invalid Bitwarden export: {}", e))?
+    };
+
+    let folders: HashMap<String, String> = export
+        .folders
+        .into_iter()
+        .map(|folder| (folder.id, folder.name))
+        .collect();
+    Ok(export
+        .items
+        .into_iter()
+        .map(|item| to_entry(item, &folders))
+        .collect())
+}
+
+/// The entries as Bitwarden JSON, password protected when a password is given
+fn write_export(entries: Vec<DecryptedEntry>, password: Option<&str>) -> Result<String, Error> {
+    let mut folders: Vec<Folder> = Vec::new();
+    let mut items = Vec::new();
+    for entry in entries {
+        let folder_id = entry.folder.as_ref().map(|name| {
+            match folders.iter().find(|folder| &folder.name == name) {
+                Some(folder) => folder.id.to_owned(),
+                None => {
+                    let id = Uuid::new_v4().to_string();
+                    folders.push(Folder {
+                        id: id.to_owned(),
+                        name: name.to_owned(),
+                    });
+                    id
+                }
+            }
+        });
+        items.push(from_entry(entry, folder_id));
+    }
+
+    let export = Export {
+        encrypted: false,
+        folders,
+        items,
+    };
+    let err = "Failed to write the Bitwarden export";
+    let json = serde_json::to_string_pretty(&export).map_err(|_| err)?;
+    match password {
+        Some(password) => {
+            let protected = encrypt_export(json.as_bytes(), password)?;
+            Ok(serde_json::to_string_pretty(&protected).map_err(|_| err)?)
+        }
+        None => Ok(json),
+    }
+}
+
+fn read_json(path: &str) -> Result<Value, Error> {
+    let contents = fs::read_to_string(path).map_err(|_| format!("Failed to read {}", path))?;
+    serde_json::from_str(&contents).map_err(|e| format!("Invalid Bitwarden export: {}", e))
+}
+
+fn to_entry(item: Item, folders: &HashMap<String, String>) -> DecryptedEntry {
+    let mut entry = DecryptedEntry {
+        name: item.name,
+        description: item.notes.filter(|n| !n.is_empty()),
+        folder: item.folder_id.and_then(|id| folders.get(&id).cloned()),
+        ..Default::default()
+    };
+
+    match item.kind {
+        LOGIN => {
+            let login = item.login.unwrap_or_default();
+            entry.username = login.username.filter(|u| !u.is_empty());
+            entry.password = login.password.unwrap_or_default();
+            entry.totp = login.totp.filter(|t| !t.is_empty());
+            let mut uris = login
+                .uris
+                .unwrap_or_default()
+                .into_iter()
+                .filter_map(|u| u.uri);
+            entry.url = uris.next();
+            for (index, uri) in uris.enumerate() {
+                entry.custom_fields.push(CustomField::new(
+                    format!("URL {}", index + 2),
+                    uri,
+                    false,
+                ));
+            }
+        }
+        CARD => {
+            let card = item.card.unwrap_or_default();
+            entry.custom_fields.extend(map_fields(&card, &CARD_FIELDS));
+        }
+        IDENTITY => {
+            let identity = item.identity.unwrap_or_default();
+            entry.username = string(&identity, "username");
+            entry
+                .custom_fields
+                .extend(map_fields(&identity, &IDENTITY_FIELDS));
+        }
+        SSH_KEY => {
+            let ssh_key = item.ssh_key.unwrap_or_default();
+            entry.password = string(&ssh_key, "privateKey").unwrap_or_default();
+            if let Some(public_key) = string(&ssh_key, "publicKey") {
+                entry
+                    .custom_fields
+                    .push(CustomField::new("Public key", public_key, false));
+            }
+        }
+        // Secure notes only have the notes
+        SECURE_NOTE => {}
+        // Item types added later are still imported with their name, notes and fields
+        _ => {}
+    }
+
+    for field in item.fields {
+        // Linked fields only point to another field of the item
+        if field.kind == FIELD_LINKED {
+            continue;
+        }
+        entry.custom_fields.push(CustomField::new(
+            field.name.unwrap_or_default(),
+            field.value.unwrap_or_default(),
+            field.kind == FIELD_HIDDEN,
+        ));
+    }
+    entry
+}
+
+fn from_entry(entry: DecryptedEntry, folder_id: Option<String>) -> Item {
+    Item {
+        id: Some(Uuid::new_v4().to_string()),
+        folder_id,
+        kind: LOGIN,
+        name: entry.name,
+        notes: entry.description,
+        fields: entry
+            .custom_fields
+            .into_iter()
+            .map(|field| Field {
+                name: Some(field.name),
+                value: Some(field.value),
+                kind: if field.protected { FIELD_HIDDEN } else { 0 },
+                linked_id: None,
+            })
+            .collect(),
+        login: Some(Login {
+            uris: Some(
+                entry
+                    .url
+                    .into_iter()
+                    .map(|uri| LoginUri {
+                        match_type: None,
+                        uri: Some(uri),
+                    })
+                    .collect(),
+            ),
+            username: entry.username,
+            password: Some(entry.password),
+            totp: entry.totp,
+        }),
+        ..Default::default()
+    }
+}
+
+fn string(map: &Map<String, Value>, key: &str) -> Option<String> {
+    map.get(key)
+        .and_then(|v| v.as_str())
+        .filter(|v| !v.is_empty())
+        .map(|v| v.to_owned())
+}
+
+fn map_fields(map: &Map<String, Value>, fields: &[(&str, &str, bool)]) -> Vec<CustomField> {
+    fields
+        .iter()
+        .filter_map(|(key, label, protected)| {
+            Some(CustomField::new(*label, string(map, key)?, *protected))
+        })
+        .collect()
+}
+
+/// The encryption and MAC keys of a password protected export: the password is stretched
+/// with the export KDF, then expanded with HKDF.
+fn derive_keys(
+    password: &str,
+    salt: &str,
+    protected: &ProtectedExport,
+) -> Result<([u8; 32], [u8; 32]), Error> {
+    let err = "Failed to derive the export key";
+    let out_of_range = "The key derivation settings of the export are out of range";
+    let mut key = [0u8; 32];
+    match protected.kdf_type {
+        0 => {
+            let iterations = protected.kdf_iterations;
+            if iterations == 0 || iterations > MAX_PBKDF2_ITERATIONS {
+                return Err(out_of_range.to_owned());
+            }
+            pbkdf2::pbkdf2_hmac::<Sha256>(
+                password.as_bytes(),
+                salt.as_bytes(),
+                iterations,
+                &mut key,
+            )
+        }
+        1 => {
+            // The memory is given in MiB
+            let memory = protected
+                .kdf_memory
+                .unwrap_or(64)
+                .checked_mul(1024)
+                .filter(|memory| *memory <= MAX_MEMORY_KIB)
+                .ok_or(out_of_range)?;
+            let iterations = protected.kdf_iterations;
+            let parallelism = protected.kdf_parallelism.unwrap_or(4);
+            if iterations > MAX_ITERATIONS || parallelism > MAX_ARGON2_PARALLELISM {
+                return Err(out_of_range.to_owned());
+            }
+            let params =
+                Params::new(memory, iterations, parallelism, Some(32)).map_err(|_| out_of_range)?;
+            Argon2::new(Algorithm::Argon2id, Version::V0x13, params)
+                .hash_password_into(password.as_bytes(), &Sha256::digest(salt), &mut key)
+                .map_err(|_| err)?;
+        }
+        kdf => return Err(format!("Unsupported Bitwarden KDF {}", kdf)),
+    }
+
+    let hkdf = Hkdf::<Sha256>::from_prk(&key).map_err(|_| err)?;
+    let mut enc_key = [0u8; 32];
+    let mut mac_key = [0u8; 32];
+    hkdf.expand(b"enc", &mut enc_key).map_err(|_| err)?;
+    hkdf.expand(b"mac", &mut mac_key).map_err(|_| err)?;
+    Ok((enc_key, mac_key))
+}
+
+fn decrypt_export(protected: &ProtectedExport, password: &str) -> Result<Vec<u8>, Error> {
+    if !protected.password_protected {
+        return Err(
+            "Exports encrypted with the Bitwarden account key cannot be imported, export with a password instead"
+                .to_owned(),
+        );
+    }
+    let keys = derive_keys(password, &protected.salt, protected)?;
+    decrypt_string(&protected.enc_key_validation, &keys).map_err(|_| "Invalid export password")?;
+    decrypt_string(&protected.data, &keys)
+}
+
+fn encrypt_export(data: &[u8], password: &str) -> Result<ProtectedExport, Error> {
+    let salt = BASE64.encode(rand::random::<[u8; 16]>());
+    let mut protected = ProtectedExport {
+        encrypted: true,
+        password_protected: true,
+        salt: salt.to_owned(),
+        kdf_type: 0,
+        kdf_iterations: PBKDF2_ITERATIONS,
+        kdf_memory: None,
+        kdf_parallelism: None,
+        enc_key_validation: String::new(),
+        data: String::new(),
+    };
+    let keys = derive_keys(password, &salt, &protected)?;
+    protected.enc_key_validation = encrypt_string(Uuid::new_v4().to_string().as_bytes(), &keys)?;
+    protected.data = encrypt_string(data, &keys)?;
+    Ok(protected)
+}
+
+/// Decrypts a Bitwarden "EncString" of type 2: `2.<iv>|<ciphertext>|<mac>`,
+/// AES-256-CBC with an HMAC-SHA256 over the IV and ciphertext
+fn decrypt_string(enc: &str, (enc_key, mac_key): &([u8; 32], [u8; 32])) -> Result<Vec<u8>, Error> {
+    let err = "Failed to decrypt the Bitwarden export";
+    let parts = enc
+        .strip_prefix("2.")
+        .ok_or("Unsupported Bitwarden encryption")?;
+    let parts: Vec<Vec<u8>> = parts
+        .split('|')
+        .map(|part| BASE64.decode(part))
+        .collect::<Result<_, _>>()
+        .map_err(|_| err)?;
+    let [iv, ciphertext, mac] = parts.as_slice() else {
+        return Err(err.to_owned());
+    };
+
+    let mut hmac = Hmac::<Sha256>::new_from_slice(mac_key).map_err(|_| err)?;
+    hmac.update(iv);
+    hmac.update(ciphertext);
+    hmac.verify_slice(mac).map_err(|_| err)?;
+
+    Aes256CbcDec::new_from_slices(enc_key, iv)
+        .map_err(|_| err)?
+        .decrypt_padded_vec_mut::<Pkcs7>(ciphertext)
+        .map_err(|_| err.to_owned())
+}
+
+fn encrypt_string(data: &[u8], (enc_key, mac_key): &([u8; 32], [u8; 32])) -> Result<String, Error> {
+    let err = "Failed to encrypt the Bitwarden export";
+    let iv: [u8; 16] = rand::random();
+    let ciphertext = Aes256CbcEnc::new_from_slices(enc_key, &iv)
+        .map_err(|_| err)?
+        .encrypt_padded_vec_mut::<Pkcs7>(data);
+
+    let mut hmac = Hmac::<Sha256>::new_from_slice(mac_key).map_err(|_| err)?;
+    hmac.update(&iv);
+    hmac.update(&ciphertext);
+    let mac = hmac.finalize().into_bytes();
+    Ok(format!(
+        "2.{}|{}|{}",
+        BASE64.encode(iv),
+        BASE64.encode(ciphertext),
+        BASE64.encode(mac)
+    ))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const PASSWORD: &str = "correct horse battery staple";
+
+    /// Password protected exports in the format of the Bitwarden clients, with the default
+    /// PBKDF2 and Argon2id settings, holding a login, a card and a secure note
+    const PBKDF2_EXPORT: &str = include_str!("../testdata/bitwarden-pbkdf2.json");
+    const ARGON2ID_EXPORT: &str = include_str!("../testdata/bitwarden-argon2id.json");
+
+    fn read(export: &str, password: Option<&str>) -> Result<Vec<DecryptedEntry>, Error> {
+        read_export(serde_json::from_str(export).unwrap(), password)
+    }
+
+    #[test]
+    fn password_protected_exports_are_decrypted() {
+        for export in [PBKDF2_EXPORT, ARGON2ID_EXPORT] {
+            let entries = read(export, Some(PASSWORD)).unwrap();
+            let [login, card, note] = entries.as_slice() else {
+                panic!("expected 3 entries, got {:?}", entries);
+            };
+
+            assert_eq!(
+                login,
+                &DecryptedEntry {
+                    name: "GitHub".to_owned(),
+                    description: Some("Work account".to_owned()),
+                    username: Some("octocat".to_owned()),
+                    url: Some("https://github.com/login".to_owned()),
+                    password: "hunter2 with spaces".to_owned(),
+                    totp: Some("JBSWY3DPEHPK3PXP".to_owned()),
+                    folder: Some("Work".to_owned()),
+                    custom_fields: vec![
+                        CustomField::new("URL 2", "https://gist.github.com", false),
+                        CustomField::new("Recovery code", "ab12-cd34", true),
+                        CustomField::new("Team", "Platform", false),
+                    ],
+                    ..Default::default()
+                }
+            );
+            assert_eq!(card.name, "Visa");
+            assert_eq!(card.folder, None);
+            assert_eq!(
+                card.custom_fields,
+                vec![
+                    CustomField::new("Cardholder name", "Jane Doe", false),
+                    CustomField::new("Brand", "Visa", false),
+                    CustomField::new("Number", "4111111111111111", true),
+                    CustomField::new("Expiration month", "4", false),
+                    CustomField::new("Expiration year", "2030", false),
+                    CustomField::new("Security code", "123", true),
+                ]
+            );
+            assert_eq!(note.name, "Wifi");
+            assert_eq!(
+                note.description.as_deref(),
+                Some("The guest network is open")
+            );
+            assert!(note.password.is_empty());
+        }
+    }
+
+    #[test]
+    fn password_protected_exports_need_the_password() {
+        assert_eq!(
+            read(PBKDF2_EXPORT, None).unwrap_err(),
+            "The export is password protected"
+        );
+        assert_eq!(
+            read(ARGON2ID_EXPORT, Some("wrong password")).unwrap_err(),
+            "Invalid export password"
+        );
+    }
+
+    #[test]
+    fn out_of_range_kdf_settings_are_refused() {
+        let settings = [
+            (PBKDF2_EXPORT, "kdfIterations", 0),
+            (PBKDF2_EXPORT, "kdfIterations", 10_000_000),
+            (ARGON2ID_EXPORT, "kdfIterations", 1_000),
+            (ARGON2ID_EXPORT, "kdfMemory", 1024 * 1024),
+            (ARGON2ID_EXPORT, "kdfMemory", u32::MAX),
+            (ARGON2ID_EXPORT, "kdfParallelism", 1_000),
+        ];
+        for (export, key, value) in settings {
+            let mut json: Value = serde_json::from_str(export).unwrap();
+            json[key] = value.into();
+            assert_eq!(
+                read_export(json, Some(PASSWORD)).unwrap_err(),
+                "The key derivation settings of the export are out of range",
+                "{} {}",
+                key,
+                value
+            );
+        }
+    }
+
+    fn entries() -> Vec<DecryptedEntry> {
+        vec![
+            DecryptedEntry {
+                name: "GitHub".to_owned(),
+                description: Some("Work account".to_owned()),
+                username: Some("octocat".to_owned()),
+                url: Some("https://github.com".to_owned()),
+                password: "hunter2".to_owned(),
+                totp: Some("JBSWY3DPEHPK3PXP".to_owned()),
+                folder: Some("Work".to_owned()),
+                custom_fields: vec![
+                    CustomField::new("PIN", "1234", true),
+                    CustomField::new("Team", "Platform", false),
+                ],
+                ..Default::default()
+            },
+            DecryptedEntry {
+                name: "Bank".to_owned(),
+                password: "s3cret".to_owned(),
+                folder: Some("Work".to_owned()),
+                ..Default::default()
+            },
+        ]
+    }
+
+    #[test]
+    fn exports_map_entries_to_logins() {
+        let json: Value = serde_json::from_str(&write_export(entries(), None).unwrap()).unwrap();
+
+        assert_eq!(json["encrypted"], false);
+        let folders = json["folders"].as_array().unwrap();
+        assert_eq!(folders.len(), 1);
+        assert_eq!(folders[0]["name"], "Work");
+
+        let item = &json["items"][0];
+        assert_eq!(item["type"], LOGIN);
+        assert_eq!(item["name"], "GitHub");
+        assert_eq!(item["notes"], "Work account");
+        assert_eq!(item["folderId"], folders[0]["id"]);
+        assert_eq!(item["login"]["username"], "octocat");
+        assert_eq!(item["login"]["password"], "hunter2");
+        assert_eq!(item["login"]["totp"], "JBSWY3DPEHPK3PXP");
+        assert_eq!(item["login"]["uris"][0]["uri"], "https://github.com");
+        assert_eq!(item["fields"][0]["name"], "PIN");
+        assert_eq!(item["fields"][0]["type"], FIELD_HIDDEN);
+        assert_eq!(item["fields"][1]["type"], 0);
+        assert_eq!(json["items"][1]["folderId"], folders[0]["id"]);
+    }
+
+    #[test]
+    fn exports_round_trip() {
+        let protected = write_export(entries(), Some(PASSWORD)).unwrap();
+        let json: Value = serde_json::from_str(&protected).unwrap();
+        assert_eq!(json["encrypted"], true);
+        assert_eq!(json["kdfIterations"], PBKDF2_ITERATIONS);
+
+        assert_eq!(read(&protected, Some(PASSWORD)).unwrap(), entries());
+        let plain = write_export(entries(), None).unwrap();
+        assert_eq!(read(&plain, None).unwrap(), entries());
+    }
+}
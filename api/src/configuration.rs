@@ -1,24 +1,260 @@
-use migration::{Migrator, MigratorTrait};
 use sea_orm::{Database, DatabaseConnection};
 use serde::{Deserialize, Serialize};
 use std::{
+    collections::BTreeMap,
+    env,
     fmt::Debug,
     fs::{self, File, OpenOptions},
     io::{Read, Write},
     path::{Path, PathBuf},
+    sync::{Mutex, RwLock},
 };
 
-use crate::{error::Error, util};
+use crate::{error::Error, migrations, settings::Settings, util};
 
+/// The name of the vault created with the configuration, which is also the vault of
+/// configurations written before there were several vaults
+pub const DEFAULT_VAULT: &str = "default";
+
+/// Selects the vault when `--vault` is not given
+pub const VAULT_ENV: &str = "MYPASS_VAULT";
+
+/// The directory of the configuration file and of the data, instead of the XDG directories
+pub const HOME_ENV: &str = "MYPASS_HOME";
+
+/// The vault selected for this process with `--vault`
+static SELECTED_VAULT: RwLock<Option<String>> = RwLock::new(None);
+
+/// The configuration file selected for this process with `--config`
+static SELECTED_CONFIG: RwLock<Option<PathBuf>> = RwLock::new(None);
+
+/// The connections of this process, by data store
+static CONNECTIONS: Mutex<BTreeMap<PathBuf, DatabaseConnection>> = Mutex::new(BTreeMap::new());
+
+/// The configuration file, `config.toml`. Configuration files written before the settings
+/// were added are JSON (`config.json`), and are still read.
 #[derive(Serialize, Deserialize, Debug)]
 pub struct Configuration {
-    db: String,
+    /// The vault used when none is selected
+    #[serde(default = "default_vault")]
+    default_vault: String,
+    /// The path to the database of each vault, by name
+    #[serde(default)]
+    vaults: BTreeMap<String, String>,
+    /// The database of a configuration written before there were several vaults
+    #[serde(default, skip_serializing)]
+    db: Option<String>,
+    #[serde(flatten)]
+    settings: Settings,
 }
 
+fn default_vault() -> String {
+    DEFAULT_VAULT.to_owned()
+}
+
+/// A vault of the configuration
+#[derive(Debug, Clone)]
+pub struct VaultInfo {
+    pub name: String,
+    pub path: PathBuf,
+    pub is_default: bool,
+}
+
+/// Connects to the data store of the current vault. The connection is shared by the process,
+/// so the data store is connected to and migrated once.
 pub async fn connect() -> Result<DatabaseConnection, Error> {
     init_config()?;
     let path_to_db = get_db_path()?;
-    connect_db(path_to_db).await
+    let shared = CONNECTIONS
+        .lock()
+        .ok()
+        .and_then(|connections| connections.get(&path_to_db).cloned());
+    if let Some(conn) = shared {
+        return Ok(conn);
+    }
+    let conn = connect_db(path_to_db.to_owned()).await?;
+    if let Ok(mut connections) = CONNECTIONS.lock() {
+        connections.insert(path_to_db, conn.clone());
+    }
+    Ok(conn)
+}
+
+/// Selects the configuration file, whose directory then also holds the data
+pub fn select_config_file(path: PathBuf) {
+    if let Ok(mut selected) = SELECTED_CONFIG.write() {
+        *selected = Some(path);
+    }
+}
+
+/// The path to the configuration file: the selected file, `config.toml` in `MYPASS_HOME`,
+/// or `mypass/config.toml` in `XDG_CONFIG_HOME` (~/.config)
+pub fn get_config_file_path() -> Result<PathBuf, Error> {
+    if let Some(path) = SELECTED_CONFIG.read().ok().and_then(|s| s.to_owned()) {
+        return Ok(path);
+    }
+    if let Some(home) = env_dir(HOME_ENV) {
+        return Ok(home.join("config.toml"));
+    }
+    Ok(xdg_dir("XDG_CONFIG_HOME", ".config")?
+        .join("mypass")
+        .join("config.toml"))
+}
+
+/// The directory of the databases and exports: the directory of the selected configuration
+/// file, `MYPASS_HOME`, or `mypass` in `XDG_DATA_HOME` (~/.local/share)
+pub fn get_data_dir_path() -> Result<PathBuf, Error> {
+    if let Some(path) = SELECTED_CONFIG.read().ok().and_then(|s| s.to_owned()) {
+        return Ok(path.parent().map(|p| p.to_path_buf()).unwrap_or_default());
+    }
+    if let Some(home) = env_dir(HOME_ENV) {
+        return Ok(home);
+    }
+    Ok(xdg_dir("XDG_DATA_HOME", ".local/share")?.join("mypass"))
+}
+
+/// The value of an environment variable holding a directory, if it is set and not empty
+fn env_dir(var: &str) -> Option<PathBuf> {
+    env::var(var)
+        .ok()
+        .filter(|dir| !dir.is_empty())
+        .map(PathBuf::from)
+}
+
+/// An XDG base directory, or its default in the home directory. The XDG specification
+/// ignores relative paths.
+fn xdg_dir(var: &str, default: &str) -> Result<PathBuf, Error> {
+    match env_dir(var).filter(|dir| dir.is_absolute()) {
+        Some(dir) => Ok(dir),
+        None => util::get_home_env_var()
+            .map(|home| PathBuf::from(home).join(default))
+            .map_err(|_| {
+                format!(
+                    "HOME is not set, set {} to the directory of the configuration and data",
+                    HOME_ENV
+                )
+            }),
+    }
+}
+
+/// The settings of the configuration file, with the environment overrides
+pub fn get_settings() -> Result<Settings, Error> {
+    init_config()?;
+    let mut settings = get_config()?.settings;
+    settings.apply_env()?;
+    settings.validate()?;
+    Ok(settings)
+}
+
+/// Sets a setting in the configuration file, see `Settings::set`
+pub fn set_setting(key: &str, value: &str) -> Result<(), Error> {
+    init_config()?;
+    let mut config = get_config()?;
+    config.settings.set(key, value)?;
+    config.settings.validate()?;
+    save_config(&config)
+}
+
+/// Replaces the settings of the configuration file
+pub fn set_settings(settings: Settings) -> Result<(), Error> {
+    settings.validate()?;
+    init_config()?;
+    let mut config = get_config()?;
+    config.settings = settings;
+    save_config(&config)
+}
+
+/// Selects the vault used by the following operations, over `MYPASS_VAULT` and the default vault
+pub fn select_vault(name: String) {
+    if let Ok(mut selected) = SELECTED_VAULT.write() {
+        *selected = Some(name);
+    }
+}
+
+/// The name of the vault in use: the selected vault, `MYPASS_VAULT` or the default vault
+pub fn current_vault() -> Result<String, Error> {
+    let selected = SELECTED_VAULT.read().ok().and_then(|s| s.to_owned());
+    if let Some(name) = selected.or_else(|| env::var(VAULT_ENV).ok().filter(|v| !v.is_empty())) {
+        return Ok(name);
+    }
+    init_config()?;
+    Ok(get_config()?.default_vault)
+}
+
+pub fn list_vaults() -> Result<Vec<VaultInfo>, Error> {
+    init_config()?;
+    let config = get_config()?;
+    Ok(config
+        .vaults
+        .iter()
+        .map(|(name, path)| VaultInfo {
+            name: name.to_owned(),
+            path: PathBuf::from(path),
+            is_default: *name == config.default_vault,
+        })
+        .collect())
+}
+
+/// Adds a vault whose database is in the directory, by default a directory of its own in
+/// the data directory. An existing database in the directory is used as is.
+pub fn add_vault(name: String, dir: Option<String>) -> Result<PathBuf, Error> {
+    init_config()?;
+    let mut config = get_config()?;
+    if name.is_empty() || name.contains(|c: char| c == '/' || c.is_whitespace()) {
+        return Err(format!(
+            "Invalid vault name {}, it cannot be empty or contain `/` or spaces",
+            name
+        ));
+    }
+    if config.vaults.contains_key(&name) {
+        return Err(format!("Vault {} already exists", name));
+    }
+    let dir = match dir {
+        Some(dir) => Path::new(&dir)
+            .canonicalize()
+            .map_err(|_| format!("Directory {dir} is invalid"))?,
+        None => {
+            let mut dir = get_data_dir_path()?;
+            dir.push("vaults");
+            dir.push(&name);
+            fs::create_dir_all(&dir).map_err(|_| "Failed to create the vault directory")?;
+            dir
+        }
+    };
+    let db_path = dir.join("db.sqlite");
+    config
+        .vaults
+        .insert(name, db_path.to_string_lossy().into_owned());
+    save_config(&config)?;
+    Ok(db_path)
+}
+
+/// Removes a vault from the configuration, returning the path of its database, which is kept
+pub fn remove_vault(name: String) -> Result<PathBuf, Error> {
+    init_config()?;
+    let mut config = get_config()?;
+    if name == config.default_vault {
+        return Err(format!(
+            "Vault {} is the default vault, use another vault first",
+            name
+        ));
+    }
+    let path = config
+        .vaults
+        .remove(&name)
+        .ok_or(format!("Unknown vault {}", name))?;
+    save_config(&config)?;
+    Ok(PathBuf::from(path))
+}
+
+/// Makes a vault the default vault
+pub fn set_default_vault(name: String) -> Result<(), Error> {
+    init_config()?;
+    let mut config = get_config()?;
+    if !config.vaults.contains_key(&name) {
+        return Err(format!("Unknown vault {}", name));
+    }
+    config.default_vault = name;
+    save_config(&config)
 }
 
 pub fn move_db(new_path: String) -> Result<(), Error> {
@@ -37,26 +273,38 @@ pub fn move_db(new_path: String) -> Result<(), Error> {
 
     let db_path = full_new_path.join("db.sqlite");
     fs::rename(cur_path, db_path.as_path()).map_err(|_| "Failed to to move db file".to_owned())?;
+    if let Ok(mut connections) = CONNECTIONS.lock() {
+        connections.remove(cur_path);
+    }
     set_config(full_new_path.to_path_buf())
 }
 
+/// Connects to a data store, applying its pending migrations (see `migrations::migrate`)
 async fn connect_db(path: PathBuf) -> Result<DatabaseConnection, Error> {
+    let conn = connect_without_migrating(&path).await?;
+    migrations::migrate(&conn, &path).await?;
+    Ok(conn)
+}
+
+/// Connects to a data store, which is created when missing
+pub(crate) async fn connect_without_migrating(path: &Path) -> Result<DatabaseConnection, Error> {
     let path_to_db = path.to_string_lossy().into_owned();
     let db_url = format!("sqlite://{}", path_to_db);
-    let is_new = util::create_file(path_to_db.to_owned())?;
-    let conn = Database::connect(db_url)
+    util::create_file(path_to_db.to_owned())?;
+    Database::connect(db_url)
         .await
-        .map_err(|_| "Failed to connect to data store")?;
-    if is_new {
-        Migrator::up(&conn, None)
-            .await
-            .map_err(|_| "Failed to push migration to data store")?;
-    }
-    Ok(conn)
+        .map_err(|_| "Failed to connect to data store".to_owned())
 }
 
+/// The path to the database of the current vault
 pub fn get_db_path() -> Result<PathBuf, Error> {
-    get_config().map(|c| PathBuf::from(c.db))
+    let name = current_vault()?;
+    let config = get_config()?;
+    config
+        .vaults
+        .get(&name)
+        .map(PathBuf::from)
+        .ok_or(format!("Unknown vault {}", name))
 }
 
 pub fn set_db_path(path: String) -> Result<(), Error> {
@@ -68,34 +316,97 @@ pub fn set_db_path(path: String) -> Result<(), Error> {
 }
 
 fn init_config() -> Result<(), Error> {
-    let config_path = get_config_dir_path().map(|mut path| {
-        path.push("config.json");
-        path
-    })?;
+    let config_path = get_config_file_path()?;
+    if !config_path.exists() && (migrate_json_config(&config_path)? || migrate_legacy_config()?) {
+        return Ok(());
+    }
     let config_path = config_path.to_string_lossy().into_owned();
     let is_new = util::create_file(config_path.clone())?;
     if is_new {
-        let config_dir = get_config_dir_path()?;
-        set_config(config_dir)?;
+        let mut config = Configuration {
+            default_vault: default_vault(),
+            vaults: BTreeMap::new(),
+            db: None,
+            settings: Settings::default(),
+        };
+        let db_path = get_data_dir_path()?.join("db.sqlite");
+        config.vaults.insert(
+            DEFAULT_VAULT.to_owned(),
+            db_path.to_string_lossy().into_owned(),
+        );
+        save_config(&config)?;
     }
     Ok(())
 }
 
-fn get_config_dir_path() -> Result<PathBuf, Error> {
-    util::get_home_env_var().map(|dir| {
-        let mut path = PathBuf::new();
-        path.push(dir);
-        path.push(".mypass");
-        path
-    })
+/// Rewrites the JSON configuration next to the configuration file as TOML.
+/// Returns whether there was a configuration to migrate.
+fn migrate_json_config(config_path: &Path) -> Result<bool, Error> {
+    let json_path = config_path.with_extension("json");
+    if json_path == config_path || !json_path.is_file() {
+        return Ok(false);
+    }
+    let config = read_config(&json_path)?;
+    save_config(&config)?;
+    fs::remove_file(json_path).map_err(|_| "Failed to remove config.json")?;
+    Ok(true)
+}
+
+/// Moves the configuration of ~/.mypass, where it was before the XDG directories were used,
+/// to the configuration file, and the databases in ~/.mypass to the data directory.
+/// Returns whether there was a configuration to migrate.
+fn migrate_legacy_config() -> Result<bool, Error> {
+    let selected = SELECTED_CONFIG.read().ok().is_some_and(|s| s.is_some());
+    if selected || env_dir(HOME_ENV).is_some() {
+        return Ok(false);
+    }
+    let Ok(home) = util::get_home_env_var() else {
+        return Ok(false);
+    };
+    let legacy_dir = PathBuf::from(home).join(".mypass");
+    let legacy_config = legacy_dir.join("config.json");
+    if !legacy_config.is_file() {
+        return Ok(false);
+    }
+
+    let err = "Failed to move the configuration from ~/.mypass";
+    let data_dir = get_data_dir_path()?;
+    let mut config = read_config(&legacy_config)?;
+    for path in config.vaults.values_mut() {
+        let Ok(relative) = Path::new(path).strip_prefix(&legacy_dir) else {
+            continue;
+        };
+        let new_path = data_dir.join(relative);
+        if Path::new(path).exists() {
+            if let Some(parent) = new_path.parent() {
+                fs::create_dir_all(parent).map_err(|_| err)?;
+            }
+            move_file(Path::new(path), &new_path).map_err(|_| err)?;
+        }
+        *path = new_path.to_string_lossy().into_owned();
+    }
+
+    let config_path = get_config_file_path()?;
+    util::create_file(config_path.to_string_lossy().into_owned())?;
+    save_config(&config)?;
+    fs::remove_file(legacy_config).map_err(|_| err)?;
+    Ok(true)
+}
+
+/// Renames a file, or copies it when it is moved to another file system
+fn move_file(from: &Path, to: &Path) -> std::io::Result<()> {
+    if fs::rename(from, to).is_ok() {
+        return Ok(());
+    }
+    fs::copy(from, to)?;
+    fs::remove_file(from)
 }
 
 fn get_config() -> Result<Configuration, Error> {
-    let config_path = get_config_dir_path().map(|mut path| {
-        path.push("config.json");
-        path
-    })?;
+    read_config(&get_config_file_path()?)
+}
 
+fn read_config(config_path: &Path) -> Result<Configuration, Error> {
     let mut file: File = OpenOptions::new()
         .read(true)
         .open(config_path)
@@ -104,31 +415,51 @@ fn get_config() -> Result<Configuration, Error> {
     let mut config_str = String::new();
     file.read_to_string(&mut config_str)
         .map_err(|_| "Failed to read from configuration file")?;
-    let config: Configuration = serde_json::from_str(&config_str)
-            .map_err(|_| "Failed to read from configuration file (invalid configuration). The file has likely been tampered with. Fix the format or delete it to solve the issue")?;
+    let invalid = |e: String| {
+        format!("Failed to read from configuration file (invalid configuration): {}. Fix the format or delete it to solve the issue", e)
+    };
+    let mut config: Configuration = if is_json(config_path) {
+        serde_json::from_str(&config_str).map_err(|e| invalid(e.to_string()))?
+    } else {
+        toml::from_str(&config_str).map_err(|e| invalid(e.message().to_owned()))?
+    };
+    if let Some(db) = config.db.take() {
+        config.vaults.entry(DEFAULT_VAULT.to_owned()).or_insert(db);
+    }
     Ok(config)
 }
 
+/// Sets the directory of the database of the current vault
 fn set_config(mut db_path: PathBuf) -> Result<(), Error> {
-    let config_path = get_config_dir_path().map(|mut path| {
-        path.push("config.json");
-        path
-    })?;
+    let name = current_vault()?;
+    let mut config = get_config()?;
+    if !config.vaults.contains_key(&name) {
+        return Err(format!("Unknown vault {}", name));
+    }
+    db_path.push("db.sqlite");
+    config
+        .vaults
+        .insert(name, db_path.to_string_lossy().into_owned());
+    save_config(&config)
+}
 
-    let mut file = File::create(config_path).map_err(|_| "Failed to create file")?;
+fn save_config(config: &Configuration) -> Result<(), Error> {
+    let config_path = get_config_file_path()?;
 
-    let default_db_path = {
-        db_path.push("db.sqlite");
-        db_path
-    };
+    let mut file = File::create(&config_path).map_err(|_| "Failed to create file")?;
 
-    let config = Configuration {
-        db: default_db_path.to_string_lossy().into_owned(),
+    let config_str = if is_json(&config_path) {
+        serde_json::to_string_pretty(&config).map_err(|_| "Failed to serialize")?
+    } else {
+        toml::to_string_pretty(&config).map_err(|_| "Failed to serialize")?
     };
-
-    let config_str = serde_json::to_string_pretty(&config).map_err(|_| "Failed to serialize")?;
     file.write_all(config_str.as_bytes())
         .map_err(|_| "Failed to write to configuration file")?;
 
     Ok(())
 }
+
+/// Whether a configuration file is JSON, as written before the settings were added
+fn is_json(path: &Path) -> bool {
+    path.extension().is_some_and(|ext| ext == "json")
+}
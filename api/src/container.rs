@@ -0,0 +1,156 @@
+use aes_gcm::{
+    aead::{Aead, KeyInit, Payload},
+    Aes256Gcm, Key as AesKey, Nonce as AesNonce,
+};
+use argon2::{Algorithm, Argon2, Params, Version};
+use base64::{engine::general_purpose::STANDARD as BASE64, Engine};
+
+use crate::{error::Error, settings::KdfSettings};
+
+// An encrypted export is an ASCII armored container:
+//
+//   magic "MYPASS\x00\x01" | argon2id memory (KiB), iterations, parallelism (u32 BE)
+//   | salt (16 bytes) | nonce (12 bytes) | content type length (u8) | content type
+//   | AES-256-GCM ciphertext of the content, with everything before it as associated data
+//
+// The content type names the format of the decrypted content (e.g. "csv").
+
+const BEGIN: &str = "-----BEGIN MYPASS EXPORT-----";
+const END: &str = "-----END MYPASS EXPORT-----";
+const MAGIC: &[u8; 8] = b"MYPASS\x00\x01";
+
+/// The upper bounds accepted when opening a container, so that a crafted file cannot
+/// make the key derivation use all the memory of the machine
+pub const MAX_MEMORY_KIB: u32 = 4 * 1024 * 1024;
+pub const MAX_ITERATIONS: u32 = 100;
+
+/// Whether the data is an encrypted export, rather than a plaintext file
+pub fn is_container(data: &[u8]) -> bool {
+    data.trim_ascii_start().starts_with(BEGIN.as_bytes())
+}
+
+/// Encrypts content with a passphrase, returning the armored container
+pub fn seal(
+    content_type: &str,
+    content: &[u8],
+    passphrase: &str,
+    kdf: &KdfSettings,
+) -> Result<String, Error> {
+    let err = "Failed to encrypt the export";
+    if passphrase.is_empty() {
+        return Err("A passphrase is required".to_owned());
+    }
+    let salt: [u8; 16] = rand::random();
+    let nonce: [u8; 12] = rand::random();
+
+    let mut header = MAGIC.to_vec();
+    for value in [kdf.memory_kib, kdf.iterations, kdf.parallelism] {
+        header.extend(value.to_be_bytes());
+    }
+    header.extend(salt);
+    header.extend(nonce);
+    header.push(content_type.len() as u8);
+    header.extend(content_type.as_bytes());
+
+    let key = derive_key(
+        passphrase,
+        &salt,
+        kdf.memory_kib,
+        kdf.iterations,
+        kdf.parallelism,
+    )?;
+    let ciphertext = Aes256Gcm::new(AesKey::<Aes256Gcm>::from_slice(&key))
+        .encrypt(
+            AesNonce::from_slice(&nonce),
+            Payload {
+                msg: content,
+                aad: &header,
+            },
+        )
+        .map_err(|_| err)?;
+
+    let encoded = BASE64.encode([header, ciphertext].concat());
+    let mut armored = format!("{}\n", BEGIN);
+    for line in encoded.as_bytes().chunks(64) {
+        armored.push_str(&String::from_utf8_lossy(line));
+        armored.push('\n');
+    }
+    armored.push_str(END);
+    armored.push('\n');
+    Ok(armored)
+}
+
+/// Decrypts a container, returning its content type and content
+pub fn open(data: &[u8], passphrase: &str) -> Result<(String, Vec<u8>), Error> {
+    let invalid = "Invalid encrypted export";
+    let text = std::str::from_utf8(data).map_err(|_| invalid)?;
+    let body = text
+        .trim()
+        .strip_prefix(BEGIN)
+        .and_then(|t| t.strip_suffix(END))
+        .ok_or(invalid)?;
+    let encoded: String = body.chars().filter(|c| !c.is_whitespace()).collect();
+    let data = BASE64.decode(encoded).map_err(|_| invalid)?;
+
+    let mut reader = Reader(&data);
+    if reader.take(MAGIC.len()).ok_or(invalid)? != MAGIC {
+        return Err("Unsupported encrypted export version".to_owned());
+    }
+    let memory = reader.u32().ok_or(invalid)?;
+    let iterations = reader.u32().ok_or(invalid)?;
+    let parallelism = reader.u32().ok_or(invalid)?;
+    if memory > MAX_MEMORY_KIB || iterations > MAX_ITERATIONS {
+        return Err(invalid.to_owned());
+    }
+    let salt = reader.take(16).ok_or(invalid)?;
+    let nonce = reader.take(12).ok_or(invalid)?;
+    let content_type_len = reader.take(1).ok_or(invalid)?[0] as usize;
+    let content_type = reader.take(content_type_len).ok_or(invalid)?;
+    let content_type = String::from_utf8(content_type.to_vec()).map_err(|_| invalid)?;
+    let (header, ciphertext) = data.split_at(data.len() - reader.0.len());
+
+    let key = derive_key(passphrase, salt, memory, iterations, parallelism)?;
+    let content = Aes256Gcm::new(AesKey::<Aes256Gcm>::from_slice(&key))
+        .decrypt(
+            AesNonce::from_slice(nonce),
+            Payload {
+                msg: ciphertext,
+                aad: header,
+            },
+        )
+        .map_err(|_| "Invalid passphrase, or the export was modified")?;
+    Ok((content_type, content))
+}
+
+fn derive_key(
+    passphrase: &str,
+    salt: &[u8],
+    memory: u32,
+    iterations: u32,
+    parallelism: u32,
+) -> Result<[u8; 32], Error> {
+    let err = "Failed to derive the export key";
+    let params = Params::new(memory, iterations, parallelism, Some(32)).map_err(|_| err)?;
+    let mut key = [0u8; 32];
+    Argon2::new(Algorithm::Argon2id, Version::V0x13, params)
+        .hash_password_into(passphrase.as_bytes(), salt, &mut key)
+        .map_err(|_| err)?;
+    Ok(key)
+}
+
+struct Reader<'a>(&'a [u8]);
+
+impl<'a> Reader<'a> {
+    fn take(&mut self, len: usize) -> Option<&'a [u8]> {
+        if len > self.0.len() {
+            return None;
+        }
+        let (value, rest) = self.0.split_at(len);
+        self.0 = rest;
+        Some(value)
+    }
+
+    fn u32(&mut self) -> Option<u32> {
+        Some(u32::from_be_bytes(self.take(4)?.try_into().ok()?))
+    }
+}
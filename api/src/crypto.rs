@@ -5,11 +5,11 @@ use aes_gcm::{
 
 use argon2::{
     password_hash::{rand_core::OsRng, PasswordHash, PasswordHasher, PasswordVerifier, SaltString},
-    Argon2,
+    Algorithm, Argon2, Params, Version,
 };
 use rand::{distributions::Alphanumeric, Rng};
 
-use crate::error::Error;
+use crate::{error::Error, settings::KdfSettings};
 
 pub struct FixedLengthKey {
     value: Vec<u8>,
@@ -75,6 +75,43 @@ pub fn encrypt_password(
         .map(|ciphertext| ciphertext.to_vec())
 }
 
+/// Encrypts a secret with a random nonce, which is stored as the first 12 bytes of the output.
+/// Use this (rather than `encrypt_password`) for any value other than the entry password,
+/// since the entry ID is already used as the nonce of the password.
+pub fn encrypt_secret(
+    master_password: String,
+    secret: String,
+    salt: String,
+) -> Result<Vec<u8>, Error> {
+    let master_key = derive_master_key(master_password, FixedLengthKey::shrink_from(salt, 12))?;
+    let key = AesKey::<Aes256Gcm>::from_slice(&master_key.value);
+    let cipher = Aes256Gcm::new(key);
+    let nonce: [u8; 12] = rand::random();
+    let ciphertext = cipher
+        .encrypt(AesNonce::from_slice(&nonce), secret.as_bytes().as_ref())
+        .map_err(|_| "Failed to encrypt secret".to_owned())?;
+    Ok([nonce.to_vec(), ciphertext].concat())
+}
+
+pub fn decrypt_secret(
+    master_password: String,
+    secret: Vec<u8>,
+    salt: String,
+) -> Result<String, Error> {
+    let err = "Failed to decrypt secret".to_owned();
+    if secret.len() < 12 {
+        return Err(err);
+    }
+    let master_key = derive_master_key(master_password, FixedLengthKey::shrink_from(salt, 12))?;
+    let key = AesKey::<Aes256Gcm>::from_slice(&master_key.value);
+    let cipher = Aes256Gcm::new(key);
+    let (nonce, ciphertext) = secret.split_at(12);
+    cipher
+        .decrypt(AesNonce::from_slice(nonce), ciphertext)
+        .map_err(|_| err.to_owned())
+        .and_then(|plaintext| String::from_utf8(plaintext).map_err(|_| err))
+}
+
 pub fn derive_master_key(
     master_password: String,
     salt: FixedLengthKey,
@@ -87,9 +124,12 @@ pub fn derive_master_key(
     Ok(FixedLengthKey::new(output_key_material.to_vec(), 32))
 }
 
-pub fn hash_password(password: String) -> Result<String, Error> {
+/// Hashes the master password with Argon2id, the parameters are stored in the hash
+pub fn hash_password(password: String, kdf: &KdfSettings) -> Result<String, Error> {
     let salt = SaltString::generate(&mut OsRng);
-    let argon2 = Argon2::default();
+    let params = Params::new(kdf.memory_kib, kdf.iterations, kdf.parallelism, None)
+        .map_err(|_| "Invalid KDF settings")?;
+    let argon2 = Argon2::new(Algorithm::Argon2id, Version::V0x13, params);
     let password_hash = argon2
         .hash_password(password.as_bytes(), &salt)
         .map_err(|_| "Faild to hash password")?
@@ -0,0 +1,376 @@
+use url::Url;
+
+use crate::{entries, entry_transfer::DecryptedEntry, error::Error};
+
+/// A known CSV layout, as exported by MyPass or another password manager
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum CsvProfile {
+    Mypass,
+    Chrome,
+    Firefox,
+    Lastpass,
+    Onepassword,
+    Dashlane,
+}
+
+/// The index of the column holding each field, `None` when the file has no such column
+#[derive(Debug, Clone, Default)]
+pub struct ColumnMapping {
+    pub name: Option<usize>,
+    pub description: Option<usize>,
+    pub username: Option<usize>,
+    pub url: Option<usize>,
+    pub password: Option<usize>,
+    pub totp: Option<usize>,
+    pub folder: Option<usize>,
+    /// Comma separated tags
+    pub tags: Option<usize>,
+}
+
+/// The header of each field for a profile, matched case-insensitively
+struct Columns {
+    /// Headers that must all be present for the profile to be detected
+    signature: &'static [&'static str],
+    name: &'static str,
+    description: &'static str,
+    username: &'static str,
+    url: &'static str,
+    password: &'static str,
+    totp: &'static str,
+    folder: &'static str,
+    tags: &'static str,
+}
+
+impl CsvProfile {
+    /// Profiles in detection order, the most specific signatures first
+    pub const ALL: [CsvProfile; 6] = [
+        CsvProfile::Firefox,
+        CsvProfile::Lastpass,
+        CsvProfile::Dashlane,
+        CsvProfile::Onepassword,
+        CsvProfile::Mypass,
+        CsvProfile::Chrome,
+    ];
+
+    pub fn name(&self) -> &'static str {
+        match self {
+            CsvProfile::Mypass => "MyPass",
+            CsvProfile::Chrome => "Chrome",
+            CsvProfile::Firefox => "Firefox",
+            CsvProfile::Lastpass => "LastPass",
+            CsvProfile::Onepassword => "1Password",
+            CsvProfile::Dashlane => "Dashlane",
+        }
+    }
+
+    fn columns(&self) -> Columns {
+        match self {
+            CsvProfile::Mypass => Columns {
+                signature: &["name", "description", "username", "url", "password"],
+                name: "name",
+                description: "description",
+                username: "username",
+                url: "url",
+                password: "password",
+                totp: "totp",
+                folder: "folder",
+                tags: "tags",
+            },
+            CsvProfile::Chrome => Columns {
+                signature: &["name", "url", "username", "password"],
+                name: "name",
+                description: "note",
+                username: "username",
+                url: "url",
+                password: "password",
+                totp: "",
+                folder: "",
+                tags: "",
+            },
+            CsvProfile::Firefox => Columns {
+                signature: &["url", "username", "password", "httprealm", "guid"],
+                name: "",
+                description: "",
+                username: "username",
+                url: "url",
+                password: "password",
+                totp: "",
+                folder: "",
+                tags: "",
+            },
+            CsvProfile::Lastpass => Columns {
+                signature: &["url", "username", "password", "extra", "name", "grouping"],
+                name: "name",
+                description: "extra",
+                username: "username",
+                url: "url",
+                password: "password",
+                totp: "totp",
+                folder: "grouping",
+                tags: "",
+            },
+            CsvProfile::Onepassword => Columns {
+                signature: &["title", "url", "username", "password", "notes"],
+                name: "title",
+                description: "notes",
+                username: "username",
+                url: "url",
+                password: "password",
+                totp: "otpauth",
+                folder: "",
+                tags: "tags",
+            },
+            CsvProfile::Dashlane => Columns {
+                signature: &["username", "username2", "title", "password", "note", "url"],
+                name: "title",
+                description: "note",
+                username: "username",
+                url: "url",
+                password: "password",
+                totp: "otpsecret",
+                folder: "category",
+                tags: "",
+            },
+        }
+    }
+
+    /// The columns of the profile within the headers of a file
+    pub fn mapping(&self, headers: &[String]) -> ColumnMapping {
+        let columns = self.columns();
+        let find = |header: &str| find_column(headers, header);
+        ColumnMapping {
+            name: find(columns.name),
+            description: find(columns.description),
+            username: find(columns.username),
+            url: find(columns.url),
+            password: find(columns.password),
+            // Newer Dashlane exports name it otpUrl
+            totp: find(columns.totp).or_else(|| match self {
+                CsvProfile::Dashlane => find("otpurl"),
+                _ => None,
+            }),
+            folder: find(columns.folder),
+            tags: find(columns.tags),
+        }
+    }
+}
+
+fn find_column(headers: &[String], header: &str) -> Option<usize> {
+    if header.is_empty() {
+        return None;
+    }
+    headers
+        .iter()
+        .position(|h| h.trim().eq_ignore_ascii_case(header))
+}
+
+/// The profile matching the headers of a file, if any
+pub fn detect_profile(headers: &[String]) -> Option<CsvProfile> {
+    CsvProfile::ALL.into_iter().find(|profile| {
+        profile
+            .columns()
+            .signature
+            .iter()
+            .all(|header| find_column(headers, header).is_some())
+    })
+}
+
+pub fn read_headers(data: &[u8]) -> Result<Vec<String>, Error> {
+    let mut rdr = csv::Reader::from_reader(data);
+    let headers = rdr
+        .headers()
+        .map_err(|e| format!("Failed to read csv headers {}", e))?;
+    Ok(headers.iter().map(|h| h.to_owned()).collect())
+}
+
+/// Reads the entries of CSV data with the given columns
+pub fn read_entries(
+    data: &[u8],
+    mapping: &ColumnMapping,
+    profile: Option<CsvProfile>,
+) -> Result<Vec<DecryptedEntry>, Error> {
+    let mut rdr = csv::ReaderBuilder::new().flexible(true).from_reader(data);
+    let mut entries = Vec::new();
+    for result in rdr.records() {
+        let record = result.map_err(|e| format!("Failed to read entry {}", e))?;
+        // Values are kept as they are, spaces in a password are part of it
+        let get = |column: Option<usize>| {
+            column
+                .and_then(|c| record.get(c))
+                .filter(|v| !v.is_empty())
+                .map(|v| v.to_owned())
+        };
+
+        let mut entry = DecryptedEntry {
+            name: get(mapping.name).unwrap_or_default(),
+            description: get(mapping.description),
+            username: get(mapping.username),
+            url: get(mapping.url),
+            password: get(mapping.password).unwrap_or_default(),
+            totp: get(mapping.totp),
+            folder: get(mapping.folder),
+            tags: get(mapping.tags)
+                .map(|tags| entries::split_tags(&tags))
+                .unwrap_or_default(),
+            ..Default::default()
+        };
+        if profile == Some(CsvProfile::Lastpass) {
+            // Secure notes have this placeholder URL, and nested folders are separated by backslashes
+            if entry.url.as_deref() == Some("http://sn") {
+                entry.url = None;
+            }
+            entry.folder = entry.folder.map(|f| f.replace('\\', "/"));
+        }
+        // Firefox has no name column, use the host of the site instead
+        if entry.name.is_empty() {
+            entry.name = entry
+                .url
+                .as_deref()
+                .and_then(|url| Url::parse(url).ok())
+                .and_then(|url| url.host_str().map(|h| h.to_owned()))
+                .unwrap_or_default();
+        }
+        entries.push(entry);
+    }
+    Ok(entries)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// The header rows of the exports of each password manager
+    const MYPASS: &str = "name,description,username,url,password,totp,folder,tags";
+    const CHROME: &str = "name,url,username,password,note";
+    const CHROME_OLD: &str = "name,url,username,password";
+    const FIREFOX: &str = "\"url\",\"username\",\"password\",\"httpRealm\",\"formActionOrigin\",\"guid\",\"timeCreated\",\"timeLastUsed\",\"timePasswordChanged\"";
+    const ONEPASSWORD: &str = "Title,Url,Username,Password,OTPAuth,Favorite,Archived,Tags,Notes";
+    const DASHLANE: &str =
+        "username,username2,username3,title,password,note,url,category,otpSecret";
+    const DASHLANE_NEW: &str =
+        "username,username2,username3,title,password,note,url,category,otpUrl";
+    const LASTPASS: &str = "url,username,password,totp,extra,name,grouping,fav";
+
+    fn read(header: &str, row: &str) -> (Option<CsvProfile>, Vec<DecryptedEntry>) {
+        let data = format!("{}\n{}\n", header, row);
+        let headers = read_headers(data.as_bytes()).unwrap();
+        let profile = detect_profile(&headers);
+        let mapping = profile.map(|p| p.mapping(&headers)).unwrap_or_default();
+        (
+            profile,
+            read_entries(data.as_bytes(), &mapping, profile).unwrap(),
+        )
+    }
+
+    #[test]
+    fn detect_profile_recognizes_the_exports() {
+        for (header, profile) in [
+            (MYPASS, CsvProfile::Mypass),
+            (CHROME, CsvProfile::Chrome),
+            (CHROME_OLD, CsvProfile::Chrome),
+            (FIREFOX, CsvProfile::Firefox),
+            (ONEPASSWORD, CsvProfile::Onepassword),
+            (DASHLANE, CsvProfile::Dashlane),
+            (DASHLANE_NEW, CsvProfile::Dashlane),
+            (LASTPASS, CsvProfile::Lastpass),
+        ] {
+            let headers = read_headers(header.as_bytes()).unwrap();
+            assert_eq!(detect_profile(&headers), Some(profile), "{}", header);
+        }
+        let headers = read_headers(b"site,login,secret").unwrap();
+        assert_eq!(detect_profile(&headers), None);
+    }
+
+    #[test]
+    fn detect_profile_prefers_mypass_over_its_chrome_subset() {
+        // The columns of Chrome are all in a MyPass export, which must not be read as Chrome
+        let (profile, entries) = read(
+            MYPASS,
+            "db,Production,admin,https://db.io,s3cret,JBSWY3DPEHPK3PXP,work/db,\"prod,sql\"",
+        );
+        assert_eq!(profile, Some(CsvProfile::Mypass));
+        assert_eq!(
+            entries[0],
+            DecryptedEntry {
+                name: "db".to_owned(),
+                description: Some("Production".to_owned()),
+                username: Some("admin".to_owned()),
+                url: Some("https://db.io".to_owned()),
+                password: "s3cret".to_owned(),
+                totp: Some("JBSWY3DPEHPK3PXP".to_owned()),
+                folder: Some("work/db".to_owned()),
+                tags: vec!["prod".to_owned(), "sql".to_owned()],
+                ..Default::default()
+            }
+        );
+
+        let (profile, entries) = read(CHROME, "github.com,https://github.com/,alice,s3cret,Work");
+        assert_eq!(profile, Some(CsvProfile::Chrome));
+        assert_eq!(entries[0].name, "github.com");
+        assert_eq!(entries[0].description.as_deref(), Some("Work"));
+        assert_eq!(entries[0].username.as_deref(), Some("alice"));
+    }
+
+    #[test]
+    fn read_entries_maps_the_columns_of_each_export() {
+        let (_, entries) = read(
+            FIREFOX,
+            "\"https://github.com\",\"alice\",\"s3cret\",,\"https://github.com\",\"{0b5c}\",\"1700000000000\",\"1700000000000\",\"1700000000000\"",
+        );
+        assert_eq!(entries[0].name, "github.com");
+        assert_eq!(entries[0].username.as_deref(), Some("alice"));
+        assert_eq!(entries[0].password, "s3cret");
+
+        let (_, entries) = read(
+            ONEPASSWORD,
+            "GitHub,https://github.com,alice,s3cret,otpauth://totp/GitHub?secret=JBSWY3DPEHPK3PXP,false,false,\"work,code\",Recovery codes in the safe",
+        );
+        let entry = &entries[0];
+        assert_eq!(entry.name, "GitHub");
+        assert_eq!(entry.url.as_deref(), Some("https://github.com"));
+        assert!(entry.totp.as_deref().unwrap().starts_with("otpauth://"));
+        assert_eq!(entry.tags, vec!["work".to_owned(), "code".to_owned()]);
+        assert_eq!(
+            entry.description.as_deref(),
+            Some("Recovery codes in the safe")
+        );
+
+        for header in [DASHLANE, DASHLANE_NEW] {
+            let (_, entries) = read(
+                header,
+                "alice,,,GitHub,s3cret,Work account,https://github.com,Work,JBSWY3DPEHPK3PXP",
+            );
+            let entry = &entries[0];
+            assert_eq!(entry.name, "GitHub");
+            assert_eq!(entry.username.as_deref(), Some("alice"));
+            assert_eq!(entry.description.as_deref(), Some("Work account"));
+            assert_eq!(entry.folder.as_deref(), Some("Work"));
+            assert_eq!(entry.totp.as_deref(), Some("JBSWY3DPEHPK3PXP"));
+        }
+
+        let (_, entries) = read(
+            LASTPASS,
+            "http://sn,,,,Wifi password is on the router,Wifi,Home\\Network,0",
+        );
+        let entry = &entries[0];
+        assert_eq!(entry.name, "Wifi");
+        assert_eq!(entry.url, None);
+        assert_eq!(entry.folder.as_deref(), Some("Home/Network"));
+        assert_eq!(
+            entry.description.as_deref(),
+            Some("Wifi password is on the router")
+        );
+    }
+
+    #[test]
+    fn read_entries_keeps_spaces_in_values() {
+        let data = b"name,url,username,password\n site , https://a.io,me, pass word \n";
+        let headers = read_headers(data).unwrap();
+        let profile = detect_profile(&headers);
+        assert_eq!(profile, Some(CsvProfile::Chrome));
+        let mapping = profile.unwrap().mapping(&headers);
+        let entries = read_entries(data, &mapping, profile).unwrap();
+        assert_eq!(entries[0].password, " pass word ");
+        assert_eq!(entries[0].name, " site ");
+    }
+}
@@ -0,0 +1,88 @@
+use model::entities::{custom_field, master};
+use sea_orm::prelude::Uuid;
+use serde::{Deserialize, Serialize};
+
+use crate::{crypto, error::Error, vault::Vault};
+
+/// A custom field of an entry, with its value decrypted
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct CustomField {
+    pub name: String,
+    pub value: String,
+    /// Whether the value should be hidden when displayed (e.g. a PIN rather than a note)
+    #[serde(default)]
+    pub protected: bool,
+}
+
+impl CustomField {
+    pub fn new(name: impl Into<String>, value: impl Into<String>, protected: bool) -> Self {
+        CustomField {
+            name: name.into(),
+            value: value.into(),
+            protected,
+        }
+    }
+}
+
+pub async fn get_custom_fields(entry_id: String) -> Result<Vec<custom_field::Model>, Error> {
+    Vault::open_current()
+        .await?
+        .get_custom_fields(entry_id)
+        .await
+}
+
+pub fn decrypt_custom_fields(
+    fields: Vec<custom_field::Model>,
+    master: &master::Model,
+    master_password: String,
+) -> Result<Vec<CustomField>, Error> {
+    fields
+        .into_iter()
+        .map(|field| {
+            let value = crypto::decrypt_secret(
+                master_password.to_owned(),
+                field.value,
+                master.id.to_owned(),
+            )?;
+            Ok(CustomField::new(field.name, value, field.protected))
+        })
+        .collect()
+}
+
+/// Replaces the custom fields of an entry
+pub async fn set_custom_fields(
+    master_password: String,
+    entry_id: String,
+    fields: Vec<CustomField>,
+) -> Result<(), Error> {
+    Vault::open_current_unlocked(master_password)
+        .await?
+        .set_custom_fields(entry_id, fields)
+        .await
+}
+
+/// Encrypts the custom fields of an entry, to be stored with `Change::SetCustomFields`
+pub(crate) fn encrypt_custom_fields(
+    master_password: String,
+    master: &master::Model,
+    entry_id: String,
+    fields: Vec<CustomField>,
+) -> Result<Vec<custom_field::Model>, Error> {
+    fields
+        .into_iter()
+        .map(|field| {
+            let value = crypto::encrypt_secret(
+                master_password.to_owned(),
+                field.value,
+                master.id.to_owned(),
+            )?;
+            Ok(custom_field::Model {
+                id: Uuid::new_v4().to_string(),
+                entry_id: entry_id.to_owned(),
+                name: field.name,
+                value,
+                protected: field.protected,
+            })
+        })
+        .collect()
+}
@@ -1,14 +1,13 @@
-use model::entities::prelude::Entry;
+use chrono::{DateTime, Utc};
 use model::entities::*;
-use sea_orm::IntoActiveModel;
-use sea_orm::{self, prelude::Uuid, ActiveModelTrait, ActiveValue::Set, EntityTrait};
+use sea_orm::{self, prelude::Uuid};
 
 use crate::error::Error;
+use crate::vault::Vault;
 use crate::{
-    configuration,
     crypto::{self},
+    util,
 };
-use crate::{master, util};
 
 pub async fn create_entry(
     master_password: String,
@@ -18,8 +17,22 @@ pub async fn create_entry(
     password: String,
     url: Option<String>,
 ) -> Result<entry::Model, Error> {
-    let con = configuration::connect().await?;
-    let master = master::require_master().await?;
+    Vault::open_current_unlocked(master_password)
+        .await?
+        .create_entry(name, description, username, password, url)
+        .await
+}
+
+/// The model of a new entry, with its password encrypted
+pub(crate) fn new_entry(
+    master_password: String,
+    master: &model::entities::master::Model,
+    name: String,
+    description: Option<String>,
+    username: Option<String>,
+    password: String,
+    url: Option<String>,
+) -> Result<entry::Model, Error> {
     let id = Uuid::new_v4().to_string();
 
     if let Some(u) = url.to_owned() {
@@ -36,19 +49,20 @@ pub async fn create_entry(
     let created_date = util::now();
     let modified_date = created_date.to_owned();
 
-    let ent = entry::ActiveModel {
-        id: Set(id),
-        name: Set(name),
-        description: Set(description),
-        url: Set(url),
-        username: Set(username),
-        password: Set(encrypted_password),
-        created_date: Set(created_date),
-        modified_date: Set(modified_date),
-    };
-    ent.insert(&con)
-        .await
-        .map_err(|_| "Failed to create a new entry".to_owned())
+    Ok(entry::Model {
+        id,
+        name,
+        description,
+        url,
+        username,
+        password: encrypted_password,
+        created_date,
+        modified_date,
+        totp: None,
+        ssh_public_key: None,
+        folder: None,
+        tags: None,
+    })
 }
 
 pub async fn update_entry(
@@ -59,92 +73,151 @@ pub async fn update_entry(
     url: Option<String>,
     passwords: Option<(String, String)>,
 ) -> Result<entry::Model, Error> {
-    let con = configuration::connect().await?;
-    let err = "Error modifying entry";
-    let mut entry: entry::ActiveModel = Entry::find_by_id(entry_id.to_owned())
-        .one(&con)
+    match passwords {
+        Some((master_password, new_password)) => {
+            Vault::open_current_unlocked(master_password)
+                .await?
+                .update_entry(
+                    entry_id,
+                    name,
+                    description,
+                    username,
+                    url,
+                    Some(new_password),
+                )
+                .await
+        }
+        None => {
+            Vault::open_current()
+                .await?
+                .update_entry(entry_id, name, description, username, url, None)
+                .await
+        }
+    }
+}
+
+/// Sets (or clears, when `totp` is `None`) the TOTP secret of an entry, see `Vault::set_totp`
+pub async fn set_totp(
+    master_password: String,
+    entry_id: String,
+    totp: Option<String>,
+) -> Result<entry::Model, Error> {
+    Vault::open_current_unlocked(master_password)
+        .await?
+        .set_totp(entry_id, totp)
         .await
-        .map_err(|_| err)?
-        .ok_or(err)?
-        .into_active_model();
+}
 
-    let mut is_modified = false;
+/// Moves an entry into a folder (a `/` separated path), or out of any folder when `None`
+pub async fn set_folder(entry_id: String, folder: Option<String>) -> Result<entry::Model, Error> {
+    Vault::open_current()
+        .await?
+        .set_folder(entry_id, folder)
+        .await
+}
 
-    if let Some(name) = name {
-        entry.name = Set(name);
-        is_modified = true;
-    }
+/// Replaces the tags of an entry
+pub async fn set_tags(entry_id: String, tags: Vec<String>) -> Result<entry::Model, Error> {
+    Vault::open_current().await?.set_tags(entry_id, tags).await
+}
 
-    if let Some(description) = description {
-        entry.description = Set(Some(description));
-        is_modified = true;
-    }
+/// The tags of an entry, which are stored comma separated
+pub fn tags_of(entry: &entry::Model) -> Vec<String> {
+    split_tags(entry.tags.as_deref().unwrap_or_default())
+}
 
-    if let Some(username) = username {
-        entry.username = Set(Some(username));
-        is_modified = true;
+/// Splits comma separated tags, trimmed and without duplicates
+pub fn split_tags(tags: &str) -> Vec<String> {
+    let mut out: Vec<String> = Vec::new();
+    for tag in tags.split(',').map(|t| t.trim()).filter(|t| !t.is_empty()) {
+        if !out.iter().any(|t| t == tag) {
+            out.push(tag.to_owned());
+        }
     }
+    out
+}
 
-    if let Some(url) = url {
-        util::validate_url(url.to_owned())?;
-        entry.url = Set(Some(url));
-        is_modified = true;
-    }
+/// The stored value of tags, `None` when there are none
+pub(crate) fn join_tags(tags: Vec<String>) -> Option<String> {
+    let tags = split_tags(&tags.join(","));
+    Some(tags.join(",")).filter(|t| !t.is_empty())
+}
 
-    if let Some(passwords) = passwords {
-        let (master_password, new_password) = passwords;
-        let master = master::require_master().await?;
-        let new_encrypted_password: Vec<u8> = crypto::encrypt_password(
-            master_password.to_owned(),
-            new_password.to_owned(),
-            entry_id,
-            master.id.to_owned(),
-        )?;
-        entry.password = Set(new_encrypted_password)
-    }
+/// Trims the segments of a folder path and removes empty ones
+pub(crate) fn normalize_folder(folder: Option<String>) -> Option<String> {
+    folder
+        .map(|f| {
+            f.split('/')
+                .map(|s| s.trim())
+                .filter(|s| !s.is_empty())
+                .collect::<Vec<&str>>()
+                .join("/")
+        })
+        .filter(|f| !f.is_empty())
+}
 
-    if is_modified {
-        let modified_date = util::now();
-        entry.modified_date = Set(modified_date);
-    }
+pub async fn delete_entry(entry_id: String) -> Result<(), Error> {
+    Vault::open_current().await?.delete_entry(entry_id).await
+}
 
-    entry
-        .update(&con)
-        .await
-        .map_err(|_| "Failed to update entry".to_owned())
+pub async fn get_entry(entry_id: String) -> Result<entry::Model, Error> {
+    Vault::open_current().await?.get_entry(entry_id).await
 }
 
-pub async fn delete_entry(entry_id: String) -> Result<(), Error> {
-    let conn = configuration::connect().await?;
-    let err = "Failed to delete entry";
-    let entry = Entry::find_by_id(entry_id)
-        .one(&conn)
-        .await
-        .map_err(|_| err)?
-        .ok_or(err)?
-        .into_active_model();
+/// The order in which `EntryQuery` lists entries
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum EntrySort {
+    Name,
+    Created,
+    Modified,
+}
 
-    entry
-        .delete(&conn)
-        .await
-        .map_err(|_| err.to_owned())
-        .map(|_| ())
+/// Which entries to list, and in which order
+#[derive(Debug, Clone, Default)]
+pub struct EntryQuery {
+    /// Only the entries modified at or after this time
+    pub modified_since: Option<DateTime<Utc>>,
+    /// Only the entries last modified before this time
+    pub older_than: Option<DateTime<Utc>>,
+    pub sort: Option<EntrySort>,
+    pub reverse: bool,
 }
 
-pub async fn get_entry(entry_id: String) -> Result<entry::Model, Error> {
-    let conn = configuration::connect().await?;
-    let err = "Failed to fetch entry";
-    Entry::find_by_id(entry_id)
-        .one(&conn)
-        .await
-        .map_err(|_| err)?
-        .ok_or(err.to_owned())
+impl EntryQuery {
+    /// Whether some entries may be left out
+    pub fn is_filtered(&self) -> bool {
+        self.modified_since.is_some() || self.older_than.is_some()
+    }
+
+    /// Filters and sorts the entries, each with its number (its position in `entries`)
+    pub fn apply(&self, entries: Vec<entry::Model>) -> Vec<(usize, entry::Model)> {
+        let mut entries: Vec<(usize, entry::Model)> = entries
+            .into_iter()
+            .enumerate()
+            .map(|(index, entry)| (index + 1, entry))
+            .filter(|(_, entry)| {
+                self.modified_since
+                    .is_none_or(|since| entry.modified_date >= since)
+                    && self
+                        .older_than
+                        .is_none_or(|before| entry.modified_date < before)
+            })
+            .collect();
+        match self.sort {
+            Some(EntrySort::Name) => {
+                entries.sort_by_key(|(_, entry)| entry.name.to_lowercase());
+            }
+            Some(EntrySort::Created) => entries.sort_by_key(|(_, entry)| entry.created_date),
+            Some(EntrySort::Modified) => entries.sort_by_key(|(_, entry)| entry.modified_date),
+            None => {}
+        }
+        if self.reverse {
+            entries.reverse();
+        }
+        entries
+    }
 }
 
 pub async fn get_all_entries() -> Result<Vec<entry::Model>, Error> {
-    let con = configuration::connect().await?;
-    Entry::find()
-        .all(&con)
-        .await
-        .map_err(|_| "Failed to get all entries".to_owned())
+    Vault::open_current().await?.get_all_entries().await
 }
@@ -1,13 +1,30 @@
+use std::{
+    collections::{HashMap, HashSet},
+    fs::{OpenOptions, Permissions},
+    io::Write,
+    os::unix::fs::{OpenOptionsExt, PermissionsExt},
+    path::Path,
+};
+
+use chrono::{DateTime, Utc};
 use model::entities;
 
 use crate::{
-    crypto,
-    entries::{self, create_entry},
+    configuration, container, crypto,
+    csv_profiles::{self, ColumnMapping, CsvProfile},
+    custom_fields::{self, CustomField},
+    entries,
     error::Error,
-    master, util,
+    ssh,
+    storage::Change,
+    totp, util,
+    vault::Vault,
 };
 use serde::{Deserialize, Serialize};
 
+/// The name, username and URL of an entry, which identify duplicates on import
+type EntryKey = (String, Option<String>, Option<String>);
+
 #[derive(Debug, Serialize, Deserialize)]
 pub struct EntryRecord {
     pub name: Option<String>,
@@ -15,82 +32,625 @@ pub struct EntryRecord {
     pub username: Option<String>,
     pub url: Option<String>,
     pub password: String,
+    pub totp: Option<String>,
+    pub folder: Option<String>,
+    /// Comma separated
+    pub tags: Option<String>,
 }
 
-impl EntryRecord {
-    pub fn from_entry(
-        entry: entities::entry::Model,
-        master: entities::master::Model,
-        master_password: String,
-    ) -> Result<EntryRecord, Error> {
-        let decrypted_password =
-            crypto::decrypt_password(master_password, entry.password, entry.id, master.id)?;
-        Ok(EntryRecord {
+impl From<DecryptedEntry> for EntryRecord {
+    fn from(entry: DecryptedEntry) -> Self {
+        EntryRecord {
             name: Some(entry.name),
             description: entry.description,
             username: entry.username,
             url: entry.url,
-            password: decrypted_password,
-        })
+            password: entry.password,
+            totp: entry.totp,
+            folder: entry.folder,
+            tags: entries::join_tags(entry.tags),
+        }
     }
 }
 
-pub async fn export_entries(master_password: String, path: Option<String>) -> Result<(), Error> {
-    let master = master::require_master().await?;
-    let path_to_csv: Result<String, Error> = path.map_or_else(
-        || {
-            let home_dir: String = util::get_home_env_var()?;
-            let p = format!("{}/{}", home_dir, String::from(".mypass/entries.csv"));
-            Ok(p)
-        },
-        Ok,
-    );
-    let err = "Failed to write to csv";
-    let path_to_csv: String = path_to_csv?;
-    util::create_file(path_to_csv.to_owned())?;
-    let entries: Vec<entities::entry::Model> = entries::get_all_entries().await?;
-    let mut wtr = csv::Writer::from_path(path_to_csv).map_err(|_| err.to_owned())?;
-
-    let mut out: Vec<EntryRecord> = Vec::new();
-    for ele in entries {
-        out.push(EntryRecord::from_entry(
-            ele.to_owned(),
-            master.to_owned(),
-            master_password.to_owned(),
-        )?);
-    }
-
-    out.iter().for_each(|entry| {
-        wtr.serialize(entry).ok();
-    });
-
-    wtr.flush().map_err(|_| err)?;
-    Ok(())
-}
-
-pub async fn import_entries(master_password: String, path: Option<String>) -> Result<(), Error> {
-    let path_to_csv: Result<String, Error> = path.map_or_else(
-        || {
-            let home_dir = util::get_home_env_var()?;
-            let p = format!("{}/{}", home_dir, String::from(".mypass/entries.csv"));
-            Ok(p)
-        },
-        Ok,
-    );
-    let path_to_csv: String = path_to_csv?;
-    let mut rdr =
-        csv::Reader::from_path(path_to_csv).map_err(|_| "Failed to read from provided path")?;
-    for result in rdr.deserialize() {
-        let record: EntryRecord = result.map_err(|e| format!("Failed to read entry {}", e))?;
-        create_entry(
+/// An entry with all of its secrets decrypted, as exchanged with other password managers
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct DecryptedEntry {
+    pub name: String,
+    pub description: Option<String>,
+    pub username: Option<String>,
+    pub url: Option<String>,
+    pub password: String,
+    pub totp: Option<String>,
+    pub folder: Option<String>,
+    pub tags: Vec<String>,
+    pub custom_fields: Vec<CustomField>,
+    /// The ID, dates and SSH public key of the entry, which only full-fidelity formats
+    /// (see `vault_file`) carry. Imports keep them when they are given.
+    pub id: Option<String>,
+    pub created_date: Option<DateTime<Utc>>,
+    pub modified_date: Option<DateTime<Utc>>,
+    pub ssh_public_key: Option<String>,
+}
+
+/// Which entries to export. Every condition that is given must match.
+#[derive(Debug, Clone, Default)]
+pub struct ExportFilter {
+    /// Matched case-insensitively against the name, username, URL and description
+    pub query: Option<String>,
+    /// Tags the entry must all have
+    pub tags: Vec<String>,
+    /// The folder of the entry, or one of its parents
+    pub folder: Option<String>,
+    /// The IDs of the entries, when only these should be exported
+    pub ids: Vec<String>,
+}
+
+impl ExportFilter {
+    pub fn matches(&self, entry: &entities::entry::Model) -> bool {
+        if !self.ids.is_empty() && !self.ids.contains(&entry.id) {
+            return false;
+        }
+        if let Some(query) = &self.query {
+            let query = query.to_lowercase();
+            let found = [
+                Some(&entry.name),
+                entry.username.as_ref(),
+                entry.url.as_ref(),
+                entry.description.as_ref(),
+            ]
+            .into_iter()
+            .flatten()
+            .any(|value| value.to_lowercase().contains(&query));
+            if !found {
+                return false;
+            }
+        }
+        if let Some(folder) = entries::normalize_folder(self.folder.to_owned()) {
+            let in_folder = entry
+                .folder
+                .as_deref()
+                .is_some_and(|f| f == folder || f.starts_with(&format!("{}/", folder)));
+            if !in_folder {
+                return false;
+            }
+        }
+        let tags = entries::tags_of(entry);
+        self.tags.iter().all(|tag| tags.contains(tag))
+    }
+}
+
+/// A field that can be left out of an export. The name of the entry is always exported.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ExportField {
+    Description,
+    Username,
+    Url,
+    Password,
+    Totp,
+    Folder,
+    Tags,
+    CustomFields,
+}
+
+/// The entries and fields of an export
+#[derive(Debug, Clone, Default)]
+pub struct ExportSelection {
+    pub filter: ExportFilter,
+    /// The fields to export, every field when `None`
+    pub fields: Option<Vec<ExportField>>,
+}
+
+impl ExportSelection {
+    /// Clears the fields of the entry that are not selected
+    fn apply(&self, entry: &mut DecryptedEntry) {
+        let Some(fields) = &self.fields else {
+            return;
+        };
+        let keep = |field: ExportField| fields.contains(&field);
+        if !keep(ExportField::Description) {
+            entry.description = None;
+        }
+        if !keep(ExportField::Username) {
+            entry.username = None;
+        }
+        if !keep(ExportField::Url) {
+            entry.url = None;
+        }
+        if !keep(ExportField::Password) {
+            entry.password = String::new();
+            entry.ssh_public_key = None;
+        }
+        if !keep(ExportField::Totp) {
+            entry.totp = None;
+        }
+        if !keep(ExportField::Folder) {
+            entry.folder = None;
+        }
+        if !keep(ExportField::Tags) {
+            entry.tags = Vec::new();
+        }
+        if !keep(ExportField::CustomFields) {
+            entry.custom_fields = Vec::new();
+        }
+    }
+}
+
+/// Decrypts the selected entries of the vault in use, with their custom fields
+pub async fn decrypt_entries(
+    master_password: String,
+    selection: &ExportSelection,
+) -> Result<Vec<DecryptedEntry>, Error> {
+    let vault = Vault::open_current_unlocked(master_password).await?;
+    decrypt_vault_entries(&vault, selection).await
+}
+
+/// Decrypts the selected entries of an unlocked vault, with their custom fields
+pub async fn decrypt_vault_entries(
+    vault: &Vault,
+    selection: &ExportSelection,
+) -> Result<Vec<DecryptedEntry>, Error> {
+    let mut out = Vec::new();
+    for entry in vault.get_all_entries().await? {
+        if !selection.filter.matches(&entry) {
+            continue;
+        }
+        let password = vault.decrypt_password(&entry)?;
+        let totp = vault.decrypt_totp(&entry)?;
+        let tags = entries::tags_of(&entry);
+        let custom_fields = vault.decrypt_custom_fields(entry.id.to_owned()).await?;
+        let mut decrypted = DecryptedEntry {
+            name: entry.name,
+            description: entry.description,
+            username: entry.username,
+            url: entry.url,
+            password,
+            totp,
+            folder: entry.folder,
+            tags,
+            custom_fields,
+            id: Some(entry.id),
+            created_date: Some(entry.created_date),
+            modified_date: Some(entry.modified_date),
+            ssh_public_key: entry.ssh_public_key,
+        };
+        selection.apply(&mut decrypted);
+        out.push(decrypted);
+    }
+    Ok(out)
+}
+
+/// What to do with an imported entry that has the same name, username and URL as an
+/// entry of the vault (or as an entry earlier in the same import)
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub enum MergeStrategy {
+    /// Keeps the existing entry and drops the imported one
+    #[default]
+    Skip,
+    /// Replaces the existing entry with the imported one
+    Overwrite,
+    /// Creates the imported entry alongside the existing one
+    KeepBoth,
+}
+
+#[derive(Debug, Clone, Copy, Default)]
+pub struct ImportOptions {
+    pub strategy: MergeStrategy,
+    /// Checks the import and reports what it would do, without changing the vault
+    pub dry_run: bool,
+    /// Keeps the values as they are, for full-fidelity formats: an empty name is not replaced by
+    /// the default name, and an invalid URL or TOTP secret is not moved to a custom field
+    pub lossless: bool,
+}
+
+/// The names of the entries an import created, overwrote and skipped
+#[derive(Debug, Clone, Default)]
+pub struct ImportSummary {
+    pub created: Vec<String>,
+    pub overwritten: Vec<String>,
+    pub skipped: Vec<String>,
+}
+
+/// Creates the entries in a single change of the storage, so that a failed import leaves the
+/// vault unchanged, and on a dry run only checks every entry.
+/// Values that are not valid for their column (a URL that does not parse, a TOTP secret
+/// that is not base32) are kept as custom fields rather than failing the import, unless the
+/// import is lossless.
+/// An entry with the ID of an existing entry is a duplicate of it, whatever its name.
+pub async fn create_entries(
+    master_password: String,
+    entries: Vec<DecryptedEntry>,
+    options: ImportOptions,
+) -> Result<ImportSummary, Error> {
+    let vault = Vault::open_current_unlocked(master_password).await?;
+    create_vault_entries(&vault, entries, options).await
+}
+
+/// Creates the entries in an unlocked vault, see `create_entries`
+pub async fn create_vault_entries(
+    vault: &Vault,
+    entries: Vec<DecryptedEntry>,
+    options: ImportOptions,
+) -> Result<ImportSummary, Error> {
+    let err = "Failed to import entries";
+    let (master, master_password) = vault.unlocked()?;
+    let settings = vault.get_settings().await?;
+
+    // The id of the entry holding each name, username and URL
+    let mut existing: HashMap<EntryKey, String> = HashMap::new();
+    let mut ids: HashSet<String> = HashSet::new();
+    for entry in vault.get_all_entries().await? {
+        ids.insert(entry.id.to_owned());
+        existing
+            .entry((entry.name, entry.username, entry.url))
+            .or_insert(entry.id);
+    }
+
+    let mut changes: Vec<Change> = Vec::new();
+    // The index in the changes of the entries created or overwritten by the import
+    let mut changed: HashMap<String, usize> = HashMap::new();
+    let mut summary = ImportSummary::default();
+    for mut record in entries {
+        if !options.lossless {
+            keep_invalid_values_as_custom_fields(&mut record);
+        }
+        let totp = match record.totp {
+            Some(secret) => Some(crypto::encrypt_secret(
+                master_password.to_owned(),
+                secret,
+                master.id.to_owned(),
+            )?),
+            None => None,
+        };
+
+        let name = if options.lossless {
+            record.name
+        } else {
+            Some(record.name)
+                .filter(|n| !n.trim().is_empty())
+                .unwrap_or(settings.entries.default_name.to_owned())
+        };
+        let key = (
+            name.to_owned(),
+            record.username.to_owned(),
+            record.url.to_owned(),
+        );
+        let duplicate = record
+            .id
+            .to_owned()
+            .filter(|id| ids.contains(id))
+            .or_else(|| existing.get(&key).cloned());
+
+        let entry_id = match (duplicate, options.strategy) {
+            (Some(_), MergeStrategy::Skip) => {
+                summary.skipped.push(name);
+                continue;
+            }
+            (Some(id), MergeStrategy::Overwrite) => {
+                let mut entry = match changed.get(&id) {
+                    Some(&index) => match &changes[index] {
+                        Change::InsertEntry(entry) | Change::UpdateEntry(entry) => entry.to_owned(),
+                        _ => return Err(err.to_owned()),
+                    },
+                    None => vault.get_entry(id.to_owned()).await.map_err(|_| err)?,
+                };
+                // The password of an SSH key entry is the private key, keep the public key
+                // only if the imported password is still one
+                if record.ssh_public_key.is_some() {
+                    entry.ssh_public_key = record.ssh_public_key;
+                } else if entry.ssh_public_key.is_some() {
+                    let public_key = ssh::parse_private_key(&record.password)
+                        .ok()
+                        .filter(|key| !key.is_encrypted())
+                        .and_then(|key| ssh::public_key_of(&key).ok());
+                    entry.ssh_public_key = public_key;
+                }
+                entry.name = name.to_owned();
+                entry.description = record.description;
+                entry.username = record.username;
+                entry.url = record.url;
+                entry.password = crypto::encrypt_password(
+                    master_password.to_owned(),
+                    record.password,
+                    id.to_owned(),
+                    master.id.to_owned(),
+                )?;
+                entry.totp = totp;
+                entry.folder = entries::normalize_folder(record.folder);
+                entry.tags = entries::join_tags(record.tags);
+                entry.modified_date = record.modified_date.unwrap_or_else(util::now);
+                // An entry created earlier in the import is still to be inserted
+                match changed.get(&id) {
+                    Some(&index) => {
+                        changes[index] = match changes[index] {
+                            Change::InsertEntry(_) => Change::InsertEntry(entry),
+                            _ => Change::UpdateEntry(entry),
+                        }
+                    }
+                    None => {
+                        changed.insert(id.to_owned(), changes.len());
+                        changes.push(Change::UpdateEntry(entry));
+                    }
+                }
+                summary.overwritten.push(name);
+                id
+            }
+            _ => {
+                let mut entry = entries::new_entry(
+                    master_password.to_owned(),
+                    master,
+                    name.to_owned(),
+                    record.description,
+                    record.username,
+                    record.password.to_owned(),
+                    None,
+                )?;
+                // Invalid URLs were moved to custom fields, unless the import is lossless
+                entry.url = record.url;
+                // The imported ID is kept unless an entry already has it, the password is
+                // encrypted with the ID
+                if let Some(id) = record.id.filter(|id| !ids.contains(id)) {
+                    entry.password = crypto::encrypt_password(
+                        master_password.to_owned(),
+                        record.password,
+                        id.to_owned(),
+                        master.id.to_owned(),
+                    )?;
+                    entry.id = id;
+                }
+                entry.totp = totp;
+                entry.folder = entries::normalize_folder(record.folder);
+                entry.tags = entries::join_tags(record.tags);
+                entry.ssh_public_key = record.ssh_public_key;
+                if let Some(created_date) = record.created_date {
+                    entry.created_date = created_date;
+                }
+                if let Some(modified_date) = record.modified_date {
+                    entry.modified_date = modified_date;
+                }
+                let id = entry.id.to_owned();
+                ids.insert(id.to_owned());
+                existing.entry(key).or_insert(id.to_owned());
+                changed.insert(id.to_owned(), changes.len());
+                changes.push(Change::InsertEntry(entry));
+                summary.created.push(name);
+                id
+            }
+        };
+        let fields = custom_fields::encrypt_custom_fields(
             master_password.to_owned(),
-            record.name.unwrap_or("Untitled".to_owned()),
-            record.description,
-            record.username,
-            record.password,
-            record.url,
-        )
-        .await?;
-    }
-    Ok(())
+            master,
+            entry_id.to_owned(),
+            record.custom_fields,
+        )?;
+        changes.push(Change::SetCustomFields(entry_id, fields));
+    }
+
+    if !options.dry_run {
+        vault.storage().apply(changes).await?;
+    }
+    Ok(summary)
+}
+
+/// Moves a URL that does not parse and a TOTP secret that is not valid to custom fields
+fn keep_invalid_values_as_custom_fields(record: &mut DecryptedEntry) {
+    if let Some(url) = record.url.take() {
+        match util::validate_url(url.to_owned()) {
+            Ok(_) => record.url = Some(url),
+            Err(_) => record
+                .custom_fields
+                .push(CustomField::new("URL", url, false)),
+        }
+    }
+    if let Some(secret) = record.totp.take() {
+        match totp::parse(&secret) {
+            Ok(_) => record.totp = Some(secret),
+            Err(_) => record
+                .custom_fields
+                .push(CustomField::new("TOTP", secret, true)),
+        }
+    }
+}
+
+/// The default path of exports, entries.mypass in the data directory, or entries.csv when unencrypted
+pub fn default_export_path(encrypted: bool) -> Result<String, Error> {
+    let extension = if encrypted { "mypass" } else { "csv" };
+    let path = configuration::get_data_dir_path()?.join(format!("entries.{}", extension));
+    Ok(path.to_string_lossy().into_owned())
+}
+
+/// The default path of imports, the encrypted export if there is one, the csv export otherwise
+pub fn default_import_path() -> Result<String, Error> {
+    let encrypted = default_export_path(true)?;
+    if Path::new(&encrypted).exists() {
+        return Ok(encrypted);
+    }
+    default_export_path(false)
+}
+
+/// Exports the selected entries as csv, encrypted with the passphrase unless none is given.
+/// Custom fields have no column, they can only be exported to the other formats.
+pub async fn export_entries(
+    master_password: String,
+    path: Option<String>,
+    passphrase: Option<String>,
+    selection: &ExportSelection,
+) -> Result<usize, Error> {
+    let err = "Failed to write to csv";
+    if selection
+        .fields
+        .as_ref()
+        .is_some_and(|fields| fields.contains(&ExportField::CustomFields))
+    {
+        return Err("Custom fields cannot be exported to csv, use JSON or YAML instead".to_owned());
+    }
+    let path = match path {
+        Some(path) => path,
+        None => default_export_path(passphrase.is_some())?,
+    };
+    let entries = decrypt_entries(master_password, selection).await?;
+    let count = entries.len();
+    let mut wtr = csv::Writer::from_writer(Vec::new());
+
+    for entry in entries {
+        wtr.serialize(EntryRecord::from(entry))
+            .map_err(|e| format!("{} {}", err, e))?;
+    }
+
+    let data = wtr.into_inner().map_err(|_| err)?;
+    let data = match passphrase {
+        Some(passphrase) => {
+            let kdf = configuration::get_settings()?.kdf;
+            container::seal("csv", &data, &passphrase, &kdf)?.into_bytes()
+        }
+        None => data,
+    };
+    write_private_file(&path, &data)?;
+    Ok(count)
+}
+
+/// Imports csv data whose columns are given by the mapping, see `csv_profiles`
+pub async fn import_entries(
+    master_password: String,
+    data: Vec<u8>,
+    mapping: ColumnMapping,
+    profile: Option<CsvProfile>,
+    options: ImportOptions,
+) -> Result<ImportSummary, Error> {
+    let entries = csv_profiles::read_entries(&data, &mapping, profile)?;
+    create_entries(master_password, entries, options).await
+}
+
+/// Writes a file only readable by the current user, replacing it if it exists
+pub fn write_private_file(path: &str, data: &[u8]) -> Result<(), Error> {
+    let err = format!("Failed to write {}", path);
+    util::create_file(path.to_owned())?;
+    let mut file = OpenOptions::new()
+        .write(true)
+        .truncate(true)
+        .mode(0o600)
+        .open(path)
+        .map_err(|_| err.to_owned())?;
+    // The mode only applies to new files
+    file.set_permissions(Permissions::from_mode(0o600))
+        .map_err(|_| err.to_owned())?;
+    file.write_all(data).map_err(|_| err)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::vault::test_vault;
+
+    fn github(password: &str) -> DecryptedEntry {
+        DecryptedEntry {
+            name: "github".to_owned(),
+            username: Some("me".to_owned()),
+            url: Some("https://github.com".to_owned()),
+            password: password.to_owned(),
+            ..Default::default()
+        }
+    }
+
+    fn options(strategy: MergeStrategy, dry_run: bool) -> ImportOptions {
+        ImportOptions {
+            strategy,
+            dry_run,
+            ..Default::default()
+        }
+    }
+
+    async fn passwords(vault: &Vault) -> Vec<String> {
+        decrypt_vault_entries(vault, &ExportSelection::default())
+            .await
+            .unwrap()
+            .into_iter()
+            .map(|entry| entry.password)
+            .collect()
+    }
+
+    #[tokio::test]
+    async fn duplicates_follow_the_merge_strategy() {
+        let vault = test_vault().await;
+        let import = |password: &str, strategy| {
+            create_vault_entries(&vault, vec![github(password)], options(strategy, false))
+        };
+        assert_eq!(
+            import("one", MergeStrategy::Skip)
+                .await
+                .unwrap()
+                .created
+                .len(),
+            1
+        );
+
+        let summary = import("two", MergeStrategy::Skip).await.unwrap();
+        assert_eq!(summary.skipped, vec!["github"]);
+        assert_eq!(passwords(&vault).await, vec!["one"]);
+
+        let summary = import("three", MergeStrategy::Overwrite).await.unwrap();
+        assert_eq!(summary.overwritten, vec!["github"]);
+        assert_eq!(passwords(&vault).await, vec!["three"]);
+
+        let summary = import("four", MergeStrategy::KeepBoth).await.unwrap();
+        assert_eq!(summary.created, vec!["github"]);
+        assert_eq!(passwords(&vault).await, vec!["three", "four"]);
+    }
+
+    #[tokio::test]
+    async fn duplicates_within_an_import_are_merged() {
+        let vault = test_vault().await;
+        let entries = vec![github("one"), github("two")];
+        let summary =
+            create_vault_entries(&vault, entries, options(MergeStrategy::Overwrite, false))
+                .await
+                .unwrap();
+        assert_eq!(summary.created.len(), 1);
+        assert_eq!(summary.overwritten.len(), 1);
+        assert_eq!(passwords(&vault).await, vec!["two"]);
+    }
+
+    #[tokio::test]
+    async fn dry_run_leaves_the_vault_unchanged() {
+        let vault = test_vault().await;
+        create_vault_entries(&vault, vec![github("one")], ImportOptions::default())
+            .await
+            .unwrap();
+
+        let entries = vec![github("two"), DecryptedEntry::default()];
+        let summary =
+            create_vault_entries(&vault, entries, options(MergeStrategy::Overwrite, true))
+                .await
+                .unwrap();
+        assert_eq!(summary.overwritten, vec!["github"]);
+        assert_eq!(summary.created, vec!["Untitled"]);
+        assert_eq!(passwords(&vault).await, vec!["one"]);
+    }
+
+    #[tokio::test]
+    async fn invalid_values_become_custom_fields() {
+        let vault = test_vault().await;
+        let entry = DecryptedEntry {
+            url: Some("not a url".to_owned()),
+            totp: Some("1nvalid!".to_owned()),
+            ..Default::default()
+        };
+        create_vault_entries(&vault, vec![entry], ImportOptions::default())
+            .await
+            .unwrap();
+
+        let imported = decrypt_vault_entries(&vault, &ExportSelection::default())
+            .await
+            .unwrap();
+        assert_eq!(imported[0].name, "Untitled");
+        assert_eq!(imported[0].url, None);
+        assert_eq!(imported[0].totp, None);
+        assert_eq!(
+            imported[0].custom_fields,
+            vec![
+                CustomField::new("TOTP", "1nvalid!", true),
+                CustomField::new("URL", "not a url", false),
+            ]
+        );
+    }
 }
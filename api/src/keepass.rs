@@ -0,0 +1,385 @@
+use std::{fs::File, io::Read};
+
+use keepass::{
+    config::{DatabaseConfig, KdfConfig, OuterCipherConfig},
+    db::{fields, GroupId, GroupRef, Value},
+    Database, DatabaseKey,
+};
+
+use crate::{
+    custom_fields::CustomField,
+    entry_transfer::{self, DecryptedEntry, ExportSelection, ImportOptions, ImportSummary},
+    error::Error,
+    totp,
+};
+
+/// Appended to the name of custom fields named like a standard field (e.g. `Title`),
+/// which would otherwise replace it in KeePass
+const RESERVED_NAME_SUFFIX: &str = " (custom field)";
+
+/// The cipher used to encrypt an exported KeePass database
+#[derive(Debug, Clone, Copy, Default)]
+pub enum Cipher {
+    #[default]
+    Aes256,
+    ChaCha20,
+}
+
+fn database_key(password: Option<String>, keyfile: Option<String>) -> Result<DatabaseKey, Error> {
+    let mut key = DatabaseKey::new();
+    if let Some(password) = password {
+        key = key.with_password(&password);
+    }
+    if let Some(keyfile) = keyfile {
+        let mut file =
+            File::open(&keyfile).map_err(|_| format!("Failed to read keyfile {}", keyfile))?;
+        key = key
+            .with_keyfile(&mut file)
+            .map_err(|_| format!("Invalid keyfile {}", keyfile))?;
+    }
+    if key.is_empty() {
+        return Err("A password or a keyfile is required".to_owned());
+    }
+    Ok(key)
+}
+
+/// Imports a KDBX database. Groups become folders, and fields other than the
+/// standard ones (and the `otp` field, which becomes the TOTP secret) become custom fields.
+/// The recycle bin is skipped.
+pub async fn import_keepass(
+    master_password: String,
+    path: String,
+    password: Option<String>,
+    keyfile: Option<String>,
+    options: ImportOptions,
+) -> Result<ImportSummary, Error> {
+    let key = database_key(password, keyfile)?;
+    let mut file = File::open(&path).map_err(|_| format!("Failed to read {}", path))?;
+    let entries = read_database(&mut file, key)?;
+    entry_transfer::create_entries(master_password, entries, options).await
+}
+
+fn read_database(source: &mut dyn Read, key: DatabaseKey) -> Result<Vec<DecryptedEntry>, Error> {
+    let db = Database::open(source, key)
+        .map_err(|e| format!("Failed to open the KeePass database: {}", e))?;
+    let mut entries = Vec::new();
+    let recycle_bin = db.recycle_bin().map(|g| g.id());
+    collect_entries(db.root(), None, recycle_bin, &mut entries);
+    Ok(entries)
+}
+
+fn collect_entries(
+    group: GroupRef<'_>,
+    folder: Option<String>,
+    recycle_bin: Option<GroupId>,
+    out: &mut Vec<DecryptedEntry>,
+) {
+    for entry in group.entries() {
+        let get = |key: &str| {
+            entry
+                .get(key)
+                .map(|v| v.to_owned())
+                .filter(|v| !v.is_empty())
+        };
+        let mut custom_fields: Vec<CustomField> = entry
+            .fields
+            .iter()
+            .filter(|(key, _)| !fields::KNOWN_FIELDS.contains(&key.as_str()) && *key != fields::OTP)
+            .map(|(key, value)| {
+                let name = key
+                    .strip_suffix(RESERVED_NAME_SUFFIX)
+                    .filter(|name| is_reserved(name))
+                    .unwrap_or(key);
+                CustomField::new(name, value.get(), value.is_protected())
+            })
+            .collect();
+        custom_fields.sort_by(|a, b| a.name.cmp(&b.name));
+
+        out.push(DecryptedEntry {
+            name: get(fields::TITLE).unwrap_or_default(),
+            description: get(fields::NOTES),
+            username: get(fields::USERNAME),
+            url: get(fields::URL),
+            password: get(fields::PASSWORD).unwrap_or_default(),
+            totp: get(fields::OTP),
+            folder: folder.to_owned(),
+            tags: entry.tags.to_owned(),
+            custom_fields,
+            ..Default::default()
+        });
+    }
+
+    for child in group.groups() {
+        if Some(child.id()) == recycle_bin {
+            continue;
+        }
+        let path = match &folder {
+            Some(folder) => format!("{}/{}", folder, child.name),
+            None => child.name.to_owned(),
+        };
+        collect_entries(child, Some(path), recycle_bin, out);
+    }
+}
+
+/// Exports the selected entries to a KDBX 4 database (Argon2id key derivation), creating a group per folder.
+/// Custom fields named like a standard field get a suffix (see `RESERVED_NAME_SUFFIX`), removed on import.
+pub async fn export_keepass(
+    master_password: String,
+    path: String,
+    password: Option<String>,
+    keyfile: Option<String>,
+    cipher: Cipher,
+    selection: &ExportSelection,
+) -> Result<usize, Error> {
+    let key = database_key(password, keyfile)?;
+    let entries = entry_transfer::decrypt_entries(master_password, selection).await?;
+    let count = entries.len();
+    let data = write_database(entries, key, export_config(cipher))?;
+    entry_transfer::write_private_file(&path, &data)?;
+    Ok(count)
+}
+
+/// A KDBX 4 configuration with the cipher and the Argon2id parameters of a new KeePassXC database
+fn export_config(cipher: Cipher) -> DatabaseConfig {
+    let mut config = DatabaseConfig::default();
+    config.outer_cipher_config = match cipher {
+        Cipher::Aes256 => OuterCipherConfig::AES256,
+        Cipher::ChaCha20 => OuterCipherConfig::ChaCha20,
+    };
+    if let KdfConfig::Argon2 { version, .. } = config.kdf_config {
+        config.kdf_config = KdfConfig::Argon2id {
+            iterations: 10,
+            memory: 64 * 1024 * 1024,
+            parallelism: 2,
+            version,
+        };
+    }
+    config
+}
+
+fn write_database(
+    entries: Vec<DecryptedEntry>,
+    key: DatabaseKey,
+    config: DatabaseConfig,
+) -> Result<Vec<u8>, Error> {
+    let mut db = Database::with_config(config);
+    db.root_mut().name = "MyPass".to_owned();
+
+    for entry in entries {
+        let group = group_for(&mut db, entry.folder.as_deref());
+        let Some(mut group) = db.group_mut(group) else {
+            continue;
+        };
+        let mut new_entry = group.add_entry();
+        new_entry.set_unprotected(fields::TITLE, entry.name.to_owned());
+        new_entry.set_protected(fields::PASSWORD, entry.password);
+        if let Some(username) = entry.username {
+            new_entry.set_unprotected(fields::USERNAME, username);
+        }
+        if let Some(url) = entry.url {
+            new_entry.set_unprotected(fields::URL, url);
+        }
+        if let Some(description) = entry.description {
+            new_entry.set_unprotected(fields::NOTES, description);
+        }
+        new_entry.tags = entry.tags;
+        if let Some(totp) = entry.totp {
+            new_entry.set_protected(fields::OTP, otp_url(&totp, &entry.name));
+        }
+        for field in entry.custom_fields {
+            let value = if field.protected {
+                Value::protected(field.value)
+            } else {
+                Value::unprotected(field.value)
+            };
+            let name = if is_reserved(&field.name) {
+                format!("{}{}", field.name, RESERVED_NAME_SUFFIX)
+            } else {
+                field.name
+            };
+            new_entry.set(name, value);
+        }
+    }
+
+    let mut data = Vec::new();
+    db.save(&mut data, key)
+        .map_err(|e| format!("Failed to write the KeePass database: {}", e))?;
+    Ok(data)
+}
+
+/// Whether KeePass uses the field name for a standard field
+fn is_reserved(name: &str) -> bool {
+    fields::KNOWN_FIELDS.contains(&name) || name == fields::OTP
+}
+
+/// KeePass clients expect an `otpauth://` URL rather than a bare base32 key
+fn otp_url(secret: &str, name: &str) -> String {
+    if secret.starts_with("otpauth://") {
+        return secret.to_owned();
+    }
+    match totp::parse(secret) {
+        Ok(mut totp) => {
+            totp.account_name = name.to_owned();
+            totp.get_url()
+        }
+        Err(_) => secret.to_owned(),
+    }
+}
+
+/// The group for a folder, creating the missing groups of its path
+fn group_for(db: &mut Database, folder: Option<&str>) -> GroupId {
+    let mut current = db.root().id();
+    for name in folder
+        .unwrap_or_default()
+        .split('/')
+        .filter(|n| !n.is_empty())
+    {
+        let existing = db
+            .group(current)
+            .and_then(|g| g.groups().find(|c| c.name == name).map(|c| c.id()));
+        current = match existing {
+            Some(id) => id,
+            None => match db.group_mut(current) {
+                Some(mut parent) => {
+                    let mut group = parent.add_group();
+                    group.name = name.to_owned();
+                    group.id()
+                }
+                None => return current,
+            },
+        };
+    }
+    current
+}
+
+#[cfg(test)]
+mod tests {
+    use sea_orm::prelude::Uuid;
+
+    use super::*;
+
+    const PASSWORD: &str = "correct horse battery staple";
+
+    /// The export configuration with a cheap key derivation, Argon2id is slow in the tests
+    fn test_config(cipher: Cipher) -> DatabaseConfig {
+        let mut config = export_config(cipher);
+        if let KdfConfig::Argon2id { version, .. } = config.kdf_config {
+            config.kdf_config = KdfConfig::Argon2id {
+                iterations: 1,
+                memory: 1024 * 1024,
+                parallelism: 1,
+                version,
+            };
+        }
+        config
+    }
+
+    fn entries() -> Vec<DecryptedEntry> {
+        vec![
+            DecryptedEntry {
+                name: "GitHub".to_owned(),
+                description: Some("Work account".to_owned()),
+                username: Some("octocat".to_owned()),
+                url: Some("https://github.com".to_owned()),
+                password: "hunter2 with spaces".to_owned(),
+                totp: Some("JBSWY3DPEHPK3PXP".to_owned()),
+                folder: Some("Work/Dev".to_owned()),
+                tags: vec!["code".to_owned(), "work".to_owned()],
+                custom_fields: vec![
+                    CustomField::new("PIN", "1234", true),
+                    CustomField::new("Team", "Platform", false),
+                ],
+                ..Default::default()
+            },
+            DecryptedEntry {
+                name: "Bank".to_owned(),
+                password: "s3cret".to_owned(),
+                ..Default::default()
+            },
+        ]
+    }
+
+    #[test]
+    fn exports_use_argon2id_and_the_cipher() {
+        let config = export_config(Cipher::ChaCha20);
+        assert_eq!(config.outer_cipher_config, OuterCipherConfig::ChaCha20);
+        assert!(matches!(
+            config.kdf_config,
+            KdfConfig::Argon2id {
+                iterations: 10,
+                parallelism: 2,
+                ..
+            }
+        ));
+        let config = export_config(Cipher::Aes256);
+        assert_eq!(config.outer_cipher_config, OuterCipherConfig::AES256);
+    }
+
+    #[test]
+    fn databases_round_trip() {
+        let keyfile = std::env::temp_dir().join(format!("mypass-{}.key", Uuid::new_v4()));
+        std::fs::write(&keyfile, rand::random::<[u8; 32]>()).unwrap();
+        let keyfile = keyfile.to_string_lossy().into_owned();
+
+        for cipher in [Cipher::Aes256, Cipher::ChaCha20] {
+            for keyfile in [None, Some(keyfile.to_owned())] {
+                let key = || database_key(Some(PASSWORD.to_owned()), keyfile.clone()).unwrap();
+                let data = write_database(entries(), key(), test_config(cipher)).unwrap();
+
+                let db = Database::open(&mut data.as_slice(), key()).unwrap();
+                assert_eq!(db.config, test_config(cipher));
+                let read = read_database(&mut data.as_slice(), key()).unwrap();
+                // The entries of the root group come first
+                let [bank, github] = read.as_slice() else {
+                    panic!("expected 2 entries, got {:?}", read);
+                };
+                let totp = github.totp.as_deref().unwrap();
+                assert!(totp.starts_with("otpauth://totp/"), "{}", totp);
+                assert!(totp.contains("secret=JBSWY3DPEHPK3PXP"), "{}", totp);
+                assert_eq!(
+                    github,
+                    &DecryptedEntry {
+                        totp: github.totp.clone(),
+                        ..entries()[0].clone()
+                    }
+                );
+                assert_eq!(bank, &entries()[1]);
+            }
+
+            // A database protected by a keyfile cannot be opened with the password alone
+            let with_keyfile = database_key(Some(PASSWORD.to_owned()), Some(keyfile.to_owned()));
+            let data = write_database(entries(), with_keyfile.unwrap(), test_config(cipher));
+            let password_only = database_key(Some(PASSWORD.to_owned()), None).unwrap();
+            assert!(read_database(&mut data.unwrap().as_slice(), password_only).is_err());
+        }
+        std::fs::remove_file(keyfile).ok();
+    }
+
+    #[test]
+    fn custom_fields_keep_the_standard_fields() {
+        let custom_fields: Vec<CustomField> =
+            ["Notes", "Password", "Title", "URL", "UserName", "otp"]
+                .into_iter()
+                .map(|name| CustomField::new(name, format!("custom {}", name), false))
+                .collect();
+        let entry = DecryptedEntry {
+            custom_fields: custom_fields.clone(),
+            ..entries().remove(0)
+        };
+        let key = || database_key(Some(PASSWORD.to_owned()), None).unwrap();
+        let data = write_database(vec![entry], key(), test_config(Cipher::Aes256)).unwrap();
+
+        let db = Database::open(&mut data.as_slice(), key()).unwrap();
+        let root = db.root();
+        let work = root.groups().next().unwrap();
+        let dev = work.groups().next().unwrap();
+        let saved = dev.entries().next().unwrap();
+        assert_eq!(saved.get(fields::TITLE), Some("GitHub"));
+        assert_eq!(saved.get(fields::PASSWORD), Some("hunter2 with spaces"));
+        assert_eq!(saved.get("Title (custom field)"), Some("custom Title"));
+
+        let entries = read_database(&mut data.as_slice(), key()).unwrap();
+        assert_eq!(entries[0].name, "GitHub");
+        assert_eq!(entries[0].custom_fields, custom_fields);
+    }
+}
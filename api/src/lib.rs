@@ -1,7 +1,22 @@
+pub mod bitwarden;
 pub mod configuration;
+pub mod container;
 pub mod crypto;
+pub mod csv_profiles;
+pub mod custom_fields;
 pub mod entries;
 pub mod entry_transfer;
 pub mod error;
+pub mod keepass;
 pub mod master;
+pub mod memory_storage;
+pub mod migrations;
+pub mod pass;
+pub mod settings;
+pub mod sqlite_storage;
+pub mod ssh;
+pub mod storage;
+pub mod totp;
 pub mod util;
+pub mod vault;
+pub mod vault_file;
@@ -0,0 +1,156 @@
+use std::sync::RwLock;
+
+use async_trait::async_trait;
+use model::entities::{custom_field, entry, master};
+
+use crate::{
+    error::Error,
+    settings::Settings,
+    storage::{Change, Storage},
+};
+
+/// A storage that lives as long as the process, for tests and ephemeral vaults
+#[derive(Debug, Default)]
+pub struct MemoryStorage {
+    data: RwLock<Data>,
+}
+
+#[derive(Debug, Clone, Default)]
+struct Data {
+    master: Option<master::Model>,
+    entries: Vec<entry::Model>,
+    custom_fields: Vec<custom_field::Model>,
+    settings: Settings,
+}
+
+impl MemoryStorage {
+    pub fn new() -> MemoryStorage {
+        MemoryStorage::default()
+    }
+
+    /// A storage with the given settings, e.g. cheaper KDF parameters for tests
+    pub fn with_settings(settings: Settings) -> MemoryStorage {
+        MemoryStorage {
+            data: RwLock::new(Data {
+                settings,
+                ..Data::default()
+            }),
+        }
+    }
+
+    fn read<T>(&self, read: impl FnOnce(&Data) -> T) -> Result<T, Error> {
+        let data = self
+            .data
+            .read()
+            .map_err(|_| "Failed to read the vault".to_owned())?;
+        Ok(read(&data))
+    }
+
+    fn write<T>(&self, write: impl FnOnce(&mut Data) -> Result<T, Error>) -> Result<T, Error> {
+        let mut data = self
+            .data
+            .write()
+            .map_err(|_| "Failed to write the vault".to_owned())?;
+        write(&mut data)
+    }
+}
+
+impl Data {
+    fn position(&self, entry_id: &str) -> Option<usize> {
+        self.entries.iter().position(|entry| entry.id == entry_id)
+    }
+
+    fn apply(&mut self, change: Change) -> Result<(), Error> {
+        match change {
+            Change::InsertEntry(entry) => {
+                if self.position(&entry.id).is_some() {
+                    return Err("Failed to create a new entry".to_owned());
+                }
+                self.entries.push(entry);
+            }
+            Change::UpdateEntry(entry) => {
+                let index = self.position(&entry.id).ok_or("Failed to update entry")?;
+                self.entries[index] = entry;
+            }
+            Change::DeleteEntry(entry_id) => {
+                let index = self.position(&entry_id).ok_or("Failed to delete entry")?;
+                self.entries.remove(index);
+                self.custom_fields
+                    .retain(|field| field.entry_id != entry_id);
+            }
+            Change::SetCustomFields(entry_id, fields) => {
+                if self.position(&entry_id).is_none() {
+                    return Err("Failed to update custom fields".to_owned());
+                }
+                self.custom_fields
+                    .retain(|field| field.entry_id != entry_id);
+                self.custom_fields.extend(fields);
+            }
+        }
+        Ok(())
+    }
+}
+
+#[async_trait]
+impl Storage for MemoryStorage {
+    async fn get_master(&self) -> Result<Option<master::Model>, Error> {
+        self.read(|data| data.master.to_owned())
+    }
+
+    async fn insert_master(&self, master: master::Model) -> Result<(), Error> {
+        self.write(|data| {
+            if data.master.is_some() {
+                return Err("Master is already configured".to_owned());
+            }
+            data.master = Some(master);
+            Ok(())
+        })
+    }
+
+    async fn get_all_entries(&self) -> Result<Vec<entry::Model>, Error> {
+        self.read(|data| data.entries.to_owned())
+    }
+
+    async fn get_entry(&self, entry_id: &str) -> Result<Option<entry::Model>, Error> {
+        self.read(|data| {
+            data.position(entry_id)
+                .map(|index| data.entries[index].to_owned())
+        })
+    }
+
+    async fn get_custom_fields(&self, entry_id: &str) -> Result<Vec<custom_field::Model>, Error> {
+        let mut fields: Vec<custom_field::Model> = self.read(|data| {
+            data.custom_fields
+                .iter()
+                .filter(|field| field.entry_id == entry_id)
+                .cloned()
+                .collect()
+        })?;
+        fields.sort_by(|a, b| a.name.cmp(&b.name));
+        Ok(fields)
+    }
+
+    /// The changes are applied to a copy, which replaces the data once they all succeeded
+    async fn apply(&self, changes: Vec<Change>) -> Result<(), Error> {
+        self.write(|data| {
+            let mut changed = data.clone();
+            for change in changes {
+                changed.apply(change)?;
+            }
+            *data = changed;
+            Ok(())
+        })
+    }
+
+    async fn get_settings(&self) -> Result<Settings, Error> {
+        self.read(|data| data.settings.to_owned())
+    }
+
+    async fn set_settings(&self, settings: Settings) -> Result<(), Error> {
+        settings.validate()?;
+        self.write(|data| {
+            data.settings = settings;
+            Ok(())
+        })
+    }
+}
@@ -0,0 +1,192 @@
+use std::{
+    fs,
+    path::{Path, PathBuf},
+};
+
+use chrono::Local;
+use migration::{Migrator, MigratorTrait, SchemaManager};
+use sea_orm::DatabaseConnection;
+
+use crate::{configuration, error::Error};
+
+/// A migration of this version of MyPass, and whether the data store has it
+#[derive(Debug, Clone)]
+pub struct MigrationInfo {
+    pub name: String,
+    pub applied: bool,
+}
+
+/// The migrations of a data store
+#[derive(Debug, Clone)]
+pub struct SchemaStatus {
+    pub path: PathBuf,
+    pub migrations: Vec<MigrationInfo>,
+    /// Migrations applied by a newer version of MyPass, which this version cannot read
+    pub unknown: Vec<String>,
+}
+
+impl SchemaStatus {
+    /// The status of a data store without any migration applied
+    fn unmigrated(path: PathBuf) -> SchemaStatus {
+        SchemaStatus {
+            path,
+            migrations: Migrator::migrations()
+                .iter()
+                .map(|m| MigrationInfo {
+                    name: m.name().to_owned(),
+                    applied: false,
+                })
+                .collect(),
+            unknown: Vec::new(),
+        }
+    }
+
+    pub fn pending(&self) -> Vec<String> {
+        self.migrations
+            .iter()
+            .filter(|m| !m.applied)
+            .map(|m| m.name.to_owned())
+            .collect()
+    }
+
+    /// Whether the data store was written by a newer version of MyPass
+    pub fn is_newer(&self) -> bool {
+        !self.unknown.is_empty()
+    }
+}
+
+/// The migrations applied when connecting to a data store
+#[derive(Debug, Clone, Default)]
+pub struct MigrationReport {
+    pub applied: Vec<String>,
+    /// The copy of the data store taken before migrating it
+    pub backup: Option<PathBuf>,
+}
+
+/// The migrations of the data store of the current vault, without migrating it
+pub async fn get_status() -> Result<SchemaStatus, Error> {
+    let path = configuration::get_db_path()?;
+    if !path.exists() {
+        return Ok(SchemaStatus::unmigrated(path));
+    }
+    let conn = configuration::connect_without_migrating(&path).await?;
+    status(&conn, &path).await
+}
+
+/// Applies the pending migrations to the data store of the current vault
+pub async fn migrate_current() -> Result<MigrationReport, Error> {
+    let path = configuration::get_db_path()?;
+    let conn = configuration::connect_without_migrating(&path).await?;
+    migrate(&conn, &path).await
+}
+
+/// Reads the applied migrations without writing to the data store. A data store without
+/// the migrations table has none applied.
+pub(crate) async fn status(conn: &DatabaseConnection, path: &Path) -> Result<SchemaStatus, Error> {
+    let err = "Failed to read the migrations of the data store";
+    let table = Migrator::migration_table_name().to_string();
+    if !SchemaManager::new(conn)
+        .has_table(table)
+        .await
+        .map_err(|_| err)?
+    {
+        return Ok(SchemaStatus::unmigrated(path.to_owned()));
+    }
+    let applied: Vec<String> = Migrator::get_migration_models(conn)
+        .await
+        .map_err(|_| err)?
+        .into_iter()
+        .map(|m| m.version)
+        .collect();
+    let migrations: Vec<MigrationInfo> = Migrator::migrations()
+        .iter()
+        .map(|m| MigrationInfo {
+            name: m.name().to_owned(),
+            applied: applied.iter().any(|name| name == m.name()),
+        })
+        .collect();
+    let unknown = applied
+        .into_iter()
+        .filter(|name| !migrations.iter().any(|m| &m.name == name))
+        .collect();
+    Ok(SchemaStatus {
+        path: path.to_owned(),
+        migrations,
+        unknown,
+    })
+}
+
+/// Applies the pending migrations, after copying the data store unless it is new.
+/// A data store written by a newer version is refused, as its schema is unknown.
+pub(crate) async fn migrate(
+    conn: &DatabaseConnection,
+    path: &Path,
+) -> Result<MigrationReport, Error> {
+    let status = status(conn, path).await?;
+    if status.is_newer() {
+        return Err(format!(
+            "The data store at {} was written by a newer version of MyPass (migrations {}), please upgrade MyPass",
+            path.to_string_lossy(),
+            status.unknown.join(", ")
+        ));
+    }
+    let pending = status.pending();
+    if pending.is_empty() {
+        return Ok(MigrationReport::default());
+    }
+    let is_new = status.migrations.iter().all(|m| !m.applied);
+    let backup = if is_new { None } else { Some(backup(path)?) };
+    Migrator::up(conn, None).await.map_err(|e| {
+        let restore = backup
+            .as_ref()
+            .map(|b| format!(", a copy from before was kept at {}", b.to_string_lossy()))
+            .unwrap_or_default();
+        format!("Failed to migrate the data store: {}{}", e, restore)
+    })?;
+    Ok(MigrationReport {
+        applied: pending,
+        backup,
+    })
+}
+
+/// Copies the data store next to it, as `<name>.<timestamp>.bak`
+fn backup(path: &Path) -> Result<PathBuf, Error> {
+    let name = path
+        .file_name()
+        .ok_or("Invalid data store path")?
+        .to_string_lossy();
+    let timestamp = Local::now().format("%Y%m%d%H%M%S");
+    let backup = path.with_file_name(format!("{}.{}.bak", name, timestamp));
+    fs::copy(path, &backup).map_err(|_| {
+        format!(
+            "Failed to back up the data store to {}, it was not migrated",
+            backup.to_string_lossy()
+        )
+    })?;
+    Ok(backup)
+}
+
+#[cfg(test)]
+mod tests {
+    use sea_orm::Database;
+
+    use super::*;
+
+    #[tokio::test]
+    async fn status_does_not_write_to_the_data_store() {
+        let conn = Database::connect("sqlite::memory:").await.unwrap();
+        let path = Path::new("db.sqlite");
+
+        let before = status(&conn, path).await.unwrap();
+        assert_eq!(before.pending().len(), Migrator::migrations().len());
+        assert!(!before.is_newer());
+        let table = Migrator::migration_table_name().to_string();
+        assert!(!SchemaManager::new(&conn).has_table(table).await.unwrap());
+
+        // A new data store is not backed up
+        let report = migrate(&conn, path).await.unwrap();
+        assert_eq!(report.applied, before.pending());
+        assert_eq!(report.backup, None);
+        assert!(status(&conn, path).await.unwrap().pending().is_empty());
+    }
+}
@@ -0,0 +1,277 @@
+use std::{
+    fs,
+    path::{Path, PathBuf},
+    process::{Command, Stdio},
+};
+
+use crate::{
+    custom_fields::CustomField,
+    entry_transfer::{self, DecryptedEntry, ImportOptions, ImportSummary},
+    error::Error,
+    util,
+};
+
+/// The directory of the password store, as used by `pass`
+pub fn default_store_dir() -> Result<String, Error> {
+    match std::env::var("PASSWORD_STORE_DIR") {
+        Ok(dir) if !dir.is_empty() => Ok(dir),
+        _ => Ok(format!("{}/.password-store", util::get_home_env_var()?)),
+    }
+}
+
+/// Imports a `pass` password store. Each `.gpg` file is decrypted with the `gpg` binary
+/// (so gpg-agent asks for the key passphrase if needed) and becomes an entry named after
+/// the file, in a folder named after its directory.
+pub async fn import_pass(
+    master_password: String,
+    dir: String,
+    options: ImportOptions,
+) -> Result<ImportSummary, Error> {
+    let root = PathBuf::from(&dir);
+    if !root.is_dir() {
+        return Err(format!("{} is not a directory", dir));
+    }
+    let mut files = Vec::new();
+    find_gpg_files(&root, &mut files)?;
+    files.sort();
+
+    let mut entries = Vec::new();
+    for file in files {
+        let relative = file.strip_prefix(&root).unwrap_or(&file);
+        let name = relative
+            .file_stem()
+            .map(|s| s.to_string_lossy().into_owned())
+            .unwrap_or_default();
+        let folder = relative
+            .parent()
+            .map(|p| p.to_string_lossy().into_owned())
+            .filter(|p| !p.is_empty());
+        let contents = decrypt(&file)?;
+        entries.push(parse_entry(name, folder, &contents));
+    }
+    entry_transfer::create_entries(master_password, entries, options).await
+}
+
+/// Symbolic links to directories are not followed, as they may point back into the store
+fn find_gpg_files(dir: &Path, out: &mut Vec<PathBuf>) -> Result<(), Error> {
+    let entries = fs::read_dir(dir).map_err(|_| format!("Failed to read {}", dir.display()))?;
+    for entry in entries.flatten() {
+        let path = entry.path();
+        // Skips .git and .gpg-id
+        if entry.file_name().to_string_lossy().starts_with('.') {
+            continue;
+        }
+        let Ok(file_type) = entry.file_type() else {
+            continue;
+        };
+        if file_type.is_symlink() && path.is_dir() {
+            continue;
+        }
+        if file_type.is_dir() {
+            find_gpg_files(&path, out)?;
+        } else if path.extension().is_some_and(|e| e == "gpg") {
+            out.push(path);
+        }
+    }
+    Ok(())
+}
+
+fn decrypt(file: &Path) -> Result<String, Error> {
+    let output = Command::new("gpg")
+        .args(["--quiet", "--yes", "--decrypt"])
+        .arg(file)
+        .stdin(Stdio::inherit())
+        .stderr(Stdio::inherit())
+        .output()
+        .map_err(|_| "Failed to run gpg, is it installed?".to_owned())?;
+    if !output.status.success() {
+        return Err(format!("Failed to decrypt {}", file.display()));
+    }
+    String::from_utf8(output.stdout).map_err(|_| format!("{} is not valid UTF-8", file.display()))
+}
+
+/// The first line is the password. The following `key: value` (or `key:value`) lines hold
+/// the username, the URL, the TOTP secret (also as a bare `otpauth://` line, as written by
+/// pass-otp) or custom fields, and any other line is part of the description.
+fn parse_entry(name: String, folder: Option<String>, contents: &str) -> DecryptedEntry {
+    let mut lines = contents.lines();
+    let mut entry = DecryptedEntry {
+        name,
+        folder,
+        password: lines.next().unwrap_or_default().to_owned(),
+        ..Default::default()
+    };
+
+    let mut description = Vec::new();
+    for line in lines {
+        if line.trim().starts_with("otpauth://") && entry.totp.is_none() {
+            entry.totp = Some(line.trim().to_owned());
+            continue;
+        }
+        let Some((key, value)) = line
+            .split_once(':')
+            .filter(|(key, value)| is_field(key, value))
+        else {
+            description.push(line);
+            continue;
+        };
+        let value = value.trim().to_owned();
+        let field = match key.to_lowercase().as_str() {
+            "user" | "username" | "login" | "email" if entry.username.is_none() => {
+                &mut entry.username
+            }
+            "url" | "website" | "site" if entry.url.is_none() => &mut entry.url,
+            "otp" | "totp" if entry.totp.is_none() => &mut entry.totp,
+            _ => {
+                entry
+                    .custom_fields
+                    .push(CustomField::new(key.trim(), value, false));
+                continue;
+            }
+        };
+        *field = Some(value);
+    }
+
+    let description = description.join("\n").trim().to_owned();
+    entry.description = Some(description).filter(|d| !d.is_empty());
+    entry
+}
+
+/// Whether a line split at its first colon is a field. Without a space after the colon, the key
+/// must be a single word, and the value must not start with `//`, which leaves out URLs
+/// such as https://... and times such as 10:30.
+fn is_field(key: &str, value: &str) -> bool {
+    if key.trim().is_empty() {
+        return false;
+    }
+    if value.is_empty() || value.starts_with(char::is_whitespace) {
+        return true;
+    }
+    key.starts_with(|c: char| c.is_alphabetic())
+        && key
+            .chars()
+            .all(|c| c.is_alphanumeric() || c == '-' || c == '_')
+        && !value.starts_with("//")
+}
+
+#[cfg(test)]
+mod tests {
+    use sea_orm::prelude::Uuid;
+
+    use super::*;
+
+    fn parse(contents: &str) -> DecryptedEntry {
+        parse_entry("github".to_owned(), Some("work".to_owned()), contents)
+    }
+
+    #[test]
+    fn parse_entry_reads_a_password_only_file() {
+        assert_eq!(
+            parse("hunter2 with spaces \n"),
+            DecryptedEntry {
+                name: "github".to_owned(),
+                folder: Some("work".to_owned()),
+                password: "hunter2 with spaces ".to_owned(),
+                ..Default::default()
+            }
+        );
+        assert_eq!(parse("").password, "");
+    }
+
+    #[test]
+    fn parse_entry_reads_the_field_aliases() {
+        for (key, username, url) in [
+            ("user", true, false),
+            ("Username", true, false),
+            ("login", true, false),
+            ("email", true, false),
+            ("url", false, true),
+            ("URL", false, true),
+            ("website", false, true),
+            ("site", false, true),
+        ] {
+            let entry = parse(&format!("s3cret\n{}: value", key));
+            assert_eq!(entry.username.is_some(), username, "{}", key);
+            assert_eq!(entry.url.is_some(), url, "{}", key);
+            assert!(entry.custom_fields.is_empty(), "{}", key);
+        }
+
+        // The first one wins, the others are kept as custom fields
+        let entry = parse("s3cret\nuser: alice\nlogin: bob\nurl: https://github.com\nPIN: 1234");
+        assert_eq!(entry.username.as_deref(), Some("alice"));
+        assert_eq!(entry.url.as_deref(), Some("https://github.com"));
+        assert_eq!(
+            entry.custom_fields,
+            vec![
+                CustomField::new("login", "bob", false),
+                CustomField::new("PIN", "1234", false),
+            ]
+        );
+    }
+
+    #[test]
+    fn parse_entry_reads_fields_without_a_space() {
+        let entry = parse("s3cret\nuser:alice\nurl:https://github.com\nrecovery-code:ab12");
+        assert_eq!(entry.username.as_deref(), Some("alice"));
+        assert_eq!(entry.url.as_deref(), Some("https://github.com"));
+        assert_eq!(
+            entry.custom_fields,
+            vec![CustomField::new("recovery-code", "ab12", false)]
+        );
+        assert_eq!(entry.description, None);
+
+        // URLs, times and sentences are part of the description
+        let entry = parse("s3cret\nhttps://github.com/login\nRotated at 10:30\n10:30\nNote:");
+        assert_eq!(
+            entry.description.as_deref(),
+            Some("https://github.com/login\nRotated at 10:30\n10:30")
+        );
+        assert_eq!(
+            entry.custom_fields,
+            vec![CustomField::new("Note", "", false)]
+        );
+    }
+
+    #[test]
+    fn parse_entry_reads_otpauth_lines() {
+        let uri = "otpauth://totp/GitHub:alice?secret=JBSWY3DPEHPK3PXP&issuer=GitHub";
+        let entry = parse(&format!("s3cret\n{}\nuser: alice", uri));
+        assert_eq!(entry.totp.as_deref(), Some(uri));
+        assert_eq!(entry.username.as_deref(), Some("alice"));
+        assert_eq!(entry.description, None);
+
+        let entry = parse("s3cret\notp: JBSWY3DPEHPK3PXP");
+        assert_eq!(entry.totp.as_deref(), Some("JBSWY3DPEHPK3PXP"));
+        // A second secret is kept in the description
+        let entry = parse(&format!("s3cret\ntotp: JBSWY3DPEHPK3PXP\n{}", uri));
+        assert_eq!(entry.totp.as_deref(), Some("JBSWY3DPEHPK3PXP"));
+        assert_eq!(entry.description.as_deref(), Some(uri));
+    }
+
+    #[test]
+    fn find_gpg_files_skips_linked_directories() {
+        let root = std::env::temp_dir().join(format!("mypass-{}", Uuid::new_v4()));
+        fs::create_dir_all(root.join("work/.git")).unwrap();
+        fs::write(root.join("github.gpg"), "").unwrap();
+        fs::write(root.join(".gpg-id"), "").unwrap();
+        fs::write(root.join("work/db.gpg"), "").unwrap();
+        fs::write(root.join("work/notes.txt"), "").unwrap();
+        fs::write(root.join("work/.git/config.gpg"), "").unwrap();
+        // A loop back to the store, and a linked file
+        std::os::unix::fs::symlink(&root, root.join("work/loop")).unwrap();
+        std::os::unix::fs::symlink(root.join("github.gpg"), root.join("work/gh.gpg")).unwrap();
+
+        let mut files = Vec::new();
+        find_gpg_files(&root, &mut files).unwrap();
+        files.sort();
+        fs::remove_dir_all(&root).ok();
+        assert_eq!(
+            files,
+            vec![
+                root.join("github.gpg"),
+                root.join("work/db.gpg"),
+                root.join("work/gh.gpg"),
+            ]
+        );
+    }
+}
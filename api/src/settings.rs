@@ -0,0 +1,363 @@
+use rand::{seq::SliceRandom, Rng};
+use serde::{Deserialize, Serialize};
+use toml::{Table, Value};
+
+use crate::{container, error::Error};
+
+/// Environment variables named `MYPASS_<SECTION>_<KEY>` (e.g. `MYPASS_GENERATOR_LENGTH`)
+/// override the settings of the configuration file
+pub const ENV_PREFIX: &str = "MYPASS_";
+
+/// The settings of the configuration file. Every setting has a default, so the file only
+/// needs the settings that differ from it.
+#[derive(Debug, Clone, Default, PartialEq, Serialize, Deserialize)]
+#[serde(default)]
+pub struct Settings {
+    pub generator: GeneratorSettings,
+    pub entries: EntrySettings,
+    pub clipboard: ClipboardSettings,
+    pub lock: LockSettings,
+    pub output: OutputSettings,
+    pub kdf: KdfSettings,
+}
+
+/// The passwords generated for new entries
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(default)]
+pub struct GeneratorSettings {
+    pub length: u32,
+    pub lowercase: bool,
+    pub uppercase: bool,
+    pub digits: bool,
+    pub symbols: bool,
+}
+
+impl Default for GeneratorSettings {
+    fn default() -> Self {
+        GeneratorSettings {
+            length: 32,
+            lowercase: true,
+            uppercase: true,
+            digits: true,
+            symbols: false,
+        }
+    }
+}
+
+const LOWERCASE: &[u8] = b"abcdefghijklmnopqrstuvwxyz";
+const UPPERCASE: &[u8] = b"ABCDEFGHIJKLMNOPQRSTUVWXYZ";
+const DIGITS: &[u8] = b"0123456789";
+const SYMBOLS: &[u8] = b"!#$%&()*+,-./:;<=>?@[]^_{|}~";
+
+impl GeneratorSettings {
+    fn classes(&self) -> Vec<&'static [u8]> {
+        [
+            (self.lowercase, LOWERCASE),
+            (self.uppercase, UPPERCASE),
+            (self.digits, DIGITS),
+            (self.symbols, SYMBOLS),
+        ]
+        .into_iter()
+        .filter(|(enabled, _)| *enabled)
+        .map(|(_, class)| class)
+        .collect()
+    }
+
+    /// Generates a password with at least one character of each enabled class
+    pub fn generate(&self) -> String {
+        let classes = self.classes();
+        let alphabet = classes.concat();
+        let mut rng = rand::thread_rng();
+        let mut password: Vec<u8> = classes
+            .iter()
+            .map(|class| class[rng.gen_range(0..class.len())])
+            .collect();
+        while password.len() < self.length as usize {
+            password.push(alphabet[rng.gen_range(0..alphabet.len())]);
+        }
+        password.shuffle(&mut rng);
+        String::from_utf8(password).unwrap_or_default()
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(default)]
+pub struct EntrySettings {
+    /// The name of entries created or imported without one
+    pub default_name: String,
+}
+
+impl Default for EntrySettings {
+    fn default() -> Self {
+        EntrySettings {
+            default_name: "Untitled".to_owned(),
+        }
+    }
+}
+
+#[derive(Debug, Clone, Default, PartialEq, Serialize, Deserialize)]
+#[serde(default)]
+pub struct ClipboardSettings {
+    /// Seconds after which a copied value is cleared from the clipboard, 0 to keep it
+    pub clear_after: u64,
+}
+
+/// Minutes of inactivity after which an unlocked session locks itself
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(default)]
+pub struct LockSettings {
+    pub tui: u64,
+    pub server: u64,
+    pub native_host: u64,
+}
+
+impl Default for LockSettings {
+    fn default() -> Self {
+        LockSettings {
+            tui: 5,
+            server: 15,
+            native_host: 15,
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum OutputFormat {
+    #[default]
+    Table,
+    Json,
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(default)]
+pub struct OutputSettings {
+    /// How entries are listed
+    pub format: OutputFormat,
+    /// The color of tables, as `#rrggbb`
+    pub color: String,
+}
+
+impl Default for OutputSettings {
+    fn default() -> Self {
+        OutputSettings {
+            format: OutputFormat::Table,
+            color: "#88c0cd".to_owned(),
+        }
+    }
+}
+
+impl OutputSettings {
+    /// The red, green and blue components of the table color
+    pub fn rgb(&self) -> Result<(u8, u8, u8), Error> {
+        let err = || format!("Invalid color {}, expected #rrggbb", self.color);
+        let hex = self
+            .color
+            .strip_prefix('#')
+            .filter(|hex| hex.len() == 6 && hex.is_ascii())
+            .ok_or_else(err)?;
+        let component = |i: usize| u8::from_str_radix(&hex[i..i + 2], 16).map_err(|_| err());
+        Ok((component(0)?, component(2)?, component(4)?))
+    }
+}
+
+/// The Argon2id parameters of the master password hash and of encrypted exports.
+/// They are stored with the hash and the export, so changing them does not affect
+/// the existing ones.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(default)]
+pub struct KdfSettings {
+    pub memory_kib: u32,
+    pub iterations: u32,
+    pub parallelism: u32,
+}
+
+impl Default for KdfSettings {
+    fn default() -> Self {
+        KdfSettings {
+            memory_kib: 64 * 1024,
+            iterations: 3,
+            parallelism: 1,
+        }
+    }
+}
+
+impl Settings {
+    /// The keys of every setting, as `<section>.<key>`
+    pub fn keys() -> Vec<String> {
+        let defaults = Settings::default().to_table().unwrap_or_default();
+        let mut keys = Vec::new();
+        for (section, values) in defaults {
+            if let Value::Table(values) = values {
+                keys.extend(values.keys().map(|key| format!("{}.{}", section, key)));
+            }
+        }
+        keys
+    }
+
+    pub fn get(&self, key: &str) -> Result<String, Error> {
+        let (section, name) = split_key(key)?;
+        let table = self.to_table()?;
+        match table.get(section).and_then(|s| s.get(name)) {
+            Some(Value::String(value)) => Ok(value.to_owned()),
+            Some(value) => Ok(value.to_string()),
+            None => Err(unknown_key(key)),
+        }
+    }
+
+    /// Sets a setting from its text value, which is parsed as the type of the setting.
+    /// The settings should be validated once every setting is set.
+    pub fn set(&mut self, key: &str, value: &str) -> Result<(), Error> {
+        let (section, name) = split_key(key)?;
+        let mut table = self.to_table()?;
+        let current = table
+            .get_mut(section)
+            .and_then(|s| s.get_mut(name))
+            .ok_or_else(|| unknown_key(key))?;
+        let invalid =
+            |kind: &str| format!("Invalid value {} for {}, expected {}", value, key, kind);
+        *current = match current {
+            Value::Integer(_) => Value::Integer(
+                value
+                    .trim()
+                    .parse::<u32>()
+                    .map_err(|_| invalid("a positive number"))?
+                    .into(),
+            ),
+            Value::Boolean(_) => Value::Boolean(
+                value
+                    .trim()
+                    .parse::<bool>()
+                    .map_err(|_| invalid("true or false"))?,
+            ),
+            _ => Value::String(value.to_owned()),
+        };
+        let settings: Settings = Value::Table(table)
+            .try_into()
+            .map_err(|e: toml::de::Error| {
+                format!("Invalid value {} for {}: {}", value, key, e.message())
+            })?;
+        *self = settings;
+        Ok(())
+    }
+
+    /// Applies the `MYPASS_<SECTION>_<KEY>` environment variables
+    pub fn apply_env(&mut self) -> Result<(), Error> {
+        for key in Settings::keys() {
+            let var = format!("{}{}", ENV_PREFIX, key.replace('.', "_").to_uppercase());
+            if let Ok(value) = std::env::var(&var) {
+                self.set(&key, &value)
+                    .map_err(|e| format!("{} (from {})", e, var))?;
+            }
+        }
+        Ok(())
+    }
+
+    pub fn validate(&self) -> Result<(), Error> {
+        let generator = &self.generator;
+        if generator.classes().is_empty() {
+            return Err("The generator needs at least one kind of character".to_owned());
+        }
+        if !(8..=1024).contains(&generator.length) {
+            return Err("The generator length must be between 8 and 1024".to_owned());
+        }
+        if self.entries.default_name.trim().is_empty() {
+            return Err("The default entry name cannot be empty".to_owned());
+        }
+        if self.lock.tui == 0 || self.lock.server == 0 || self.lock.native_host == 0 {
+            return Err("Lock timeouts must be at least 1 minute".to_owned());
+        }
+        self.output.rgb()?;
+        let kdf = &self.kdf;
+        if !(1..=container::MAX_ITERATIONS).contains(&kdf.iterations) {
+            return Err(format!(
+                "KDF iterations must be between 1 and {}",
+                container::MAX_ITERATIONS
+            ));
+        }
+        if !(1..=16).contains(&kdf.parallelism) {
+            return Err("KDF parallelism must be between 1 and 16".to_owned());
+        }
+        if kdf.memory_kib < 8 * 1024 || kdf.memory_kib > container::MAX_MEMORY_KIB {
+            return Err(format!(
+                "KDF memory must be between 8192 and {} KiB",
+                container::MAX_MEMORY_KIB
+            ));
+        }
+        Ok(())
+    }
+
+    fn to_table(&self) -> Result<Table, Error> {
+        Table::try_from(self).map_err(|_| "Failed to serialize the settings".to_owned())
+    }
+}
+
+fn split_key(key: &str) -> Result<(&str, &str), Error> {
+    key.split_once('.').ok_or_else(|| unknown_key(key))
+}
+
+fn unknown_key(key: &str) -> Error {
+    format!(
+        "Unknown setting {}, expected one of {}",
+        key,
+        Settings::keys().join(", ")
+    )
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn set_parses_the_value_as_the_type_of_the_setting() {
+        let mut settings = Settings::default();
+        settings.set("generator.length", " 20 ").unwrap();
+        settings.set("generator.symbols", "true").unwrap();
+        settings.set("entries.default_name", "New entry").unwrap();
+        assert_eq!(settings.generator.length, 20);
+        assert!(settings.generator.symbols);
+        assert_eq!(settings.get("entries.default_name").unwrap(), "New entry");
+        assert_eq!(settings.get("generator.length").unwrap(), "20");
+
+        assert!(settings.set("generator.length", "long").is_err());
+        assert!(settings.set("generator.symbols", "yes").is_err());
+        assert!(settings.set("generator.size", "20").is_err());
+        assert!(settings.set("length", "20").is_err());
+        assert_eq!(settings.generator.length, 20);
+    }
+
+    #[test]
+    fn validate_rejects_unusable_settings() {
+        assert!(Settings::default().validate().is_ok());
+        let invalid = |change: fn(&mut Settings)| {
+            let mut settings = Settings::default();
+            change(&mut settings);
+            settings.validate().is_err()
+        };
+        assert!(invalid(|s| s.generator.length = 4));
+        assert!(invalid(|s| {
+            s.generator.lowercase = false;
+            s.generator.uppercase = false;
+            s.generator.digits = false;
+        }));
+        assert!(invalid(|s| s.entries.default_name = " ".to_owned()));
+        assert!(invalid(|s| s.lock.tui = 0));
+        assert!(invalid(|s| s.output.color = "blue".to_owned()));
+        assert!(invalid(|s| s.kdf.memory_kib = 1024));
+        assert!(invalid(|s| s.kdf.parallelism = 0));
+    }
+
+    #[test]
+    fn generated_passwords_have_every_enabled_class() {
+        let generator = GeneratorSettings {
+            length: 12,
+            symbols: true,
+            ..GeneratorSettings::default()
+        };
+        let password = generator.generate();
+        assert_eq!(password.len(), 12);
+        for class in generator.classes() {
+            assert!(password.bytes().any(|c| class.contains(&c)));
+        }
+    }
+}
@@ -0,0 +1,181 @@
+use std::{path::Path, sync::RwLock};
+
+use async_trait::async_trait;
+use model::entities::prelude::{CustomField, Entry, Master};
+use model::entities::{custom_field, entry, master};
+use sea_orm::{
+    ActiveModelTrait, ColumnTrait, ConnectionTrait, DatabaseConnection, EntityTrait,
+    IntoActiveModel, QueryFilter, QueryOrder, TransactionTrait,
+};
+
+use crate::{
+    configuration,
+    error::Error,
+    migrations,
+    settings::Settings,
+    storage::{Change, Storage},
+};
+
+/// A SQLite data store, through sea-orm
+pub struct SqliteStorage {
+    conn: DatabaseConnection,
+    /// The settings of a data store opened on its own, instead of those of the configuration file
+    settings: Option<RwLock<Settings>>,
+}
+
+impl SqliteStorage {
+    /// The data store of the vault in use, with the settings of the configuration file
+    pub async fn current() -> Result<SqliteStorage, Error> {
+        Ok(SqliteStorage {
+            conn: configuration::connect().await?,
+            settings: None,
+        })
+    }
+
+    /// Opens a data store, which is created when missing and migrated when it is older.
+    /// Its settings are the defaults, kept for as long as the storage.
+    pub async fn open(path: &Path) -> Result<SqliteStorage, Error> {
+        let conn = configuration::connect_without_migrating(path).await?;
+        migrations::migrate(&conn, path).await?;
+        Ok(SqliteStorage {
+            conn,
+            settings: Some(RwLock::new(Settings::default())),
+        })
+    }
+
+    pub fn connection(&self) -> &DatabaseConnection {
+        &self.conn
+    }
+}
+
+async fn apply_change<C: ConnectionTrait>(con: &C, change: Change) -> Result<(), Error> {
+    match change {
+        Change::InsertEntry(entry) => {
+            entry
+                .into_active_model()
+                .reset_all()
+                .insert(con)
+                .await
+                .map_err(|_| "Failed to create a new entry".to_owned())?;
+        }
+        Change::UpdateEntry(entry) => {
+            entry
+                .into_active_model()
+                .reset_all()
+                .update(con)
+                .await
+                .map_err(|_| "Failed to update entry".to_owned())?;
+        }
+        Change::DeleteEntry(entry_id) => {
+            let err = "Failed to delete entry";
+            delete_custom_fields(con, &entry_id).await?;
+            let result = Entry::delete_by_id(entry_id)
+                .exec(con)
+                .await
+                .map_err(|_| err)?;
+            if result.rows_affected == 0 {
+                return Err(err.to_owned());
+            }
+        }
+        Change::SetCustomFields(entry_id, fields) => {
+            delete_custom_fields(con, &entry_id).await?;
+            for field in fields {
+                field
+                    .into_active_model()
+                    .reset_all()
+                    .insert(con)
+                    .await
+                    .map_err(|_| "Failed to update custom fields".to_owned())?;
+            }
+        }
+    }
+    Ok(())
+}
+
+async fn delete_custom_fields<C: ConnectionTrait>(con: &C, entry_id: &str) -> Result<(), Error> {
+    CustomField::delete_many()
+        .filter(custom_field::Column::EntryId.eq(entry_id))
+        .exec(con)
+        .await
+        .map_err(|_| "Failed to update custom fields".to_owned())
+        .map(|_| ())
+}
+
+#[async_trait]
+impl Storage for SqliteStorage {
+    async fn get_master(&self) -> Result<Option<master::Model>, Error> {
+        Master::find()
+            .all(&self.conn)
+            .await
+            .map_err(|_| "Failed to get master".to_owned())
+            .map(|entries| entries.first().map(|t| t.to_owned()))
+    }
+
+    async fn insert_master(&self, master: master::Model) -> Result<(), Error> {
+        master
+            .into_active_model()
+            .reset_all()
+            .insert(&self.conn)
+            .await
+            .map_err(|e| e.to_string())
+            .map(|_| ())
+    }
+
+    async fn get_all_entries(&self) -> Result<Vec<entry::Model>, Error> {
+        Entry::find()
+            .all(&self.conn)
+            .await
+            .map_err(|_| "Failed to get all entries".to_owned())
+    }
+
+    async fn get_entry(&self, entry_id: &str) -> Result<Option<entry::Model>, Error> {
+        Entry::find_by_id(entry_id)
+            .one(&self.conn)
+            .await
+            .map_err(|_| "Failed to fetch entry".to_owned())
+    }
+
+    async fn get_custom_fields(&self, entry_id: &str) -> Result<Vec<custom_field::Model>, Error> {
+        CustomField::find()
+            .filter(custom_field::Column::EntryId.eq(entry_id))
+            .order_by_asc(custom_field::Column::Name)
+            .all(&self.conn)
+            .await
+            .map_err(|_| "Failed to get custom fields".to_owned())
+    }
+
+    /// The changes are applied in a single transaction
+    async fn apply(&self, changes: Vec<Change>) -> Result<(), Error> {
+        let err = "Failed to save the changes";
+        let txn = self.conn.begin().await.map_err(|_| err)?;
+        for change in changes {
+            // The transaction is rolled back when dropped
+            apply_change(&txn, change).await?;
+        }
+        txn.commit().await.map_err(|_| err.to_owned())
+    }
+
+    async fn get_settings(&self) -> Result<Settings, Error> {
+        match &self.settings {
+            Some(settings) => settings
+                .read()
+                .map(|settings| settings.to_owned())
+                .map_err(|_| "Failed to read the settings".to_owned()),
+            None => configuration::get_settings(),
+        }
+    }
+
+    async fn set_settings(&self, new_settings: Settings) -> Result<(), Error> {
+        new_settings.validate()?;
+        match &self.settings {
+            Some(settings) => {
+                let mut settings = settings
+                    .write()
+                    .map_err(|_| "Failed to write the settings".to_owned())?;
+                *settings = new_settings;
+                Ok(())
+            }
+            None => configuration::set_settings(new_settings),
+        }
+    }
+}
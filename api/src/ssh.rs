@@ -0,0 +1,77 @@
+use model::entities::{entry, master};
+use signature::{SignatureEncoding, Signer};
+use ssh_key::{private::KeypairData, sha2::Sha256, Algorithm, HashAlg, PrivateKey, Signature};
+
+use crate::{crypto, error::Error, vault::Vault};
+
+/// Parses an OpenSSH private key. The key may still be encrypted with a passphrase.
+pub fn parse_private_key(pem: &str) -> Result<PrivateKey, Error> {
+    PrivateKey::from_openssh(pem.trim()).map_err(|e| format!("Invalid OpenSSH private key: {}", e))
+}
+
+/// Derives the public key (in OpenSSH `authorized_keys` format) stored alongside a private key
+pub fn public_key_of(key: &PrivateKey) -> Result<String, Error> {
+    key.public_key()
+        .to_openssh()
+        .map_err(|_| "Failed to encode the SSH public key".to_owned())
+}
+
+/// Stores an SSH key as a new entry. The private key is kept (without a passphrase)
+/// as the encrypted password of the entry, and the public key is stored in the clear
+/// so that the agent can list identities while the vault is locked.
+pub async fn create_ssh_key(
+    master_password: String,
+    name: String,
+    description: Option<String>,
+    key: &PrivateKey,
+) -> Result<entry::Model, Error> {
+    Vault::open_current_unlocked(master_password)
+        .await?
+        .create_ssh_key(name, description, key)
+        .await
+}
+
+/// All entries that hold an SSH key
+pub async fn get_ssh_keys() -> Result<Vec<entry::Model>, Error> {
+    Vault::open_current().await?.get_ssh_keys().await
+}
+
+/// Decrypts the private key of an SSH key entry
+pub fn decrypt_ssh_key(
+    entry: &entry::Model,
+    master: &master::Model,
+    master_password: String,
+) -> Result<PrivateKey, Error> {
+    if entry.ssh_public_key.is_none() {
+        return Err(format!("{} is not an SSH key", entry.name));
+    }
+    let pem = crypto::decrypt_password(
+        master_password,
+        entry.password.to_owned(),
+        entry.id.to_owned(),
+        master.id.to_owned(),
+    )?;
+    parse_private_key(&pem)
+}
+
+/// Signs `data` with a decrypted private key. RSA keys sign with SHA-512 unless
+/// `rsa_hash` requests SHA-256 (`rsa-sha2-256`).
+pub fn sign(key: &PrivateKey, data: &[u8], rsa_hash: HashAlg) -> Result<Signature, Error> {
+    let err = "Failed to sign with the SSH key";
+    match (key.key_data(), rsa_hash) {
+        (KeypairData::Rsa(keypair), HashAlg::Sha256) => {
+            let signature = rsa::pkcs1v15::SigningKey::<Sha256>::try_from(keypair)
+                .map_err(|_| err)?
+                .try_sign(data)
+                .map_err(|_| err)?;
+            Signature::new(
+                Algorithm::Rsa {
+                    hash: Some(HashAlg::Sha256),
+                },
+                signature.to_vec(),
+            )
+            .map_err(|_| err.to_owned())
+        }
+        _ => key.try_sign(data).map_err(|_| err.to_owned()),
+    }
+}
@@ -0,0 +1,165 @@
+use async_trait::async_trait;
+use model::entities::{custom_field, entry, master};
+
+use crate::{error::Error, settings::Settings};
+
+/// A change to the entries, applied with others by `Storage::apply`
+#[derive(Debug, Clone)]
+pub enum Change {
+    InsertEntry(entry::Model),
+    UpdateEntry(entry::Model),
+    /// Deletes an entry and its custom fields
+    DeleteEntry(String),
+    /// Replaces the custom fields of an entry
+    SetCustomFields(String, Vec<custom_field::Model>),
+}
+
+/// Where a vault keeps its master, entries and settings. Values are stored as given, the
+/// encryption is done by the `Vault`.
+///
+/// `SqliteStorage` is the data store of the command line, and `MemoryStorage` keeps a vault
+/// in memory for tests and ephemeral vaults.
+#[async_trait]
+pub trait Storage: Send + Sync {
+    async fn get_master(&self) -> Result<Option<master::Model>, Error>;
+
+    async fn insert_master(&self, master: master::Model) -> Result<(), Error>;
+
+    /// The entries, in the order in which they are numbered
+    async fn get_all_entries(&self) -> Result<Vec<entry::Model>, Error>;
+
+    async fn get_entry(&self, entry_id: &str) -> Result<Option<entry::Model>, Error>;
+
+    /// The custom fields of an entry, by name
+    async fn get_custom_fields(&self, entry_id: &str) -> Result<Vec<custom_field::Model>, Error>;
+
+    /// Applies the changes in order, either all of them or none
+    async fn apply(&self, changes: Vec<Change>) -> Result<(), Error>;
+
+    async fn get_settings(&self) -> Result<Settings, Error>;
+
+    async fn set_settings(&self, settings: Settings) -> Result<(), Error>;
+}
+
+#[cfg(test)]
+mod tests {
+    use sea_orm::prelude::Uuid;
+
+    use super::*;
+    use crate::{memory_storage::MemoryStorage, sqlite_storage::SqliteStorage, util};
+
+    fn entry(name: &str) -> entry::Model {
+        entry::Model {
+            id: Uuid::new_v4().to_string(),
+            name: name.to_owned(),
+            description: None,
+            url: None,
+            username: None,
+            password: vec![1, 2, 3],
+            created_date: util::now(),
+            modified_date: util::now(),
+            totp: None,
+            ssh_public_key: None,
+            folder: None,
+            tags: None,
+        }
+    }
+
+    fn field(entry_id: &str, name: &str) -> custom_field::Model {
+        custom_field::Model {
+            id: Uuid::new_v4().to_string(),
+            entry_id: entry_id.to_owned(),
+            name: name.to_owned(),
+            value: vec![4, 5, 6],
+            protected: false,
+        }
+    }
+
+    async fn apply_is_all_or_nothing(storage: &dyn Storage) {
+        let first = entry("first");
+        storage
+            .apply(vec![Change::InsertEntry(first.to_owned())])
+            .await
+            .unwrap();
+
+        let second = entry("second");
+        let result = storage
+            .apply(vec![
+                Change::InsertEntry(second.to_owned()),
+                Change::UpdateEntry(entry("missing")),
+            ])
+            .await;
+        assert!(result.is_err());
+        assert_eq!(storage.get_all_entries().await.unwrap(), vec![first]);
+        assert_eq!(storage.get_entry(&second.id).await.unwrap(), None);
+    }
+
+    async fn delete_entry_deletes_its_custom_fields(storage: &dyn Storage) {
+        let kept = entry("kept");
+        let deleted = entry("deleted");
+        let fields = vec![field(&deleted.id, "b"), field(&deleted.id, "a")];
+        storage
+            .apply(vec![
+                Change::InsertEntry(kept.to_owned()),
+                Change::InsertEntry(deleted.to_owned()),
+                Change::SetCustomFields(kept.id.to_owned(), vec![field(&kept.id, "c")]),
+                Change::SetCustomFields(deleted.id.to_owned(), fields),
+            ])
+            .await
+            .unwrap();
+        let names: Vec<String> = storage
+            .get_custom_fields(&deleted.id)
+            .await
+            .unwrap()
+            .into_iter()
+            .map(|f| f.name)
+            .collect();
+        assert_eq!(names, vec!["a", "b"]);
+
+        storage
+            .apply(vec![Change::DeleteEntry(deleted.id.to_owned())])
+            .await
+            .unwrap();
+        assert_eq!(storage.get_entry(&deleted.id).await.unwrap(), None);
+        assert_eq!(
+            storage.get_entry(&kept.id).await.unwrap(),
+            Some(kept.to_owned())
+        );
+        assert!(storage
+            .get_custom_fields(&deleted.id)
+            .await
+            .unwrap()
+            .is_empty());
+        assert_eq!(storage.get_custom_fields(&kept.id).await.unwrap().len(), 1);
+        assert!(storage
+            .apply(vec![Change::DeleteEntry(deleted.id)])
+            .await
+            .is_err());
+    }
+
+    async fn invalid_settings_are_rejected(storage: &dyn Storage) {
+        let mut settings = storage.get_settings().await.unwrap();
+        settings.generator.length = 4;
+        assert!(storage.set_settings(settings).await.is_err());
+        assert_eq!(storage.get_settings().await.unwrap().generator.length, 32);
+    }
+
+    async fn check_contract(storage: &dyn Storage) {
+        apply_is_all_or_nothing(storage).await;
+        delete_entry_deletes_its_custom_fields(storage).await;
+        invalid_settings_are_rejected(storage).await;
+    }
+
+    #[tokio::test]
+    async fn memory_storage_follows_the_contract() {
+        check_contract(&MemoryStorage::new()).await;
+    }
+
+    #[tokio::test]
+    async fn sqlite_storage_follows_the_contract() {
+        let path = std::env::temp_dir().join(format!("mypass-{}.sqlite", Uuid::new_v4()));
+        let storage = SqliteStorage::open(&path).await.unwrap();
+        check_contract(&storage).await;
+        std::fs::remove_file(path).ok();
+    }
+}
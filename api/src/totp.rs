@@ -0,0 +1,57 @@
+use std::time::{SystemTime, UNIX_EPOCH};
+
+use model::entities::{entry, master};
+use totp_rs::{Algorithm, Secret, TOTP};
+
+use crate::{crypto, error::Error};
+
+/// Parses a TOTP secret, given either as an `otpauth://` URL or as a base32 key
+/// (in which case the common defaults of SHA1, 6 digits and 30 seconds are used).
+pub fn parse(secret: &str) -> Result<TOTP, Error> {
+    let secret = secret.trim();
+    if secret.starts_with("otpauth://") {
+        return TOTP::from_url_unchecked(secret).map_err(|e| format!("Invalid TOTP URL: {}", e));
+    }
+
+    let key: String = secret
+        .chars()
+        .filter(|c| !c.is_whitespace() && *c != '-')
+        .collect::<String>()
+        .to_uppercase();
+    let bytes = Secret::Encoded(key)
+        .to_bytes()
+        .map_err(|_| "Invalid TOTP secret (expected a base32 key or an otpauth:// URL)")?;
+    Ok(TOTP::new_unchecked(
+        Algorithm::SHA1,
+        6,
+        1,
+        30,
+        bytes,
+        None,
+        String::new(),
+    ))
+}
+
+/// Generates the current code and the number of seconds it remains valid for
+pub fn generate_code(secret: &str) -> Result<(String, u64), Error> {
+    let totp = parse(secret)?;
+    let now = SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .map_err(|_| "Invalid system time")?
+        .as_secs();
+    let ttl = totp.step - (now % totp.step);
+    Ok((totp.generate(now), ttl))
+}
+
+/// Decrypts the TOTP secret of an entry, if it has one
+pub fn decrypt_totp(
+    entry: &entry::Model,
+    master: &master::Model,
+    master_password: String,
+) -> Result<Option<String>, Error> {
+    entry
+        .totp
+        .to_owned()
+        .map(|totp| crypto::decrypt_secret(master_password, totp, master.id.to_owned()))
+        .transpose()
+}
@@ -1,6 +1,8 @@
 use std::{env, fs, path};
 
-use chrono::{Local, NaiveDateTime};
+use chrono::{
+    DateTime, Local, NaiveDate, NaiveDateTime, SecondsFormat, SubsecRound, TimeDelta, TimeZone, Utc,
+};
 use url::Url;
 
 use crate::error::Error;
@@ -18,9 +20,74 @@ pub fn create_file(p: String) -> Result<bool, Error> {
     Ok(true)
 }
 
-pub fn now() -> String {
-    let created_date: NaiveDateTime = Local::now().naive_local();
-    created_date.format("%Y-%m-%d %H:%M:%S").to_string()
+/// The current time, to the second
+pub fn now() -> DateTime<Utc> {
+    Utc::now().trunc_subsecs(0)
+}
+
+/// A date as exchanged with other programs, e.g. `2024-01-31T12:00:00Z`
+pub fn format_date(date: &DateTime<Utc>) -> String {
+    date.to_rfc3339_opts(SecondsFormat::Secs, true)
+}
+
+/// A date in the local time, as shown to the user
+pub fn format_local_date(date: &DateTime<Utc>) -> String {
+    date.with_timezone(&Local)
+        .format("%Y-%m-%d %H:%M:%S")
+        .to_string()
+}
+
+/// Reads a date with its offset (RFC 3339), or a local date and time (`2024-01-31 12:00:00`)
+/// or day (`2024-01-31`) as written by earlier versions and most password managers
+pub fn parse_date(date: &str) -> Result<DateTime<Utc>, Error> {
+    let date = date.trim();
+    if let Ok(date) = DateTime::parse_from_rfc3339(date) {
+        return Ok(date.with_timezone(&Utc));
+    }
+    let local = NaiveDateTime::parse_from_str(date, "%Y-%m-%d %H:%M:%S")
+        .or_else(|_| NaiveDateTime::parse_from_str(date, "%Y-%m-%dT%H:%M:%S"))
+        .ok()
+        .or_else(|| {
+            NaiveDate::parse_from_str(date, "%Y-%m-%d")
+                .ok()
+                .and_then(|day| day.and_hms_opt(0, 0, 0))
+        })
+        .ok_or(format!(
+            "Invalid date {}, expected e.g. 2024-01-31 or 2024-01-31T12:00:00Z",
+            date
+        ))?;
+    Local
+        .from_local_datetime(&local)
+        .earliest()
+        .map(|date| date.with_timezone(&Utc))
+        .ok_or(format!("Invalid local date {}", date))
+}
+
+/// Reads a point in time, either a date (see `parse_date`) or an age from now such as
+/// `90d` (with `m`, `h`, `d`, `w` or `y` for minutes, hours, days, weeks or years)
+pub fn parse_time(time: &str) -> Result<DateTime<Utc>, Error> {
+    let time = time.trim();
+    let unit = time.chars().last().unwrap_or_default();
+    let amount = time[..time.len() - unit.len_utf8().min(time.len())].parse::<u32>();
+    let age = match (amount, unit) {
+        (Ok(n), 'm') => Some(TimeDelta::try_minutes(n.into())),
+        (Ok(n), 'h') => Some(TimeDelta::try_hours(n.into())),
+        (Ok(n), 'd') => Some(TimeDelta::try_days(n.into())),
+        (Ok(n), 'w') => Some(TimeDelta::try_weeks(n.into())),
+        (Ok(n), 'y') => Some(TimeDelta::try_days(i64::from(n) * 365)),
+        _ => None,
+    };
+    match age {
+        Some(age) => age
+            .and_then(|age| Utc::now().checked_sub_signed(age))
+            .ok_or(format!("Invalid time {}, the age is too large", time)),
+        None => parse_date(time).map_err(|_| {
+            format!(
+                "Invalid time {}, expected a date (e.g. 2024-01-31) or an age (e.g. 90d)",
+                time
+            )
+        }),
+    }
 }
 
 pub fn validate_url(url: String) -> Result<(), Error> {
@@ -31,3 +98,28 @@ pub fn validate_url(url: String) -> Result<(), Error> {
 pub fn get_home_env_var() -> Result<String, Error> {
     env::var("HOME").map_err(|_| "HOME environment variable not set.".to_owned())
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn parse_time_reads_ages_and_dates() {
+        let before = Utc::now();
+        let time = parse_time("30d").unwrap();
+        assert!(time <= before - TimeDelta::days(30) + TimeDelta::seconds(1));
+        assert!(time >= before - TimeDelta::days(30) - TimeDelta::seconds(5));
+        assert_eq!(
+            parse_time("2024-01-31T12:00:00Z").unwrap(),
+            Utc.with_ymd_and_hms(2024, 1, 31, 12, 0, 0).unwrap()
+        );
+        assert!(parse_time("soon").is_err());
+    }
+
+    #[test]
+    fn parse_time_rejects_ages_that_overflow() {
+        assert!(parse_time("999999999d").is_err());
+        assert!(parse_time("300000y").is_err());
+        assert!(parse_time("4294967295w").is_err());
+    }
+}
@@ -0,0 +1,551 @@
+use std::{path::Path, sync::Arc};
+
+use model::entities::{custom_field, entry, master};
+use sea_orm::prelude::Uuid;
+use ssh_key::{LineEnding, PrivateKey};
+
+use crate::{
+    crypto,
+    custom_fields::{self, CustomField},
+    entries::{self, join_tags, normalize_folder},
+    error::Error,
+    memory_storage::MemoryStorage,
+    settings::Settings,
+    sqlite_storage::SqliteStorage,
+    ssh,
+    storage::{Change, Storage},
+    totp, util,
+};
+
+/// A storage and, once unlocked, the key to its entries.
+///
+/// Every operation of the command line goes through a vault, which can also be used on its own
+/// to embed MyPass: `Vault::open` only needs the path to a data store, and does not read the
+/// configuration file or depend on the vault selected for the process.
+pub struct Vault {
+    storage: Arc<dyn Storage>,
+    key: Option<UnlockedKey>,
+}
+
+/// The master and its password, which encrypts the entries
+struct UnlockedKey {
+    master: master::Model,
+    password: String,
+}
+
+impl Vault {
+    /// Opens a SQLite data store, see `SqliteStorage::open`
+    pub async fn open(path: impl AsRef<Path>) -> Result<Vault, Error> {
+        Ok(Vault::new(SqliteStorage::open(path.as_ref()).await?))
+    }
+
+    /// Opens the data store of the vault in use (see `configuration::current_vault`),
+    /// sharing the connection of the process
+    pub async fn open_current() -> Result<Vault, Error> {
+        Ok(Vault::new(SqliteStorage::current().await?))
+    }
+
+    /// An empty vault that only lives in memory
+    pub fn in_memory() -> Vault {
+        Vault::new(MemoryStorage::new())
+    }
+
+    /// The vault in use, unlocked with a master password that the caller already authenticated
+    pub(crate) async fn open_current_unlocked(master_password: String) -> Result<Vault, Error> {
+        let mut vault = Vault::open_current().await?;
+        let master = vault.require_master().await?;
+        vault.key = Some(UnlockedKey {
+            master,
+            password: master_password,
+        });
+        Ok(vault)
+    }
+
+    pub fn new(storage: impl Storage + 'static) -> Vault {
+        Vault::from_storage(Arc::new(storage))
+    }
+
+    /// A vault on a storage shared with other vaults
+    pub fn from_storage(storage: Arc<dyn Storage>) -> Vault {
+        Vault { storage, key: None }
+    }
+
+    pub fn storage(&self) -> &Arc<dyn Storage> {
+        &self.storage
+    }
+
+    pub async fn get_settings(&self) -> Result<Settings, Error> {
+        self.storage.get_settings().await
+    }
+
+    pub async fn set_settings(&self, settings: Settings) -> Result<(), Error> {
+        self.storage.set_settings(settings).await
+    }
+
+    pub async fn get_master(&self) -> Result<Option<master::Model>, Error> {
+        self.storage.get_master().await
+    }
+
+    pub async fn require_master(&self) -> Result<master::Model, Error> {
+        self.get_master()
+            .await?
+            .ok_or("Master not configured. Please create a master key".to_owned())
+    }
+
+    pub async fn is_master_configured(&self) -> Result<bool, Error> {
+        Ok(self.get_master().await?.is_some())
+    }
+
+    /// Creates the master, whose password is hashed with the KDF parameters of the settings
+    pub async fn create_master(&self, password: String) -> Result<master::Model, Error> {
+        if self.get_master().await?.is_some() {
+            return Err("Master is already configured".to_owned());
+        }
+        let kdf = self.get_settings().await?.kdf;
+        let hashed_password = crypto::hash_password(password, &kdf)?;
+        let master = master::Model {
+            id: Uuid::new_v4().to_string(),
+            name: "Main Master (Default)".to_owned(),
+            description: Some("Master key to access your passwords".to_owned()),
+            password: hashed_password,
+        };
+        self.storage.insert_master(master.to_owned()).await?;
+        Ok(master)
+    }
+
+    /// Verifies the master password without unlocking the vault
+    pub async fn authenticate(&self, master_password: String) -> Result<master::Model, Error> {
+        let master = self.require_master().await?;
+        crypto::verify_password(master_password, master.password.to_owned()).and_then(
+            |is_authenticated| {
+                if is_authenticated {
+                    Ok(master)
+                } else {
+                    Err("Invalid master password".to_owned())
+                }
+            },
+        )
+    }
+
+    /// Verifies the master password and keeps it to encrypt and decrypt the entries
+    pub async fn unlock(&mut self, master_password: String) -> Result<&master::Model, Error> {
+        let master = self.authenticate(master_password.to_owned()).await?;
+        let key = self.key.insert(UnlockedKey {
+            master,
+            password: master_password,
+        });
+        Ok(&key.master)
+    }
+
+    /// Forgets the master password
+    pub fn lock(&mut self) {
+        self.key = None;
+    }
+
+    pub fn is_unlocked(&self) -> bool {
+        self.key.is_some()
+    }
+
+    fn key(&self) -> Result<&UnlockedKey, Error> {
+        self.key
+            .as_ref()
+            .ok_or("The vault is locked, unlock it with the master password".to_owned())
+    }
+
+    /// The master and its password, when the vault is unlocked
+    pub(crate) fn unlocked(&self) -> Result<(&master::Model, String), Error> {
+        let key = self.key()?;
+        Ok((&key.master, key.password.to_owned()))
+    }
+
+    pub async fn get_all_entries(&self) -> Result<Vec<entry::Model>, Error> {
+        self.storage.get_all_entries().await
+    }
+
+    pub async fn get_entry(&self, entry_id: String) -> Result<entry::Model, Error> {
+        self.storage
+            .get_entry(&entry_id)
+            .await?
+            .ok_or("Failed to fetch entry".to_owned())
+    }
+
+    async fn find_entry_to_modify(&self, entry_id: String) -> Result<entry::Model, Error> {
+        let err = "Error modifying entry";
+        self.storage
+            .get_entry(&entry_id)
+            .await
+            .map_err(|_| err)?
+            .ok_or(err.to_owned())
+    }
+
+    async fn save_entry(&self, entry: entry::Model) -> Result<entry::Model, Error> {
+        self.storage
+            .apply(vec![Change::UpdateEntry(entry.to_owned())])
+            .await?;
+        Ok(entry)
+    }
+
+    /// Creates an entry, with its password encrypted (requires the vault to be unlocked)
+    pub async fn create_entry(
+        &self,
+        name: String,
+        description: Option<String>,
+        username: Option<String>,
+        password: String,
+        url: Option<String>,
+    ) -> Result<entry::Model, Error> {
+        let key = self.key()?;
+        let ent = entries::new_entry(
+            key.password.to_owned(),
+            &key.master,
+            name,
+            description,
+            username,
+            password,
+            url,
+        )?;
+        self.storage
+            .apply(vec![Change::InsertEntry(ent.to_owned())])
+            .await?;
+        Ok(ent)
+    }
+
+    /// Updates the given fields of an entry. An empty description, username or URL clears it.
+    /// A new password requires the vault to be unlocked.
+    pub async fn update_entry(
+        &self,
+        entry_id: String,
+        name: Option<String>,
+        description: Option<String>,
+        username: Option<String>,
+        url: Option<String>,
+        password: Option<String>,
+    ) -> Result<entry::Model, Error> {
+        let mut entry = self.find_entry_to_modify(entry_id.to_owned()).await?;
+        let is_ssh_key = entry.ssh_public_key.is_some();
+
+        let mut is_modified = false;
+
+        if let Some(name) = name {
+            entry.name = name;
+            is_modified = true;
+        }
+
+        if let Some(description) = description {
+            entry.description = Some(description).filter(|d| !d.is_empty());
+            is_modified = true;
+        }
+
+        if let Some(username) = username {
+            entry.username = Some(username).filter(|u| !u.is_empty());
+            is_modified = true;
+        }
+
+        if let Some(url) = url {
+            if !url.is_empty() {
+                util::validate_url(url.to_owned())?;
+            }
+            entry.url = Some(url).filter(|u| !u.is_empty());
+            is_modified = true;
+        }
+
+        if let Some(new_password) = password {
+            // The password of an SSH key entry is the private key, so keep the public key in sync
+            if is_ssh_key {
+                let key = ssh::parse_private_key(&new_password)?;
+                if key.is_encrypted() {
+                    return Err("The SSH key must be decrypted before it is stored".to_owned());
+                }
+                entry.ssh_public_key = Some(ssh::public_key_of(&key)?);
+            }
+            let key = self.key()?;
+            let new_encrypted_password: Vec<u8> = crypto::encrypt_password(
+                key.password.to_owned(),
+                new_password.to_owned(),
+                entry_id,
+                key.master.id.to_owned(),
+            )?;
+            entry.password = new_encrypted_password;
+        }
+
+        if is_modified {
+            entry.modified_date = util::now();
+        }
+
+        self.save_entry(entry).await
+    }
+
+    /// Sets (or clears, when `totp` is `None`) the TOTP secret of an entry.
+    /// The secret may be a base32 key or an `otpauth://` URL.
+    pub async fn set_totp(
+        &self,
+        entry_id: String,
+        totp: Option<String>,
+    ) -> Result<entry::Model, Error> {
+        let mut entry = self.find_entry_to_modify(entry_id).await?;
+        let encrypted_totp = match totp {
+            Some(totp) => {
+                totp::parse(&totp)?;
+                let key = self.key()?;
+                Some(crypto::encrypt_secret(
+                    key.password.to_owned(),
+                    totp,
+                    key.master.id.to_owned(),
+                )?)
+            }
+            None => None,
+        };
+        entry.totp = encrypted_totp;
+        entry.modified_date = util::now();
+        self.save_entry(entry).await
+    }
+
+    /// Moves an entry into a folder (a `/` separated path), or out of any folder when `None`
+    pub async fn set_folder(
+        &self,
+        entry_id: String,
+        folder: Option<String>,
+    ) -> Result<entry::Model, Error> {
+        let mut entry = self.find_entry_to_modify(entry_id).await?;
+        entry.folder = normalize_folder(folder);
+        entry.modified_date = util::now();
+        self.save_entry(entry).await
+    }
+
+    /// Replaces the tags of an entry
+    pub async fn set_tags(
+        &self,
+        entry_id: String,
+        tags: Vec<String>,
+    ) -> Result<entry::Model, Error> {
+        let mut entry = self.find_entry_to_modify(entry_id).await?;
+        entry.tags = join_tags(tags);
+        entry.modified_date = util::now();
+        self.save_entry(entry).await
+    }
+
+    pub async fn delete_entry(&self, entry_id: String) -> Result<(), Error> {
+        let err = "Failed to delete entry";
+        self.storage
+            .get_entry(&entry_id)
+            .await
+            .map_err(|_| err)?
+            .ok_or(err)?;
+        self.storage
+            .apply(vec![Change::DeleteEntry(entry_id)])
+            .await
+    }
+
+    /// Decrypts the password of an entry (requires the vault to be unlocked)
+    pub fn decrypt_password(&self, entry: &entry::Model) -> Result<String, Error> {
+        let key = self.key()?;
+        crypto::decrypt_password(
+            key.password.to_owned(),
+            entry.password.to_owned(),
+            entry.id.to_owned(),
+            key.master.id.to_owned(),
+        )
+    }
+
+    /// Decrypts the TOTP secret of an entry, if it has one (requires the vault to be unlocked)
+    pub fn decrypt_totp(&self, entry: &entry::Model) -> Result<Option<String>, Error> {
+        let key = self.key()?;
+        totp::decrypt_totp(entry, &key.master, key.password.to_owned())
+    }
+
+    pub async fn get_custom_fields(
+        &self,
+        entry_id: String,
+    ) -> Result<Vec<custom_field::Model>, Error> {
+        self.storage.get_custom_fields(&entry_id).await
+    }
+
+    /// The custom fields of an entry, decrypted (requires the vault to be unlocked)
+    pub async fn decrypt_custom_fields(&self, entry_id: String) -> Result<Vec<CustomField>, Error> {
+        let fields = self.get_custom_fields(entry_id).await?;
+        let key = self.key()?;
+        custom_fields::decrypt_custom_fields(fields, &key.master, key.password.to_owned())
+    }
+
+    /// Replaces the custom fields of an entry (requires the vault to be unlocked)
+    pub async fn set_custom_fields(
+        &self,
+        entry_id: String,
+        fields: Vec<CustomField>,
+    ) -> Result<(), Error> {
+        let key = self.key()?;
+        let fields = custom_fields::encrypt_custom_fields(
+            key.password.to_owned(),
+            &key.master,
+            entry_id.to_owned(),
+            fields,
+        )?;
+        self.storage
+            .apply(vec![Change::SetCustomFields(entry_id, fields)])
+            .await
+    }
+
+    /// Stores an SSH key as a new entry (requires the vault to be unlocked), see `ssh::create_ssh_key`
+    pub async fn create_ssh_key(
+        &self,
+        name: String,
+        description: Option<String>,
+        key: &PrivateKey,
+    ) -> Result<entry::Model, Error> {
+        if key.is_encrypted() {
+            return Err("The SSH key must be decrypted before it is stored".to_owned());
+        }
+        let public_key = ssh::public_key_of(key)?;
+        let private_key = key
+            .to_openssh(LineEnding::LF)
+            .map_err(|_| "Failed to encode the SSH private key")?
+            .to_string();
+
+        let entry = self
+            .create_entry(name, description, None, private_key, None)
+            .await?;
+        self.set_public_key(entry.id, Some(public_key)).await
+    }
+
+    pub(crate) async fn set_public_key(
+        &self,
+        entry_id: String,
+        public_key: Option<String>,
+    ) -> Result<entry::Model, Error> {
+        let mut entry = self.find_entry_to_modify(entry_id).await?;
+        entry.ssh_public_key = public_key;
+        self.save_entry(entry).await
+    }
+
+    /// All entries that hold an SSH key
+    pub async fn get_ssh_keys(&self) -> Result<Vec<entry::Model>, Error> {
+        let entries = self.get_all_entries().await?;
+        Ok(entries
+            .into_iter()
+            .filter(|entry| entry.ssh_public_key.is_some())
+            .collect())
+    }
+
+    /// Decrypts the private key of an SSH key entry (requires the vault to be unlocked)
+    pub fn decrypt_ssh_key(&self, entry: &entry::Model) -> Result<PrivateKey, Error> {
+        let key = self.key()?;
+        ssh::decrypt_ssh_key(entry, &key.master, key.password.to_owned())
+    }
+}
+
+/// An unlocked vault in memory, with cheap KDF parameters
+#[cfg(test)]
+pub(crate) async fn test_vault() -> Vault {
+    use crate::settings::KdfSettings;
+
+    let settings = Settings {
+        kdf: KdfSettings {
+            memory_kib: 8 * 1024,
+            iterations: 1,
+            parallelism: 1,
+        },
+        ..Settings::default()
+    };
+    let mut vault = Vault::new(MemoryStorage::with_settings(settings));
+    vault
+        .create_master("master password".to_owned())
+        .await
+        .unwrap();
+    vault.unlock("master password".to_owned()).await.unwrap();
+    vault
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[tokio::test]
+    async fn in_memory_vault_needs_the_master_password() {
+        let mut vault = Vault::in_memory();
+        assert!(vault
+            .create_entry("db".to_owned(), None, None, "s3cret".to_owned(), None)
+            .await
+            .is_err());
+        vault
+            .create_master("master password".to_owned())
+            .await
+            .unwrap();
+        assert!(vault.create_master("other".to_owned()).await.is_err());
+        assert!(vault.unlock("wrong password".to_owned()).await.is_err());
+        assert!(!vault.is_unlocked());
+    }
+
+    #[tokio::test]
+    async fn empty_values_clear_the_optional_fields() {
+        let vault = test_vault().await;
+        let entry = vault
+            .create_entry(
+                "db".to_owned(),
+                Some("Production".to_owned()),
+                Some("admin".to_owned()),
+                "s3cret".to_owned(),
+                Some("https://db.example.com".to_owned()),
+            )
+            .await
+            .unwrap();
+        let entry = vault
+            .update_entry(
+                entry.id,
+                None,
+                Some(String::new()),
+                Some(String::new()),
+                Some(String::new()),
+                None,
+            )
+            .await
+            .unwrap();
+        assert_eq!(entry.name, "db");
+        assert_eq!(entry.description, None);
+        assert_eq!(entry.username, None);
+        assert_eq!(entry.url, None);
+        assert!(vault
+            .update_entry(
+                entry.id,
+                None,
+                None,
+                None,
+                Some("not a url".to_owned()),
+                None
+            )
+            .await
+            .is_err());
+    }
+
+    #[tokio::test]
+    async fn entries_are_encrypted_and_deleted_with_their_custom_fields() {
+        let vault = test_vault().await;
+        let entry = vault
+            .create_entry("db".to_owned(), None, None, " s3cret ".to_owned(), None)
+            .await
+            .unwrap();
+        assert_ne!(entry.password, b" s3cret ".to_vec());
+        assert_eq!(vault.decrypt_password(&entry).unwrap(), " s3cret ");
+
+        let fields = vec![CustomField::new("PIN", "1234", true)];
+        vault
+            .set_custom_fields(entry.id.to_owned(), fields.to_owned())
+            .await
+            .unwrap();
+        assert_eq!(
+            vault
+                .decrypt_custom_fields(entry.id.to_owned())
+                .await
+                .unwrap(),
+            fields
+        );
+
+        vault.delete_entry(entry.id.to_owned()).await.unwrap();
+        assert!(vault.get_all_entries().await.unwrap().is_empty());
+        assert!(vault
+            .get_custom_fields(entry.id.to_owned())
+            .await
+            .unwrap()
+            .is_empty());
+        assert!(vault.delete_entry(entry.id).await.is_err());
+    }
+}
@@ -0,0 +1,268 @@
+use serde::{Deserialize, Serialize};
+
+use crate::{
+    configuration, container,
+    custom_fields::CustomField,
+    entry_transfer::{self, DecryptedEntry, ExportSelection, ImportOptions, ImportSummary},
+    error::Error,
+    util,
+};
+
+/// The version of the vault file schema. It is increased when a field is removed or changes
+/// meaning, fields added later are optional so that older files still import.
+pub const SCHEMA_VERSION: u32 = 1;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum VaultFormat {
+    Json,
+    Yaml,
+}
+
+impl VaultFormat {
+    /// The content type of an encrypted export of this format, see `container`
+    pub fn content_type(&self) -> &'static str {
+        match self {
+            VaultFormat::Json => "json",
+            VaultFormat::Yaml => "yaml",
+        }
+    }
+}
+
+/// A full-fidelity export of the vault: every column of the entries, with their secrets
+/// decrypted, and the rows of their child tables nested in each entry
+#[derive(Debug, Serialize, Deserialize)]
+struct VaultFile {
+    schema_version: u32,
+    exported_date: Option<String>,
+    #[serde(default)]
+    entries: Vec<VaultEntry>,
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+struct VaultEntry {
+    id: Option<String>,
+    name: String,
+    description: Option<String>,
+    username: Option<String>,
+    url: Option<String>,
+    #[serde(default)]
+    password: String,
+    totp: Option<String>,
+    ssh_public_key: Option<String>,
+    folder: Option<String>,
+    #[serde(default)]
+    tags: Vec<String>,
+    created_date: Option<String>,
+    modified_date: Option<String>,
+    #[serde(default)]
+    custom_fields: Vec<CustomField>,
+}
+
+impl From<DecryptedEntry> for VaultEntry {
+    fn from(entry: DecryptedEntry) -> Self {
+        VaultEntry {
+            id: entry.id,
+            name: entry.name,
+            description: entry.description,
+            username: entry.username,
+            url: entry.url,
+            password: entry.password,
+            totp: entry.totp,
+            ssh_public_key: entry.ssh_public_key,
+            folder: entry.folder,
+            tags: entry.tags,
+            created_date: entry.created_date.as_ref().map(util::format_date),
+            modified_date: entry.modified_date.as_ref().map(util::format_date),
+            custom_fields: entry.custom_fields,
+        }
+    }
+}
+
+impl TryFrom<VaultEntry> for DecryptedEntry {
+    type Error = Error;
+
+    /// Dates are read as in `util::parse_date`, so exports of earlier versions (in local time) are read
+    fn try_from(entry: VaultEntry) -> Result<Self, Error> {
+        Ok(DecryptedEntry {
+            name: entry.name,
+            description: entry.description,
+            username: entry.username,
+            url: entry.url,
+            password: entry.password,
+            totp: entry.totp,
+            folder: entry.folder,
+            tags: entry.tags,
+            custom_fields: entry.custom_fields,
+            id: entry.id,
+            created_date: entry
+                .created_date
+                .as_deref()
+                .map(util::parse_date)
+                .transpose()?,
+            modified_date: entry
+                .modified_date
+                .as_deref()
+                .map(util::parse_date)
+                .transpose()?,
+            ssh_public_key: entry.ssh_public_key,
+        })
+    }
+}
+
+/// Exports the selected entries as JSON or YAML, encrypted with the passphrase unless none is given
+pub async fn export_vault(
+    master_password: String,
+    path: String,
+    format: VaultFormat,
+    passphrase: Option<String>,
+    selection: &ExportSelection,
+) -> Result<usize, Error> {
+    let entries = entry_transfer::decrypt_entries(master_password, selection).await?;
+    let count = entries.len();
+    let data = write_vault_file(entries, format)?;
+    let data = match passphrase {
+        Some(passphrase) => {
+            let kdf = configuration::get_settings()?.kdf;
+            container::seal(format.content_type(), &data, &passphrase, &kdf)?.into_bytes()
+        }
+        None => data,
+    };
+    entry_transfer::write_private_file(&path, &data)?;
+    Ok(count)
+}
+
+/// Imports a JSON or YAML export, keeping the IDs, dates and values of the entries as they are
+pub async fn import_vault(
+    master_password: String,
+    data: Vec<u8>,
+    format: VaultFormat,
+    options: ImportOptions,
+) -> Result<ImportSummary, Error> {
+    let entries = read_vault_file(&data, format)?;
+    let options = ImportOptions {
+        lossless: true,
+        ..options
+    };
+    entry_transfer::create_entries(master_password, entries, options).await
+}
+
+/// The entries as a JSON or YAML export
+pub fn write_vault_file(
+    entries: Vec<DecryptedEntry>,
+    format: VaultFormat,
+) -> Result<Vec<u8>, Error> {
+    let file = VaultFile {
+        schema_version: SCHEMA_VERSION,
+        exported_date: Some(util::format_date(&util::now())),
+        entries: entries.into_iter().map(VaultEntry::from).collect(),
+    };
+    let err = "Failed to write the export";
+    match format {
+        VaultFormat::Json => serde_json::to_vec_pretty(&file).map_err(|_| err.to_owned()),
+        VaultFormat::Yaml => serde_yaml::to_string(&file)
+            .map(|data| data.into_bytes())
+            .map_err(|_| err.to_owned()),
+    }
+}
+
+/// The entries of a JSON or YAML export
+pub fn read_vault_file(data: &[u8], format: VaultFormat) -> Result<Vec<DecryptedEntry>, Error> {
+    let file: VaultFile = match format {
+        VaultFormat::Json => {
+            serde_json::from_slice(data).map_err(|e| format!("Invalid JSON export: {}", e))?
+        }
+        VaultFormat::Yaml => {
+            serde_yaml::from_slice(data).map_err(|e| format!("Invalid YAML export: {}", e))?
+        }
+    };
+    if file.schema_version > SCHEMA_VERSION {
+        return Err(format!(
+            "The export has schema version {}, this version of MyPass reads up to {}",
+            file.schema_version, SCHEMA_VERSION
+        ));
+    }
+    file.entries
+        .into_iter()
+        .map(DecryptedEntry::try_from)
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{
+        entry_transfer::{create_vault_entries, decrypt_vault_entries},
+        vault::test_vault,
+    };
+
+    fn entries() -> Vec<DecryptedEntry> {
+        vec![
+            DecryptedEntry {
+                name: "github".to_owned(),
+                description: Some("Work account".to_owned()),
+                username: Some("me".to_owned()),
+                url: Some("https://github.com".to_owned()),
+                password: " s3cret ".to_owned(),
+                totp: Some("JBSWY3DPEHPK3PXP".to_owned()),
+                folder: Some("Work/Dev".to_owned()),
+                tags: vec!["a".to_owned(), "b".to_owned()],
+                custom_fields: vec![
+                    CustomField::new("Note", "kept", false),
+                    CustomField::new("PIN", "1234", true),
+                ],
+                created_date: Some(util::parse_date("2020-01-02T03:04:05Z").unwrap()),
+                modified_date: Some(util::parse_date("2021-01-02T03:04:05Z").unwrap()),
+                ..Default::default()
+            },
+            // Values that other imports would fix
+            DecryptedEntry {
+                name: String::new(),
+                url: Some("not a url".to_owned()),
+                password: "x".to_owned(),
+                totp: Some("1nvalid!".to_owned()),
+                ..Default::default()
+            },
+        ]
+    }
+
+    async fn round_trip(format: VaultFormat) {
+        let options = ImportOptions {
+            lossless: true,
+            ..Default::default()
+        };
+        let source = test_vault().await;
+        create_vault_entries(&source, entries(), options)
+            .await
+            .unwrap();
+        let exported = decrypt_vault_entries(&source, &ExportSelection::default())
+            .await
+            .unwrap();
+
+        let data = write_vault_file(exported.to_owned(), format).unwrap();
+        let target = test_vault().await;
+        let summary =
+            create_vault_entries(&target, read_vault_file(&data, format).unwrap(), options)
+                .await
+                .unwrap();
+        assert_eq!(summary.created.len(), 2);
+        let imported = decrypt_vault_entries(&target, &ExportSelection::default())
+            .await
+            .unwrap();
+
+        assert_eq!(imported, exported);
+        assert_eq!(imported[1].name, "");
+        assert_eq!(imported[1].url.as_deref(), Some("not a url"));
+        assert_eq!(imported[1].totp.as_deref(), Some("1nvalid!"));
+        assert!(imported[1].custom_fields.is_empty());
+    }
+
+    #[tokio::test]
+    async fn json_export_round_trips() {
+        round_trip(VaultFormat::Json).await;
+    }
+
+    #[tokio::test]
+    async fn yaml_export_round_trips() {
+        round_trip(VaultFormat::Yaml).await;
+    }
+}
@@ -0,0 +1,61 @@
+use std::io;
+
+use clap::Command;
+use clap_complete::{env::Shells, CompletionCandidate, Shell};
+use tokio::runtime::Handle;
+
+use crate::util::PrintError;
+
+pub fn print_completions(mut cmd: Command, shell: Shell, dynamic: bool) -> Result<(), ()> {
+    let name = cmd.get_name().to_owned();
+    if !dynamic {
+        clap_complete::generate(shell, &mut cmd, name, &mut io::stdout());
+        return Ok(());
+    }
+
+    // The registration script calls back into this binary (`COMPLETE=<shell> mypass`)
+    // on every <TAB>, which is what allows entry names to be completed live
+    let shells = Shells::builtins();
+    let completer = shells
+        .completer(&shell.to_string())
+        .ok_or(format!(
+            "Dynamic completions are not supported for {}",
+            shell
+        ))
+        .print_err()?;
+    let bin = std::env::current_exe()
+        .map(|p| p.to_string_lossy().into_owned())
+        .unwrap_or(name.to_owned());
+    completer
+        .write_registration("COMPLETE", &name, &name, &bin, &mut io::stdout())
+        .print_err()
+}
+
+pub fn setting_candidates() -> Vec<CompletionCandidate> {
+    api::settings::Settings::keys()
+        .into_iter()
+        .map(CompletionCandidate::new)
+        .collect()
+}
+
+pub fn entry_candidates() -> Vec<CompletionCandidate> {
+    // Completers are synchronous but are invoked from within the async entrypoint
+    let entries =
+        tokio::task::block_in_place(|| Handle::current().block_on(api::entries::get_all_entries()))
+            .unwrap_or_default();
+
+    let mut candidates: Vec<CompletionCandidate> = Vec::new();
+    for entry in entries {
+        let help = entry
+            .username
+            .to_owned()
+            .map_or(entry.id.to_owned(), |u| format!("{} ({})", u, entry.id));
+        candidates.push(CompletionCandidate::new(entry.name.to_owned()).help(Some(help.into())));
+        candidates.push(
+            CompletionCandidate::new(entry.id)
+                .help(Some(entry.name.into()))
+                .hide(true),
+        );
+    }
+    candidates
+}
@@ -0,0 +1,30 @@
+use api::migrations;
+
+use crate::{util::PrintError, view};
+
+/// Applies the pending migrations of the data store, or lists its migrations with `status`
+pub async fn migrate(status: bool) -> Result<(), ()> {
+    if status {
+        let status = migrations::get_status().await.print_err()?;
+        view::print_migrations(&status).print_err()?;
+        if status.is_newer() {
+            eprintln!(
+                "This data store was written by a newer version of MyPass (migrations {}), please upgrade MyPass",
+                status.unknown.join(", ")
+            );
+        }
+        return Ok(());
+    }
+    let report = migrations::migrate_current().await.print_err()?;
+    if report.applied.is_empty() {
+        println!("The data store is up to date");
+        return Ok(());
+    }
+    if let Some(backup) = report.backup {
+        println!("Backed up the data store to {}", backup.to_string_lossy());
+    }
+    for name in report.applied {
+        println!("Applied {}", name);
+    }
+    Ok(())
+}
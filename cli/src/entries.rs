@@ -1,42 +1,99 @@
-use api::crypto;
+use api::{
+    crypto,
+    entries::{EntryQuery, EntrySort},
+    error::Error,
+};
+use clap::{Args, ValueEnum};
 use model::entities::entry;
 
 use crate::{
     master::{prompt_authenticate, AuthenticatedMaster},
+    picker,
     util::{self, input, PrintError},
     view,
 };
 
-pub async fn view_all_entries(verbose: bool) -> Result<(), ()> {
+#[derive(Debug, Clone, Copy, ValueEnum)]
+pub enum Sort {
+    Name,
+    Created,
+    Modified,
+}
+
+impl From<Sort> for EntrySort {
+    fn from(sort: Sort) -> Self {
+        match sort {
+            Sort::Name => EntrySort::Name,
+            Sort::Created => EntrySort::Created,
+            Sort::Modified => EntrySort::Modified,
+        }
+    }
+}
+
+/// Which entries `view` lists, and in which order. Entries keep their number.
+#[derive(Debug, Clone, Default, Args)]
+pub struct ListOptions {
+    /// Only entries modified since a date (e.g. 2024-01-31) or for an age (e.g. 7d, 12h, 2w)
+    #[arg(long)]
+    pub modified_since: Option<String>,
+
+    /// Only entries not modified since a date or for an age (e.g. 90d, 1y)
+    #[arg(long)]
+    pub older_than: Option<String>,
+
+    /// Sort the entries (default is their number)
+    #[arg(long, value_enum)]
+    pub sort: Option<Sort>,
+
+    /// Reverse the order of the entries
+    #[arg(short, long)]
+    pub reverse: bool,
+}
+
+impl ListOptions {
+    fn query(&self) -> Result<EntryQuery, Error> {
+        let parse = |time: &Option<String>| time.as_deref().map(api::util::parse_time).transpose();
+        Ok(EntryQuery {
+            modified_since: parse(&self.modified_since)?,
+            older_than: parse(&self.older_than)?,
+            sort: self.sort.map(EntrySort::from),
+            reverse: self.reverse,
+        })
+    }
+}
+
+pub async fn view_all_entries(verbose: bool, options: &ListOptions) -> Result<(), ()> {
+    let query = options.query().print_err()?;
     let entries = api::entries::get_all_entries().await.print_err()?;
-    view::print_entries(entries, verbose).print_err()
+    view::print_entries(query.apply(entries), verbose, query.is_filtered()).print_err()
 }
 
 pub async fn view_entry(
-    number: Option<usize>,
+    reference: Option<String>,
     view_pass: bool,
     copy_password: bool,
     copy_username: bool,
     copy_url: bool,
     verbose: bool,
 ) -> Result<(), ()> {
-    let number =
-        util::unwrap_or_input_number(number, "Enter entry number: ", "Invalid entry number")?;
-    let entry = entry_by_number(number).await?;
+    let (number, entry) = prompt_entry(reference).await?;
 
-    let decrypted_password = if view_pass || copy_password {
-        let master: AuthenticatedMaster = prompt_authenticate().await?;
-        Some(
+    let master: Option<AuthenticatedMaster> = if view_pass || copy_password {
+        Some(prompt_authenticate().await?)
+    } else {
+        None
+    };
+    let decrypted_password = match &master {
+        Some(master) => Some(
             crypto::decrypt_password(
-                master.password,
+                master.password.to_owned(),
                 entry.password.to_owned(),
                 entry.id.to_owned(),
-                master.master.id,
+                master.master.id.to_owned(),
             )
             .print_err()?,
-        )
-    } else {
-        None
+        ),
+        None => None,
     };
 
     let item_to_copy: Option<String> = if copy_password {
@@ -58,16 +115,96 @@ pub async fn view_entry(
     item_to_copy.map(util::copy_to_clipboard);
 
     let decrypted_password = if view_pass { decrypted_password } else { None };
+    let entry_id = entry.id.to_owned();
     view::print_entry(entry, number, decrypted_password, verbose).print_err()?;
+
+    // Custom fields are only shown when revealing the entry, their values being encrypted
+    if let (true, Some(master)) = (view_pass, master) {
+        let fields = api::custom_fields::get_custom_fields(entry_id)
+            .await
+            .print_err()?;
+        let fields =
+            api::custom_fields::decrypt_custom_fields(fields, &master.master, master.password)
+                .print_err()?;
+        view::print_custom_fields(fields).print_err()?;
+    }
     Ok(())
 }
 
-pub async fn entry_by_number(number: usize) -> Result<entry::Model, ()> {
+pub async fn prompt_entry(reference: Option<String>) -> Result<(usize, entry::Model), ()> {
+    if reference.is_none() && util::is_interactive() {
+        return pick_entry().await;
+    }
+    let reference = util::unwrap_or_input(reference, "Enter entry number, name or ID: ")
+        .ok_or_else(|| eprintln!("Invalid entry number"))?;
+    find_entry(&reference).await
+}
+
+pub async fn pick_entry() -> Result<(usize, entry::Model), ()> {
     let entries = api::entries::get_all_entries().await.print_err()?;
-    if number == 0 || number > entries.len() {
+    if entries.is_empty() {
+        println!("No password entries. Create one with `mypass create`");
         return Err(());
     }
-    Ok(entries[number - 1].to_owned())
+    let items: Vec<String> = entries
+        .iter()
+        .enumerate()
+        .map(|(index, e)| {
+            let mut item = format!("{}. {}", index + 1, e.name);
+            for field in [&e.username, &e.url].into_iter().flatten() {
+                item.push_str(&format!("  {}", field));
+            }
+            item
+        })
+        .collect();
+    let index = picker::pick("Search entries: ", &items)
+        .print_err()?
+        .ok_or_else(|| eprintln!("No entry selected"))?;
+    Ok((index + 1, entries[index].to_owned()))
+}
+
+/// Resolves an entry from its number (as listed by `mypass view`), its ID or its name
+pub async fn find_entry(reference: &str) -> Result<(usize, entry::Model), ()> {
+    lookup_entry(reference).await.print_err()
+}
+
+pub async fn lookup_entry(reference: &str) -> Result<(usize, entry::Model), Error> {
+    let entries = api::entries::get_all_entries().await?;
+    resolve_entry(reference, &entries)
+}
+
+/// Finds the entry with the ID or name `reference`, or else at that number.
+/// Names come before numbers so that an entry named e.g. `3` can be selected.
+fn resolve_entry(
+    reference: &str,
+    entries: &[entry::Model],
+) -> Result<(usize, entry::Model), Error> {
+    if let Some(found) = entries.iter().position(|e| e.id == reference) {
+        return Ok((found + 1, entries[found].to_owned()));
+    }
+
+    let mut matches = entries
+        .iter()
+        .enumerate()
+        .filter(|(_, e)| e.name == reference);
+    match (matches.next(), matches.next()) {
+        (Some((index, entry)), None) => return Ok((index + 1, entry.to_owned())),
+        (Some(_), Some(_)) => {
+            return Err(format!(
+                "Multiple entries are named {}, use the entry ID instead",
+                reference
+            ))
+        }
+        _ => {}
+    }
+
+    if let Ok(number) = reference.parse::<usize>() {
+        if number == 0 || number > entries.len() {
+            return Err("Invalid entry number".to_owned());
+        }
+        return Ok((number, entries[number - 1].to_owned()));
+    }
+    Err(format!("No entry matches {}", reference))
 }
 
 pub async fn create_entry(
@@ -81,13 +218,14 @@ pub async fn create_entry(
     let description = util::unwrap_or_input(description, enter_a("description").as_ref());
     let username = util::unwrap_or_input(username, enter_a("username").as_ref());
     let url = util::unwrap_or_input(url, enter_a("url").as_ref());
+    let settings = util::settings()?;
     let password: String =
         util::get_password_with_prompt("Enter a password (leave empty to generate): ")
-            .unwrap_or_else(|_| api::crypto::generate_password());
+            .unwrap_or_else(|_| settings.generator.generate());
     let master: AuthenticatedMaster = prompt_authenticate().await?;
     let entry = api::entries::create_entry(
         master.password,
-        name.unwrap_or("Untitled".to_owned()),
+        name.unwrap_or(settings.entries.default_name),
         description,
         username,
         password,
@@ -100,6 +238,7 @@ pub async fn create_entry(
 }
 
 pub async fn create_many() -> Result<(), ()> {
+    let settings = util::settings()?;
     let mut m: Option<AuthenticatedMaster> = None;
     loop {
         println!("\n");
@@ -123,7 +262,7 @@ pub async fn create_many() -> Result<(), ()> {
         m = Some(master.clone());
         let entry = api::entries::create_entry(
             master.password,
-            name.unwrap_or("Untitled".to_owned()),
+            name.unwrap_or(settings.entries.default_name.to_owned()),
             description,
             username,
             password,
@@ -136,28 +275,28 @@ pub async fn create_many() -> Result<(), ()> {
 }
 
 pub async fn update_entry(
-    entry_number: Option<usize>,
+    reference: Option<String>,
     name: Option<String>,
     description: Option<String>,
     username: Option<String>,
     url: Option<String>,
+    folder: Option<String>,
+    tags: Option<String>,
 ) -> Result<(), ()> {
-    let number =
-        util::unwrap_or_input_number(entry_number, "Enter entry number: ", "Invalid entry number")?;
-    let entry = entry_by_number(number).await?;
+    let (number, entry) = prompt_entry(reference).await?;
     let enter_a = |name: &str| -> String { format!("Enter a {} (skip to leave blank): ", name) };
     let name = util::unwrap_or_input(name, enter_a("name").as_ref());
     let description = util::unwrap_or_input(description, enter_a("description").as_ref());
     let username = util::unwrap_or_input(username, enter_a("username").as_ref());
     let url = util::unwrap_or_input(url, enter_a("url").as_ref());
     let password = rpassword::prompt_password("Enter a password (skip to leave blank): ")
-        .map_err(|_| println!("Failed to get password"))
+        .map_err(|_| eprintln!("Failed to get password"))
         .ok()
         .and_then(|p| if p.trim().is_empty() { None } else { Some(p) });
     let password: Option<String> = if let Some(p) = password {
         let retyped = util::get_password_with_prompt_print("Retype new password: ")?;
         if retyped != p {
-            println!("Passwords must be the same");
+            eprintln!("Passwords must be the same");
             return Err(());
         }
         Some(p)
@@ -170,17 +309,26 @@ pub async fn update_entry(
     } else {
         None
     };
-    let entry = api::entries::update_entry(entry.id, name, description, username, url, passwords)
-        .await
-        .print_err()?;
+    let mut entry =
+        api::entries::update_entry(entry.id, name, description, username, url, passwords)
+            .await
+            .print_err()?;
+    if folder.is_some() {
+        entry = api::entries::set_folder(entry.id, folder)
+            .await
+            .print_err()?;
+    }
+    if let Some(tags) = tags {
+        entry = api::entries::set_tags(entry.id, api::entries::split_tags(&tags))
+            .await
+            .print_err()?;
+    }
     view::print_entry(entry, number, None, true).print_err()?;
     Ok(())
 }
 
-pub async fn delete_entry(number: Option<usize>) -> Result<(), ()> {
-    let number =
-        util::unwrap_or_input_number(number, "Enter entry number: ", "Invalid entry number")?;
-    let entry = entry_by_number(number).await?;
+pub async fn delete_entry(reference: Option<String>) -> Result<(), ()> {
+    let (_, entry) = prompt_entry(reference).await?;
     prompt_authenticate().await?;
 
     api::entries::delete_entry(entry.id.to_owned())
@@ -188,23 +336,52 @@ pub async fn delete_entry(number: Option<usize>) -> Result<(), ()> {
         .print_err()
 }
 
-pub async fn export_entries(path: Option<String>) -> Result<(), ()> {
-    let path = util::unwrap_or_input(path, "Export path (default is ~/.mypass/entries.csv): ");
-    let master = prompt_authenticate().await?;
+#[cfg(test)]
+mod tests {
+    use super::*;
 
-    api::entry_transfer::export_entries(master.password, path)
-        .await
-        .print_err()?;
-    println!("Export finished");
-    Ok(())
-}
+    fn entry(id: &str, name: &str) -> entry::Model {
+        entry::Model {
+            id: id.to_owned(),
+            name: name.to_owned(),
+            description: None,
+            username: None,
+            password: Vec::new(),
+            url: None,
+            created_date: Default::default(),
+            modified_date: Default::default(),
+            totp: None,
+            ssh_public_key: None,
+            folder: None,
+            tags: None,
+        }
+    }
 
-pub async fn import_entries(path: Option<String>) -> Result<(), ()> {
-    let path = util::unwrap_or_input(path, "Import path (default is ~/.mypass/entries.csv): ");
-    let master = prompt_authenticate().await?;
-    api::entry_transfer::import_entries(master.password, path)
-        .await
-        .print_err()?;
-    println!("Import finished");
-    Ok(())
+    #[test]
+    fn resolve_entry_finds_ids_names_and_numbers() {
+        let entries = [entry("a1", "bank"), entry("b2", "github")];
+        assert_eq!(resolve_entry("b2", &entries).unwrap().0, 2);
+        assert_eq!(resolve_entry("github", &entries).unwrap().0, 2);
+        assert_eq!(resolve_entry("1", &entries).unwrap().1.name, "bank");
+        assert!(resolve_entry("0", &entries).is_err());
+        assert!(resolve_entry("3", &entries).is_err());
+        assert!(resolve_entry("gitlab", &entries).is_err());
+    }
+
+    #[test]
+    fn resolve_entry_prefers_names_over_numbers() {
+        let entries = [entry("a1", "bank"), entry("b2", "github"), entry("c3", "1")];
+        assert_eq!(
+            resolve_entry("1", &entries).unwrap(),
+            (3, entries[2].clone())
+        );
+        assert_eq!(resolve_entry("2", &entries).unwrap().1.name, "github");
+    }
+
+    #[test]
+    fn resolve_entry_rejects_ambiguous_names() {
+        let entries = [entry("a1", "github"), entry("b2", "github")];
+        assert!(resolve_entry("github", &entries).is_err());
+        assert_eq!(resolve_entry("b2", &entries).unwrap().0, 2);
+    }
 }
@@ -1,16 +1,41 @@
-use clap::{Parser, Subcommand};
+use std::path::PathBuf;
+
+use api::entry_transfer::{ExportField, ExportFilter, ExportSelection, ImportOptions};
+use clap::{CommandFactory, Parser, Subcommand};
+use clap_complete::{ArgValueCandidates, CompleteEnv, Shell};
 
 use crate::{
+    completions::{entry_candidates, print_completions, setting_candidates},
+    db::migrate,
     entries::{
-        create_entry, create_many, delete_entry, export_entries, import_entries, update_entry,
-        view_all_entries, view_entry,
+        create_entry, create_many, delete_entry, update_entry, view_all_entries, view_entry,
+        ListOptions,
     },
+    git_credential::git_credential,
     master::{create_master, move_db, set_path, view_master, view_path},
+    secrets::{inject, read, run_with_secrets},
+    server::{serve, Scope},
+    settings::{get_setting, list_settings, set_setting},
+    ssh::{add_ssh_key, list_ssh_keys},
+    ssh_agent::ssh_agent,
+    transfer::{export_entries, import_entries, Field, Format, KeePassCipher, Merge, Profile},
+    tui,
+    util::{clear_clipboard, print_migration_warnings},
+    vault::{add_vault, list_vaults, remove_vault, use_vault},
 };
 
 #[derive(Parser)]
-#[command(version("0.4.1"), about, long_about)]
+#[command(name = "mypass", version("0.4.1"), about, long_about)]
 struct Cli {
+    /// The configuration file to use, whose directory also holds the data
+    /// (default is $MYPASS_HOME/config.toml, or $XDG_CONFIG_HOME/mypass/config.toml)
+    #[arg(long, global = true)]
+    config: Option<PathBuf>,
+
+    /// The vault to use (default is $MYPASS_VAULT, or the default vault)
+    #[arg(long, global = true)]
+    vault: Option<String>,
+
     #[command(subcommand)]
     command: RootCommands,
 }
@@ -19,9 +44,9 @@ struct Cli {
 enum RootCommands {
     /// View password entries
     View {
-        /// The number of the entry to view
-        #[arg(short, long)]
-        number: Option<usize>,
+        /// The number, name or ID of the entry to view
+        #[arg(short, long, add = ArgValueCandidates::new(entry_candidates))]
+        number: Option<String>,
 
         /// Decrypt and reveal the password
         #[arg(short, long)]
@@ -43,6 +68,9 @@ enum RootCommands {
         #[arg(short, long)]
         verbose: bool,
 
+        #[command(flatten)]
+        list: ListOptions,
+
         #[command(subcommand)]
         commands: Option<ViewCommands>,
     },
@@ -71,9 +99,9 @@ enum RootCommands {
 
     /// Update a password entry
     Update {
-        /// The number of the entry to update
-        #[arg(short, long)]
-        number: Option<usize>,
+        /// The number, name or ID of the entry to update
+        #[arg(short, long, add = ArgValueCandidates::new(entry_candidates))]
+        number: Option<String>,
 
         /// The name of the entry
         #[arg(long)]
@@ -90,27 +118,187 @@ enum RootCommands {
         /// A URL associated to the password
         #[arg(long)]
         url: Option<String>,
+
+        /// Move the entry to a folder, a `/` separated path (empty to remove it from its folder)
+        #[arg(long)]
+        folder: Option<String>,
+
+        /// Replace the tags of the entry, comma separated (empty to remove them)
+        #[arg(short, long)]
+        tags: Option<String>,
     },
 
     /// Delete a password entry
     Delete {
-        /// The number of the entry to delete
-        #[arg(short, long)]
-        number: Option<usize>,
+        /// The number, name or ID of the entry to delete
+        #[arg(short, long, add = ArgValueCandidates::new(entry_candidates))]
+        number: Option<String>,
     },
 
-    /// Export entries to csv
+    /// Export entries to csv, KeePass, Bitwarden, JSON or YAML
     Export {
-        /// Path to export (default is ~/.mypass/entries.csv)
+        /// Path to export (default is entries.mypass in the data directory for csv, entries.csv when unencrypted)
         #[arg(short, long)]
         path: Option<String>,
+
+        /// The format of the exported file
+        #[arg(short, long, value_enum, default_value_t)]
+        format: Format,
+
+        /// A keyfile protecting the KeePass database, in addition to or instead of a password
+        #[arg(short, long)]
+        keyfile: Option<String>,
+
+        /// The cipher of the KeePass database
+        #[arg(long, value_enum, default_value_t)]
+        cipher: KeePassCipher,
+
+        /// Write the passwords in plain text (csv, Bitwarden, JSON and YAML formats)
+        #[arg(long)]
+        unencrypted: bool,
+
+        /// Read the export passphrase from the first line of a file instead of prompting
+        #[arg(long, conflicts_with = "unencrypted")]
+        passphrase_file: Option<String>,
+
+        /// Only export entries whose name, username, url or description contains this text
+        #[arg(short, long)]
+        query: Option<String>,
+
+        /// Only export entries with this tag (repeat to require several tags)
+        #[arg(short, long)]
+        tag: Vec<String>,
+
+        /// Only export entries in this folder or its subfolders
+        #[arg(long)]
+        folder: Option<String>,
+
+        /// Only export this entry, by number, name or ID (repeat to export several)
+        #[arg(short, long, add = ArgValueCandidates::new(entry_candidates))]
+        entry: Vec<String>,
+
+        /// The fields to export, comma separated (default is every field, the name is always exported)
+        #[arg(long, value_enum, value_delimiter = ',')]
+        fields: Option<Vec<Field>>,
     },
 
-    /// Import entries from csv
+    /// Import entries from csv, KeePass, Bitwarden, pass, JSON or YAML
     Import {
-        /// Path to export (default is ~/.mypass/entries.csv)
+        /// Path to import, the same as `--path` (e.g. the directory of a pass store)
+        #[arg(value_name = "PATH", conflicts_with = "path")]
+        source: Option<String>,
+
+        /// Path to import (default is entries.mypass or entries.csv in the data directory for csv, ~/.password-store for pass)
         #[arg(short, long)]
         path: Option<String>,
+
+        /// The format of the imported file
+        #[arg(short, long, value_enum, default_value_t)]
+        format: Format,
+
+        /// The keyfile of the KeePass database
+        #[arg(short, long)]
+        keyfile: Option<String>,
+
+        /// The application that exported the csv file (detected from its columns by default,
+        /// unknown columns are mapped interactively)
+        #[arg(long, value_enum)]
+        profile: Option<Profile>,
+
+        /// Read the passphrase of an encrypted export from the first line of a file instead of prompting
+        #[arg(long)]
+        passphrase_file: Option<String>,
+
+        /// What to do with entries that have the same name, username and url as an existing entry
+        #[arg(short, long, value_enum, default_value_t)]
+        merge: Merge,
+
+        /// Show what the import would change without changing the vault
+        #[arg(long)]
+        dry_run: bool,
+    },
+
+    /// Open the full-screen terminal interface
+    Tui,
+
+    /// Act as a git credential helper (`git config credential.helper mypass`)
+    GitCredential {
+        /// The action requested by git (get, store or erase)
+        action: String,
+    },
+
+    /// Print the raw value of a secret reference, for use in scripts
+    Read {
+        /// The reference, mypass://<vault>/<entry>/<field> (e.g. mypass://default/github/password)
+        uri: String,
+    },
+
+    /// Resolve `{{ mypass://<entry>/<field> }}` references in a template
+    Inject {
+        /// The template to read (default is stdin)
+        #[arg(short, long)]
+        input: Option<String>,
+
+        /// The file to write, with 0600 permissions (default is stdout)
+        #[arg(short, long)]
+        output: Option<String>,
+    },
+
+    /// Run a command with secrets injected as environment variables
+    Run {
+        /// A variable to set, e.g. DB_PASS=db:password
+        /// (field is one of name, description, username, url, password or totp)
+        #[arg(short, long = "env", value_name = "NAME=ENTRY:FIELD")]
+        env: Vec<String>,
+
+        /// The command to run (after `--`)
+        #[arg(last = true, required = true)]
+        command: Vec<String>,
+    },
+
+    /// Manage SSH keys stored in the vault
+    SshKey {
+        #[command(subcommand)]
+        commands: SshKeyCommands,
+    },
+
+    /// Run an SSH agent serving the SSH keys stored in the vault
+    SshAgent {
+        /// Path of the agent socket
+        /// (default is $XDG_RUNTIME_DIR/mypass-agent.sock or agent.sock in the data directory)
+        #[arg(short, long)]
+        socket: Option<String>,
+
+        /// Ask for confirmation on the terminal before each use of a key
+        #[arg(short, long)]
+        confirm: bool,
+
+        /// Start locked, keys can be listed but not used until `ssh-add -X` with the master password
+        #[arg(short, long)]
+        locked: bool,
+    },
+
+    /// Serve a local HTTP/JSON API for other tools
+    Serve {
+        /// Listen on a Unix socket (protected by its file permissions) instead of localhost
+        #[arg(short, long)]
+        socket: Option<String>,
+
+        /// The localhost port to listen on, with a random bearer token (default is a random port)
+        #[arg(short, long, default_value_t = 0, conflicts_with = "socket")]
+        port: u16,
+
+        /// What clients are allowed to do (default is read,reveal)
+        #[arg(long = "scope", value_enum, value_delimiter = ',')]
+        scopes: Vec<Scope>,
+
+        /// Minutes of inactivity after which the session locks itself (default is lock.server)
+        #[arg(long)]
+        lock_after: Option<u64>,
+
+        /// Start locked, until a client unlocks the session with the master password
+        #[arg(short, long)]
+        locked: bool,
     },
 
     /// Configures MyPass
@@ -118,6 +306,35 @@ enum RootCommands {
         #[command(subcommand)]
         commands: ConfigCommands,
     },
+
+    /// Manage the data store
+    Db {
+        #[command(subcommand)]
+        commands: DbCommands,
+    },
+
+    /// Clears the clipboard after a delay, started by the commands that copy to it
+    #[command(hide = true)]
+    ClearClipboard {
+        #[arg(long)]
+        after: u64,
+    },
+
+    /// Manage vaults, each with its own data store and master
+    Vault {
+        #[command(subcommand)]
+        commands: VaultCommands,
+    },
+
+    /// Generate shell completions
+    Completions {
+        /// The shell to generate completions for
+        shell: Shell,
+
+        /// Print a script that completes entry names live (re-source it on upgrade)
+        #[arg(short, long)]
+        dynamic: bool,
+    },
 }
 
 #[derive(Subcommand)]
@@ -127,13 +344,16 @@ enum ViewCommands {
         /// Display all columns
         #[arg(short, long)]
         verbose: bool,
+
+        #[command(flatten)]
+        list: ListOptions,
     },
 
     /// View one password entry
     One {
-        /// The number of the entry to view
-        #[arg(short, long)]
-        number: Option<usize>,
+        /// The number, name or ID of the entry to view
+        #[arg(short, long, add = ArgValueCandidates::new(entry_candidates))]
+        number: Option<String>,
 
         /// Decrypt and reveal the password
         #[arg(short, long)]
@@ -163,6 +383,66 @@ enum CreateCommands {
     Many,
 }
 
+#[derive(Subcommand)]
+enum SshKeyCommands {
+    /// Add an OpenSSH private key to the vault
+    Add {
+        /// Path to the private key (e.g. ~/.ssh/id_ed25519)
+        #[arg(short, long)]
+        file: String,
+
+        /// The name of the entry (default is the key comment)
+        #[arg(short, long)]
+        name: Option<String>,
+
+        /// The description of the entry
+        #[arg(short, long)]
+        description: Option<String>,
+    },
+
+    /// List the public keys in authorized_keys format
+    List,
+}
+
+#[derive(Subcommand)]
+enum VaultCommands {
+    /// List the vaults
+    List,
+
+    /// Add a vault and create its master
+    Add {
+        /// The name of the vault
+        name: String,
+
+        /// The directory of the db file (default is a directory of the vault in the vaults directory of the data directory)
+        #[arg(short, long)]
+        path: Option<String>,
+    },
+
+    /// Remove a vault from the configuration (its db file is kept)
+    Remove {
+        /// The name of the vault
+        name: String,
+    },
+
+    /// Make a vault the default vault
+    Use {
+        /// The name of the vault
+        name: String,
+    },
+}
+
+#[derive(Subcommand)]
+enum DbCommands {
+    /// Apply the pending migrations of the data store (also applied on every command),
+    /// after backing it up next to it
+    Migrate {
+        /// List the applied and pending migrations instead
+        #[arg(short, long)]
+        status: bool,
+    },
+}
+
 #[derive(Subcommand)]
 enum ConfigCommands {
     /// View Path to data store
@@ -175,19 +455,50 @@ enum ConfigCommands {
     /// Move the db file to a new path (will move the db for you)
     Move,
 
-    /// Set the db file path
+    /// Set a setting, or the db file path when no setting is given
     Set {
+        /// The setting to set, as <section>.<key> (e.g. generator.length)
+        #[arg(add = ArgValueCandidates::new(setting_candidates), requires = "value", conflicts_with = "path")]
+        key: Option<String>,
+
+        /// The value of the setting
+        value: Option<String>,
+
         /// Path to set (assumes you manually moved the db to this path)
         #[arg(short, long)]
         path: Option<String>,
     },
 
+    /// View the value of a setting, with the environment overrides
+    Get {
+        /// The setting to view, as <section>.<key> (e.g. generator.length)
+        #[arg(add = ArgValueCandidates::new(setting_candidates))]
+        key: String,
+    },
+
+    /// List the settings, with the environment overrides
+    List,
+
     /// Configure or view master
     Master,
 }
 
 pub async fn run() {
+    CompleteEnv::with_factory(Cli::command).complete();
     let cli = Cli::parse();
+    print_migration_warnings();
+    if let Some(config) = &cli.config {
+        match std::path::absolute(config) {
+            Ok(config) => api::configuration::select_config_file(config),
+            Err(_) => {
+                eprintln!("Invalid configuration file {}", config.to_string_lossy());
+                return;
+            }
+        }
+    }
+    if let Some(vault) = &cli.vault {
+        api::configuration::select_vault(vault.to_owned());
+    }
 
     if enforce_configured_master(&cli).await.is_err() {
         return;
@@ -202,10 +513,11 @@ pub async fn run() {
             copy_username,
             copy_url,
             verbose,
+            list,
         } => match commands {
             Some(command) => match command {
-                ViewCommands::All { verbose } => {
-                    view_all_entries(verbose).await.ok();
+                ViewCommands::All { verbose, list } => {
+                    view_all_entries(verbose, &list).await.ok();
                 }
                 ViewCommands::One {
                     number,
@@ -226,10 +538,9 @@ pub async fn run() {
                         .await
                         .ok();
                 } else if password || copy {
-                    println!("You may only specify the password or copy option with the number option `-n`");
-                    return;
+                    eprintln!("You may only specify the password or copy option with the number option `-n`");
                 } else {
-                    view_all_entries(verbose).await.ok();
+                    view_all_entries(verbose, &list).await.ok();
                 }
             }
         },
@@ -255,20 +566,123 @@ pub async fn run() {
             description,
             username,
             url,
+            folder,
+            tags,
         } => {
-            update_entry(number, name, description, username, url)
+            update_entry(number, name, description, username, url, folder, tags)
                 .await
                 .ok();
         }
-        RootCommands::Export { path } => {
-            export_entries(path).await.ok();
+        RootCommands::Export {
+            path,
+            format,
+            keyfile,
+            cipher,
+            unencrypted,
+            passphrase_file,
+            query,
+            tag,
+            folder,
+            entry,
+            fields,
+        } => {
+            // The entries are resolved to their IDs by export_entries
+            let filter = ExportFilter {
+                query,
+                tags: tag,
+                folder,
+                ids: entry,
+            };
+            let selection = ExportSelection {
+                filter,
+                fields: fields.map(|fields| fields.into_iter().map(ExportField::from).collect()),
+            };
+            export_entries(
+                path,
+                format,
+                keyfile,
+                cipher,
+                unencrypted,
+                passphrase_file,
+                selection,
+            )
+            .await
+            .ok();
         }
-        RootCommands::Import { path } => {
-            import_entries(path).await.ok();
+        RootCommands::Import {
+            source,
+            path,
+            format,
+            keyfile,
+            profile,
+            passphrase_file,
+            merge,
+            dry_run,
+        } => {
+            let options = ImportOptions {
+                strategy: merge.into(),
+                dry_run,
+                ..Default::default()
+            };
+            let path = source.or(path);
+            import_entries(path, format, keyfile, profile, passphrase_file, options)
+                .await
+                .ok();
         }
         RootCommands::Delete { number } => {
             delete_entry(number).await.ok();
         }
+        RootCommands::Run { env, command } => {
+            let code = run_with_secrets(env, command).await.unwrap_or(1);
+            std::process::exit(code);
+        }
+        RootCommands::Read { uri } => {
+            if read(uri).await.is_err() {
+                std::process::exit(1);
+            }
+        }
+        RootCommands::Inject { input, output } => {
+            if inject(input, output).await.is_err() {
+                std::process::exit(1);
+            }
+        }
+        RootCommands::GitCredential { action } => {
+            git_credential(action).await.ok();
+        }
+        RootCommands::SshKey { commands } => match commands {
+            SshKeyCommands::Add {
+                file,
+                name,
+                description,
+            } => {
+                add_ssh_key(file, name, description).await.ok();
+            }
+            SshKeyCommands::List => {
+                list_ssh_keys().await.ok();
+            }
+        },
+        RootCommands::SshAgent {
+            socket,
+            confirm,
+            locked,
+        } => {
+            ssh_agent(socket, confirm, locked).await.ok();
+        }
+        RootCommands::Serve {
+            socket,
+            port,
+            scopes,
+            lock_after,
+            locked,
+        } => {
+            serve(socket, port, scopes, lock_after, locked).await.ok();
+        }
+        RootCommands::Tui => {
+            tui::run().await.ok();
+        }
+        RootCommands::Completions { shell, dynamic } => {
+            print_completions(Cli::command(), shell, dynamic).ok();
+        }
         RootCommands::Config { commands } => match commands {
             ConfigCommands::Path { copy } => {
                 view_path(copy).await.ok();
@@ -276,7 +690,7 @@ pub async fn run() {
             ConfigCommands::Master => {
                 let is_master_configured = api::master::is_master_configured()
                     .await
-                    .map_err(|e| println!("{}", e))
+                    .map_err(|e| eprintln!("{}", e))
                     .ok();
                 if let Some(is_master_configured) = is_master_configured {
                     if is_master_configured {
@@ -289,24 +703,70 @@ pub async fn run() {
             ConfigCommands::Move => {
                 move_db().await.ok();
             }
-            ConfigCommands::Set { path } => {
+            ConfigCommands::Set {
+                key: Some(key),
+                value: Some(value),
+                ..
+            } => {
+                set_setting(key, value).ok();
+            }
+            ConfigCommands::Set { path, .. } => {
                 set_path(path).await.ok();
             }
+            ConfigCommands::Get { key } => {
+                get_setting(key).ok();
+            }
+            ConfigCommands::List => {
+                list_settings().ok();
+            }
+        },
+        RootCommands::Db { commands } => match commands {
+            DbCommands::Migrate { status } => {
+                migrate(status).await.ok();
+            }
+        },
+        RootCommands::ClearClipboard { after } => {
+            clear_clipboard(after).ok();
+        }
+        RootCommands::Vault { commands } => match commands {
+            VaultCommands::List => {
+                list_vaults().ok();
+            }
+            VaultCommands::Add { name, path } => {
+                add_vault(name, path).await.ok();
+            }
+            VaultCommands::Remove { name } => {
+                remove_vault(name).ok();
+            }
+            VaultCommands::Use { name } => {
+                use_vault(name).ok();
+            }
         },
     };
 }
 
 async fn enforce_configured_master(cli: &Cli) -> Result<(), ()> {
+    if let RootCommands::Vault { .. }
+    | RootCommands::Db { .. }
+    | RootCommands::ClearClipboard { .. } = cli.command
+    {
+        return Ok(());
+    }
     let is_master_configured = api::master::is_master_configured()
         .await
-        .map_err(|e| println!("{}", e))?;
+        .map_err(|e| eprintln!("{}", e))?;
     // TODO: Btter way to do this
     if let RootCommands::Config {
-        commands: ConfigCommands::Master,
-    } = cli.command
+        commands:
+            ConfigCommands::Master
+            | ConfigCommands::Get { .. }
+            | ConfigCommands::List
+            | ConfigCommands::Set { key: Some(_), .. },
+    }
+    | RootCommands::Completions { .. } = cli.command
     {
     } else if !is_master_configured {
-        println!("Master is not configured. Please create a master key. (`mypass config master`)");
+        eprintln!("Master is not configured. Please create a master key. (`mypass config master`)");
         return Err(());
     }
     Ok(())
@@ -0,0 +1,327 @@
+use std::io::{self, BufRead, Write};
+
+use api::{crypto, error::Error};
+use model::entities::entry;
+use url::Url;
+
+use crate::{master::prompt_authenticate, util::PrintError};
+
+/// The tag of the entries created by `store`, the only ones `erase` deletes
+const STORED_TAG: &str = "git-credential";
+
+/// The attributes of the git credential protocol that are used for matching
+#[derive(Default)]
+struct Credential {
+    protocol: Option<String>,
+    host: Option<String>,
+    path: Option<String>,
+    username: Option<String>,
+    password: Option<String>,
+}
+
+impl Credential {
+    fn read() -> Result<Credential, Error> {
+        let mut credential = Credential::default();
+        for line in io::stdin().lock().lines() {
+            let line = line.map_err(|_| "Failed to read credential from stdin")?;
+            if line.is_empty() {
+                break;
+            }
+            let Some((key, value)) = line.split_once('=') else {
+                continue;
+            };
+            let value = Some(value.to_owned());
+            match key {
+                "protocol" => credential.protocol = value,
+                "host" => credential.host = value.map(|h| h.to_lowercase()),
+                "path" => credential.path = value.map(|p| p.trim_matches('/').to_owned()),
+                "username" => credential.username = value,
+                "password" => credential.password = value,
+                _ => {}
+            }
+        }
+        Ok(credential)
+    }
+
+    fn path(&self) -> &str {
+        self.path.as_deref().unwrap_or("")
+    }
+
+    /// Returns the length of the entry's URL path if the entry matches, so that
+    /// the most specific entry can be preferred. The entry path must be a prefix of
+    /// the requested path (when git sends one, see `credential.useHttpPath`).
+    fn match_entry(&self, entry: &entry::Model) -> Option<usize> {
+        let url = Url::parse(entry.url.as_deref()?).ok()?;
+        if Some(url.scheme()) != self.protocol.as_deref() {
+            return None;
+        }
+        let host = match url.port() {
+            Some(port) => format!("{}:{}", url.host_str()?, port),
+            None => url.host_str()?.to_owned(),
+        };
+        if Some(host.to_lowercase().as_str()) != self.host.as_deref() {
+            return None;
+        }
+        if self.username.is_some() && entry.username != self.username {
+            return None;
+        }
+        let entry_path = url.path().trim_matches('/');
+        let is_prefix = entry_path.is_empty()
+            || self.path.is_none()
+            || self.path() == entry_path
+            || self.path().starts_with(&format!("{}/", entry_path));
+        is_prefix.then_some(entry_path.len())
+    }
+
+    /// The matching entry with the longest URL path
+    fn best_match<'a>(&self, entries: &'a [entry::Model]) -> Option<&'a entry::Model> {
+        entries
+            .iter()
+            .filter_map(|e| self.match_entry(e).map(|score| (score, e)))
+            .max_by_key(|(score, _)| *score)
+            .map(|(_, e)| e)
+    }
+
+    fn is_exact_match(&self, entry: &entry::Model) -> bool {
+        let entry_path = entry
+            .url
+            .as_deref()
+            .and_then(|u| Url::parse(u).ok())
+            .map(|u| u.path().trim_matches('/').to_owned())
+            .unwrap_or_default();
+        self.match_entry(entry).is_some()
+            && entry_path == self.path()
+            && entry.username == self.username
+    }
+
+    fn url(&self) -> Option<String> {
+        let url = format!(
+            "{}://{}/{}",
+            self.protocol.as_ref()?,
+            self.host.as_ref()?,
+            self.path()
+        );
+        Some(url)
+    }
+}
+
+/// Implements the git credential helper protocol (`get`, `store` and `erase`) on stdin/stdout.
+/// Unknown actions are ignored, as required by the protocol.
+pub async fn git_credential(action: String) -> Result<(), ()> {
+    match action.as_str() {
+        "get" => get().await,
+        "store" => store().await,
+        "erase" => erase().await,
+        _ => Ok(()),
+    }
+}
+
+async fn get() -> Result<(), ()> {
+    let credential = Credential::read().print_err()?;
+    let entries = api::entries::get_all_entries().await.print_err()?;
+    let Some(entry) = credential.best_match(&entries) else {
+        return Ok(());
+    };
+
+    let master = prompt_authenticate().await?;
+    let password = crypto::decrypt_password(
+        master.password,
+        entry.password.to_owned(),
+        entry.id.to_owned(),
+        master.master.id,
+    )
+    .print_err()?;
+
+    let mut out = String::new();
+    if let Some(username) = &entry.username {
+        out.push_str(&format!("username={}\n", username));
+    }
+    out.push_str(&format!("password={}\n", password));
+    io::stdout()
+        .write_all(out.as_bytes())
+        .map_err(|_| eprintln!("Failed to write credential"))
+}
+
+/// Creates an entry for a credential git reports as working, unless one already exists
+async fn store() -> Result<(), ()> {
+    let credential = Credential::read().print_err()?;
+    let (Some(url), Some(password)) = (credential.url(), credential.password.to_owned()) else {
+        return Ok(());
+    };
+    let entries = api::entries::get_all_entries().await.print_err()?;
+    if entries.iter().any(|e| credential.is_exact_match(e)) {
+        return Ok(());
+    }
+
+    let name = url
+        .split_once("://")
+        .map_or(url.as_str(), |(_, rest)| rest)
+        .trim_end_matches('/')
+        .to_owned();
+    let master = prompt_authenticate().await?;
+    let entry = api::entries::create_entry(
+        master.password,
+        name,
+        None,
+        credential.username,
+        password,
+        Some(url),
+    )
+    .await
+    .print_err()?;
+    api::entries::set_tags(entry.id, vec![STORED_TAG.to_owned()])
+        .await
+        .print_err()
+        .map(|_| ())
+}
+
+/// Deletes the entries created by `store` that hold a credential git reports as rejected.
+/// Entries created by the user are kept, a rejection may only be a temporary failure.
+async fn erase() -> Result<(), ()> {
+    let credential = Credential::read().print_err()?;
+    let Some(rejected) = credential.password.to_owned() else {
+        return Ok(());
+    };
+    let entries = api::entries::get_all_entries().await.print_err()?;
+    let candidates: Vec<&entry::Model> = entries
+        .iter()
+        .filter(|e| api::entries::tags_of(e).iter().any(|t| t == STORED_TAG))
+        .filter(|e| credential.is_exact_match(e))
+        .collect();
+    if candidates.is_empty() {
+        return Ok(());
+    }
+
+    let master = prompt_authenticate().await?;
+    for entry in candidates {
+        let password = crypto::decrypt_password(
+            master.password.to_owned(),
+            entry.password.to_owned(),
+            entry.id.to_owned(),
+            master.master.id.to_owned(),
+        )
+        .print_err()?;
+        if password == rejected {
+            api::entries::delete_entry(entry.id.to_owned())
+                .await
+                .print_err()?;
+        }
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn request(
+        protocol: &str,
+        host: &str,
+        path: Option<&str>,
+        username: Option<&str>,
+    ) -> Credential {
+        Credential {
+            protocol: Some(protocol.to_owned()),
+            host: Some(host.to_owned()),
+            path: path.map(|p| p.to_owned()),
+            username: username.map(|u| u.to_owned()),
+            password: None,
+        }
+    }
+
+    fn entry(name: &str, url: &str, username: Option<&str>) -> entry::Model {
+        entry::Model {
+            id: name.to_owned(),
+            name: name.to_owned(),
+            description: None,
+            username: username.map(|u| u.to_owned()),
+            password: Vec::new(),
+            url: Some(url.to_owned()),
+            created_date: Default::default(),
+            modified_date: Default::default(),
+            totp: None,
+            ssh_public_key: None,
+            folder: None,
+            tags: None,
+        }
+    }
+
+    #[test]
+    fn match_entry_checks_the_protocol_and_host() {
+        let credential = request("https", "github.com", None, None);
+        assert!(credential
+            .match_entry(&entry("a", "https://github.com", None))
+            .is_some());
+        assert!(credential
+            .match_entry(&entry("a", "https://GitHub.com/", None))
+            .is_some());
+        assert!(credential
+            .match_entry(&entry("a", "http://github.com", None))
+            .is_none());
+        assert!(credential
+            .match_entry(&entry("a", "https://gitlab.com", None))
+            .is_none());
+        assert!(credential
+            .match_entry(&entry("a", "https://github.com:8443", None))
+            .is_none());
+        assert!(credential
+            .match_entry(&entry("a", "not a url", None))
+            .is_none());
+
+        let credential = request("https", "git.example.com:8443", None, None);
+        assert!(credential
+            .match_entry(&entry("a", "https://git.example.com:8443/", None))
+            .is_some());
+    }
+
+    #[test]
+    fn match_entry_checks_the_username() {
+        let alice = entry("a", "https://github.com", Some("alice"));
+        let anyone = entry("b", "https://github.com", None);
+
+        let credential = request("https", "github.com", None, Some("alice"));
+        assert!(credential.match_entry(&alice).is_some());
+        assert!(credential.match_entry(&anyone).is_none());
+        let credential = request("https", "github.com", None, Some("bob"));
+        assert!(credential.match_entry(&alice).is_none());
+        // Without a username, git accepts the username of the entry
+        let credential = request("https", "github.com", None, None);
+        assert!(credential.match_entry(&alice).is_some());
+    }
+
+    #[test]
+    fn match_entry_checks_the_path() {
+        let org = entry("a", "https://github.com/acme", None);
+        let credential = request("https", "github.com", Some("acme/app.git"), None);
+        assert_eq!(credential.match_entry(&org), Some(4));
+        assert_eq!(
+            credential.match_entry(&entry("b", "https://github.com/", None)),
+            Some(0)
+        );
+        assert!(credential
+            .match_entry(&entry("c", "https://github.com/acme/other.git", None))
+            .is_none());
+        // The entry path is a prefix of whole segments
+        let credential = request("https", "github.com", Some("acme-corp/app.git"), None);
+        assert!(credential.match_entry(&org).is_none());
+    }
+
+    #[test]
+    fn best_match_prefers_the_longest_path() {
+        let entries = vec![
+            entry("host", "https://github.com", None),
+            entry("repo", "https://github.com/acme/app.git", None),
+            entry("org", "https://github.com/acme", None),
+        ];
+        let credential = request("https", "github.com", Some("acme/app.git"), None);
+        assert_eq!(credential.best_match(&entries).unwrap().name, "repo");
+        let credential = request("https", "github.com", Some("acme/other.git"), None);
+        assert_eq!(credential.best_match(&entries).unwrap().name, "org");
+        // Without a path from git (credential.useHttpPath is off), every entry of the host
+        // matches and the most specific one is used
+        let credential = request("https", "github.com", None, None);
+        assert_eq!(credential.best_match(&entries).unwrap().name, "repo");
+        let credential = request("https", "gitlab.com", None, None);
+        assert!(credential.best_match(&entries).is_none());
+    }
+}
@@ -1,9 +1,36 @@
+mod completions;
+mod db;
 mod entries;
 mod entrypoint;
+mod git_credential;
 mod master;
+mod native_host;
+mod picker;
+mod reference;
+mod secrets;
+mod server;
+mod settings;
+mod ssh;
+mod ssh_agent;
+mod transfer;
+mod tui;
 mod util;
+mod vault;
 mod view;
 
 pub async fn run() {
     entrypoint::run().await
 }
+
+/// Entrypoint of the `git-credential-mypass` binary (`git config credential.helper mypass`)
+pub async fn run_git_credential() {
+    let action = std::env::args().nth(1).unwrap_or_default();
+    git_credential::git_credential(action).await.ok();
+}
+
+/// Entrypoint of the `mypass-native-host` binary, started by the browser for the extension
+pub async fn run_native_host() {
+    if let Err(e) = native_host::native_host().await {
+        eprintln!("{}", e);
+    }
+}
@@ -34,7 +34,7 @@ pub async fn create_master() -> Result<(), ()> {
     let master_password = util::get_master_password()?;
     let master_password2 = util::get_password_with_prompt_print("Retype Master Password: ")?;
     if master_password != master_password2 {
-        println!("Passwords are not the same, cancelling.");
+        eprintln!("Passwords are not the same, cancelling.");
         return Err(());
     }
     let master = api::master::create_master(master_password)
@@ -60,7 +60,7 @@ pub async fn view_path(copy: bool) -> Result<(), ()> {
 pub async fn move_db() -> Result<(), ()> {
     let new_path = util::input("Enter new db file path: ")
         .ok_or(())
-        .map_err(|_| println!("File path is required"))?;
+        .map_err(|_| eprintln!("File path is required"))?;
     api::configuration::move_db(new_path.clone()).print_err()
 }
 
@@ -0,0 +1,384 @@
+use std::time::{Duration, Instant};
+
+use api::error::Error;
+use model::entities::entry;
+use serde::Deserialize;
+use serde_json::{json, Value};
+use tokio::io::{self, AsyncRead, AsyncReadExt, AsyncWrite, AsyncWriteExt};
+use url::{Host, Url};
+
+use crate::master::{authenticate, AuthenticatedMaster};
+
+/// Messages from the host are limited to 1 MB by browsers
+const MAX_MESSAGE_LEN: usize = 1024 * 1024;
+
+/// Messages to the host are limited to 64 MB by browsers
+const MAX_REQUEST_LEN: usize = 64 * 1024 * 1024;
+
+#[derive(Deserialize)]
+#[serde(tag = "type", rename_all = "lowercase")]
+enum Request {
+    Status,
+    /// Unlocks the session with the master password
+    Unlock {
+        password: String,
+    },
+    Lock,
+    /// The entries to offer on a page
+    Query {
+        origin: String,
+    },
+    /// The credentials of an entry returned by `query` for the same origin
+    Credentials {
+        origin: String,
+        entry: String,
+    },
+}
+
+/// A request, with an optional `id` that is echoed back in the response
+#[derive(Deserialize)]
+struct Envelope {
+    id: Option<Value>,
+    #[serde(flatten)]
+    request: Request,
+}
+
+struct Session {
+    master: Option<AuthenticatedMaster>,
+    last_used: Option<Instant>,
+    /// Idle time after which the session locks itself
+    lock_after: Duration,
+}
+
+/// Serves a browser extension over the native messaging protocol: each message is
+/// JSON preceded by its length as a 32-bit integer in native byte order.
+pub async fn native_host() -> Result<(), Error> {
+    let lock_after = Duration::from_secs(api::configuration::get_settings()?.lock.native_host * 60);
+    serve(io::stdin(), io::stdout(), Session::new(lock_after)).await
+}
+
+/// Answers each message of the browser until it closes the port
+async fn serve(
+    mut reader: impl AsyncRead + Unpin,
+    mut writer: impl AsyncWrite + Unpin,
+    mut session: Session,
+) -> Result<(), Error> {
+    while let Some(message) = read_message(&mut reader).await? {
+        let response = match serde_json::from_slice::<Envelope>(&message) {
+            Ok(Envelope { id, request }) => {
+                let mut response = session
+                    .handle(request)
+                    .await
+                    .unwrap_or_else(|e| json!({ "error": e }));
+                response["id"] = id.unwrap_or(Value::Null);
+                response
+            }
+            Err(e) => json!({ "id": null, "error": format!("Invalid request: {}", e) }),
+        };
+        write_message(&mut writer, &response).await?;
+    }
+    Ok(())
+}
+
+/// Reads a message, or `None` once the browser closed the port
+async fn read_message(reader: &mut (impl AsyncRead + Unpin)) -> Result<Option<Vec<u8>>, Error> {
+    let mut len = [0u8; 4];
+    if reader.read_exact(&mut len).await.is_err() {
+        return Ok(None);
+    }
+    let len = u32::from_ne_bytes(len) as usize;
+    if len > MAX_REQUEST_LEN {
+        return Err("The message from the browser is too large".to_owned());
+    }
+    let mut message = vec![0u8; len];
+    reader
+        .read_exact(&mut message)
+        .await
+        .map_err(|_| "Failed to read from the browser")?;
+    Ok(Some(message))
+}
+
+async fn write_message(
+    writer: &mut (impl AsyncWrite + Unpin),
+    response: &Value,
+) -> Result<(), Error> {
+    let err = "Failed to write to the browser";
+    let mut response = serde_json::to_vec(response).map_err(|_| err)?;
+    if response.len() > MAX_MESSAGE_LEN {
+        response = serde_json::to_vec(&json!({ "error": "The response is too large" }))
+            .map_err(|_| err)?;
+    }
+    writer
+        .write_all(&(response.len() as u32).to_ne_bytes())
+        .await
+        .map_err(|_| err)?;
+    writer.write_all(&response).await.map_err(|_| err)?;
+    writer.flush().await.map_err(|_| err.to_owned())
+}
+
+impl Session {
+    fn new(lock_after: Duration) -> Session {
+        Session {
+            master: None,
+            last_used: None,
+            lock_after,
+        }
+    }
+
+    async fn handle(&mut self, request: Request) -> Result<Value, Error> {
+        match request {
+            Request::Status => Ok(json!({ "unlocked": self.is_unlocked() })),
+            Request::Unlock { password } => {
+                let master = authenticate(password.to_owned())
+                    .await
+                    .map_err(|_| "Invalid master password")?;
+                self.master = Some(AuthenticatedMaster::new(master, password));
+                self.last_used = Some(Instant::now());
+                Ok(json!({ "unlocked": true }))
+            }
+            Request::Lock => {
+                self.master = None;
+                Ok(json!({ "unlocked": false }))
+            }
+            Request::Query { origin } => {
+                let origin = parse_origin(&origin)?;
+                let mut matches: Vec<(usize, entry::Model)> = api::entries::get_all_entries()
+                    .await?
+                    .into_iter()
+                    .filter_map(|e| Some((match_origin(e.url.as_deref()?, &origin)?, e)))
+                    .collect();
+                // The most specific host first
+                matches.sort_by(|a, b| b.0.cmp(&a.0).then(a.1.name.cmp(&b.1.name)));
+                let entries: Vec<Value> = matches
+                    .into_iter()
+                    .map(|(_, e)| {
+                        json!({ "id": e.id, "name": e.name, "username": e.username, "url": e.url })
+                    })
+                    .collect();
+                Ok(json!({ "entries": entries, "unlocked": self.is_unlocked() }))
+            }
+            Request::Credentials { origin, entry } => {
+                let origin = parse_origin(&origin)?;
+                let master = self.unlocked()?;
+                let entry = api::entries::get_entry(entry).await?;
+                entry
+                    .url
+                    .as_deref()
+                    .and_then(|url| match_origin(url, &origin))
+                    .ok_or("The entry does not match the origin")?;
+
+                let password = api::crypto::decrypt_password(
+                    master.password.to_owned(),
+                    entry.password.to_owned(),
+                    entry.id.to_owned(),
+                    master.master.id.to_owned(),
+                )?;
+                let totp = api::totp::decrypt_totp(&entry, &master.master, master.password)?
+                    .map(|totp| api::totp::generate_code(&totp).map(|(code, _)| code))
+                    .transpose()?;
+                Ok(json!({ "username": entry.username, "password": password, "totp": totp }))
+            }
+        }
+    }
+
+    fn is_unlocked(&self) -> bool {
+        self.master.is_some()
+            && self
+                .last_used
+                .is_some_and(|t| t.elapsed() <= self.lock_after)
+    }
+
+    fn unlocked(&mut self) -> Result<AuthenticatedMaster, Error> {
+        if !self.is_unlocked() {
+            self.master = None;
+            return Err("Locked, unlock with the master password first".to_owned());
+        }
+        self.last_used = Some(Instant::now());
+        self.master.clone().ok_or("Locked".to_owned())
+    }
+}
+
+fn parse_origin(origin: &str) -> Result<Url, Error> {
+    let url = Url::parse(origin).map_err(|_| format!("Invalid origin {}", origin))?;
+    match (url.scheme(), url.host()) {
+        ("https" | "http", Some(_)) => Ok(url),
+        _ => Err(format!("Unsupported origin {}", origin)),
+    }
+}
+
+/// Whether an entry should be offered on a page, returning the length of the matched host
+/// so that the most specific entries can be listed first.
+///
+/// The scheme and port must be the same, except that an `http` entry is offered on the
+/// `https` version of the site. The host must be the same, or the page may be on a
+/// subdomain of the entry host (`accounts.example.com` for `example.com`), as long as both are
+/// within the same registrable domain: an entry for `github.io` is not offered on `user.github.io`.
+fn match_origin(entry_url: &str, origin: &Url) -> Option<usize> {
+    let url = Url::parse(entry_url).ok()?;
+    let scheme_matches = if url.scheme() == origin.scheme() {
+        url.port_or_known_default() == origin.port_or_known_default()
+    } else {
+        url.scheme() == "http"
+            && origin.scheme() == "https"
+            && url.port().is_none()
+            && origin.port().is_none()
+    };
+    if !scheme_matches {
+        return None;
+    }
+
+    let (Some(Host::Domain(host)), Some(Host::Domain(origin_host))) = (url.host(), origin.host())
+    else {
+        // IP addresses only match exactly
+        return (url.host().is_some() && url.host() == origin.host())
+            .then(|| url.host_str().map_or(0, |h| h.len()));
+    };
+    let host = host.trim_end_matches('.').to_lowercase();
+    let origin_host = origin_host.trim_end_matches('.').to_lowercase();
+    if host == origin_host {
+        return Some(host.len());
+    }
+    let is_subdomain = origin_host.ends_with(&format!(".{}", host));
+    let registrable = psl::domain_str(&origin_host)?;
+    (is_subdomain && host.len() >= registrable.len()).then_some(host.len())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn frame(message: &[u8]) -> Vec<u8> {
+        let mut framed = (message.len() as u32).to_ne_bytes().to_vec();
+        framed.extend(message);
+        framed
+    }
+
+    /// Sends the messages of a scripted browser and returns the responses
+    async fn exchange(input: Vec<u8>) -> (Result<(), Error>, Vec<Value>) {
+        let mut output = Vec::new();
+        let result = serve(
+            input.as_slice(),
+            &mut output,
+            Session::new(Duration::from_secs(60)),
+        )
+        .await;
+        let mut responses = Vec::new();
+        let mut rest = output.as_slice();
+        while let Some(message) = read_message(&mut rest).await.unwrap() {
+            responses.push(serde_json::from_slice(&message).unwrap());
+        }
+        (result, responses)
+    }
+
+    #[tokio::test]
+    async fn messages_are_length_prefixed() {
+        let mut input = frame(br#"{"type":"status","id":1}"#);
+        input.extend(frame(br#"{"type":"lock","id":"two"}"#));
+        let (result, responses) = exchange(input).await;
+        assert!(result.is_ok());
+        assert_eq!(
+            responses,
+            vec![
+                json!({ "unlocked": false, "id": 1 }),
+                json!({ "unlocked": false, "id": "two" }),
+            ]
+        );
+    }
+
+    #[tokio::test]
+    async fn malformed_messages_are_answered_with_an_error() {
+        let mut input = frame(b"not json");
+        input.extend(frame(br#"{"type":"unknown","id":1}"#));
+        input.extend(frame(
+            br#"{"type":"credentials","origin":"ftp://example.com","entry":"1"}"#,
+        ));
+        input.extend(frame(
+            br#"{"type":"credentials","origin":"https://example.com","entry":"1"}"#,
+        ));
+        input.extend(frame(br#"{"type":"status"}"#));
+        let (result, responses) = exchange(input).await;
+        assert!(result.is_ok());
+        assert_eq!(responses.len(), 5);
+        let error = |index: usize| responses[index]["error"].as_str().unwrap().to_owned();
+        assert!(error(0).starts_with("Invalid request"));
+        assert!(error(1).starts_with("Invalid request"));
+        assert_eq!(responses[1]["id"], Value::Null);
+        assert!(error(2).starts_with("Unsupported origin"));
+        assert!(error(3).starts_with("Locked"));
+        assert_eq!(responses[4], json!({ "unlocked": false, "id": null }));
+    }
+
+    #[tokio::test]
+    async fn oversized_and_truncated_messages_end_the_session() {
+        let input = ((MAX_REQUEST_LEN + 1) as u32).to_ne_bytes().to_vec();
+        let (result, responses) = exchange(input).await;
+        assert!(result.is_err());
+        assert!(responses.is_empty());
+
+        let mut input = 10u32.to_ne_bytes().to_vec();
+        input.extend(b"{}");
+        let (result, responses) = exchange(input).await;
+        assert!(result.is_err());
+        assert!(responses.is_empty());
+    }
+
+    fn matches(entry_url: &str, origin: &str) -> bool {
+        match_origin(entry_url, &parse_origin(origin).unwrap()).is_some()
+    }
+
+    #[test]
+    fn entries_match_their_site_and_its_subdomains() {
+        assert!(matches("https://example.com/login", "https://example.com"));
+        assert!(matches("https://Example.com.", "https://example.com"));
+        assert!(matches(
+            "https://example.com",
+            "https://accounts.example.com"
+        ));
+        assert!(matches("http://example.com", "https://example.com"));
+        assert!(matches("https://127.0.0.1:8080", "https://127.0.0.1:8080"));
+        assert_eq!(
+            match_origin(
+                "https://accounts.example.com",
+                &parse_origin("https://accounts.example.com").unwrap()
+            ),
+            Some("accounts.example.com".len())
+        );
+    }
+
+    #[test]
+    fn entries_do_not_match_other_sites() {
+        assert!(!matches(
+            "https://accounts.example.com",
+            "https://example.com"
+        ));
+        assert!(!matches(
+            "https://example.com",
+            "https://example.com.evil.com"
+        ));
+        assert!(!matches("https://example.com", "https://evilexample.com"));
+        assert!(!matches("https://example.com", "https://example.co"));
+        assert!(!matches("https://example.com", "http://example.com"));
+        assert!(!matches("https://example.com", "https://example.com:8443"));
+        assert!(!matches("https://127.0.0.1", "https://127.0.0.2"));
+        assert!(!matches("not a url", "https://example.com"));
+    }
+
+    #[test]
+    fn entries_on_public_suffixes_do_not_match_other_registrants() {
+        assert!(!matches("https://github.io", "https://user.github.io"));
+        assert!(!matches("https://co.uk", "https://example.co.uk"));
+        assert!(matches("https://user.github.io", "https://user.github.io"));
+        assert!(matches(
+            "https://example.co.uk",
+            "https://www.example.co.uk"
+        ));
+    }
+
+    #[test]
+    fn only_web_origins_are_accepted() {
+        assert!(parse_origin("https://example.com").is_ok());
+        assert!(parse_origin("chrome-extension://abcdef").is_err());
+        assert!(parse_origin("file:///etc/passwd").is_err());
+        assert!(parse_origin("example.com").is_err());
+    }
+}
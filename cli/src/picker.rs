@@ -0,0 +1,210 @@
+use std::io::{self, Stdout, Write};
+
+use api::error::Error;
+use crossterm::{
+    cursor::{MoveToColumn, MoveUp},
+    event::{self, Event, KeyCode, KeyEvent, KeyEventKind, KeyModifiers},
+    queue,
+    style::{Attribute, Print, SetAttribute},
+    terminal::{self, Clear, ClearType},
+};
+
+const MAX_VISIBLE: usize = 10;
+
+/// Interactive fuzzy finder drawn inline below the cursor.
+/// Returns the index (in `items`) of the chosen item, or `None` if cancelled.
+pub fn pick(prompt: &str, items: &[String]) -> Result<Option<usize>, Error> {
+    let err = "Failed to draw the entry picker";
+    terminal::enable_raw_mode().map_err(|_| err)?;
+    let mut out = io::stdout();
+    let result = run(&mut out, prompt, items);
+    queue!(out, MoveToColumn(0), Clear(ClearType::FromCursorDown)).ok();
+    out.flush().ok();
+    terminal::disable_raw_mode().map_err(|_| err)?;
+    result.map_err(|_| err.to_owned())
+}
+
+fn run(out: &mut Stdout, prompt: &str, items: &[String]) -> io::Result<Option<usize>> {
+    let mut query = String::new();
+    let mut selected: usize = 0;
+    let mut offset: usize = 0;
+    loop {
+        let matches = filter(&query, items);
+        selected = selected.min(matches.len().saturating_sub(1));
+        if selected < offset {
+            offset = selected;
+        } else if selected >= offset + MAX_VISIBLE {
+            offset = selected + 1 - MAX_VISIBLE;
+        }
+        draw(out, prompt, &query, items, &matches, selected, offset)?;
+
+        let Event::Key(KeyEvent {
+            code,
+            modifiers,
+            kind: KeyEventKind::Press,
+            ..
+        }) = event::read()?
+        else {
+            continue;
+        };
+        let ctrl = modifiers.contains(KeyModifiers::CONTROL);
+        match code {
+            KeyCode::Enter => return Ok(matches.get(selected).copied()),
+            KeyCode::Esc => return Ok(None),
+            KeyCode::Char('c') | KeyCode::Char('d') if ctrl => return Ok(None),
+            KeyCode::Up => selected = selected.saturating_sub(1),
+            KeyCode::Char('p') | KeyCode::Char('k') if ctrl => {
+                selected = selected.saturating_sub(1)
+            }
+            KeyCode::Down | KeyCode::Tab => selected += 1,
+            KeyCode::Char('n') | KeyCode::Char('j') if ctrl => selected += 1,
+            KeyCode::Char('u') if ctrl => query.clear(),
+            KeyCode::Backspace => {
+                query.pop();
+            }
+            KeyCode::Char(c) => {
+                query.push(c);
+                selected = 0;
+            }
+            _ => {}
+        }
+    }
+}
+
+fn draw(
+    out: &mut Stdout,
+    prompt: &str,
+    query: &str,
+    items: &[String],
+    matches: &[usize],
+    selected: usize,
+    offset: usize,
+) -> io::Result<()> {
+    let width = terminal::size().map(|(w, _)| w as usize).unwrap_or(80);
+    queue!(
+        out,
+        MoveToColumn(0),
+        Clear(ClearType::FromCursorDown),
+        Print(format!(
+            "{}{}  ({}/{})",
+            prompt,
+            query,
+            matches.len(),
+            items.len()
+        ))
+    )?;
+
+    let visible: Vec<&usize> = matches.iter().skip(offset).take(MAX_VISIBLE).collect();
+    for (i, index) in visible.iter().enumerate() {
+        let line: String = items[**index]
+            .chars()
+            .take(width.saturating_sub(3))
+            .collect();
+        queue!(out, Print("\r\n"))?;
+        if offset + i == selected {
+            queue!(
+                out,
+                SetAttribute(Attribute::Reverse),
+                Print(format!("> {}", line)),
+                SetAttribute(Attribute::Reset)
+            )?;
+        } else {
+            queue!(out, Print(format!("  {}", line)))?;
+        }
+    }
+
+    if !visible.is_empty() {
+        queue!(out, MoveUp(visible.len() as u16))?;
+    }
+    let column = (prompt.chars().count() + query.chars().count()).min(u16::MAX as usize);
+    queue!(out, MoveToColumn(column as u16))?;
+    out.flush()
+}
+
+/// Indices of the items matching `query`, best match first
+pub fn filter(query: &str, items: &[String]) -> Vec<usize> {
+    let mut scored: Vec<(i64, usize)> = items
+        .iter()
+        .enumerate()
+        .filter_map(|(index, item)| score(query, item).map(|s| (s, index)))
+        .collect();
+    scored.sort_by(|a, b| b.0.cmp(&a.0).then(a.1.cmp(&b.1)));
+    scored.into_iter().map(|(_, index)| index).collect()
+}
+
+/// Case insensitive subsequence match. Consecutive characters and characters
+/// at the start of a word score higher, gaps between matched characters score lower.
+fn score(query: &str, item: &str) -> Option<i64> {
+    let item: Vec<char> = item.to_lowercase().chars().collect();
+    let mut total: i64 = 0;
+    let mut position: usize = 0;
+    let mut previous: Option<usize> = None;
+    for q in query.to_lowercase().chars().filter(|c| !c.is_whitespace()) {
+        let found = position + item[position..].iter().position(|c| *c == q)?;
+        total += 1;
+        if previous.is_some_and(|p| p + 1 == found) {
+            total += 5;
+        } else if let Some(p) = previous {
+            total -= (found - p).min(10) as i64;
+        }
+        if found == 0 || !item[found - 1].is_alphanumeric() {
+            total += 3;
+        }
+        previous = Some(found);
+        position = found + 1;
+    }
+    Some(total)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn items(names: &[&str]) -> Vec<String> {
+        names.iter().map(|name| name.to_string()).collect()
+    }
+
+    #[test]
+    fn score_prefers_prefix_matches() {
+        assert!(score("git", "github") > score("git", "digit"));
+        assert_eq!(filter("git", &items(&["digit", "github"])), vec![1, 0]);
+    }
+
+    #[test]
+    fn score_prefers_word_boundaries() {
+        assert!(score("gh", "git-hub") > score("gh", "github"));
+        assert!(score("mail", "work mail") > score("mail", "hotmail"));
+    }
+
+    #[test]
+    fn score_prefers_consecutive_characters() {
+        assert!(score("ab", "xabx") > score("ab", "xaxb"));
+        assert_eq!(filter("ab", &items(&["xaxb", "xabx"])), vec![1, 0]);
+    }
+
+    #[test]
+    fn score_ignores_case_and_whitespace() {
+        assert_eq!(score("GIT", "github"), score("git", "github"));
+        assert_eq!(score("git", "GitHub"), score("git", "github"));
+        assert_eq!(score("g h", "github"), score("gh", "github"));
+    }
+
+    #[test]
+    fn score_rejects_non_matches() {
+        assert_eq!(score("z", "github"), None);
+        // Characters must appear in the same order
+        assert_eq!(score("hg", "github"), None);
+        assert_eq!(score("githubs", "github"), None);
+        assert_eq!(
+            filter("xyz", &items(&["github", "gitlab"])),
+            Vec::<usize>::new()
+        );
+    }
+
+    #[test]
+    fn filter_keeps_the_order_of_equal_scores() {
+        let names = items(&["bank", "github", "gitlab"]);
+        assert_eq!(filter("", &names), vec![0, 1, 2]);
+        assert_eq!(filter("git", &names), vec![1, 2]);
+    }
+}
@@ -0,0 +1,273 @@
+use std::{fmt::Display, str::FromStr};
+
+use api::{configuration, crypto, error::Error};
+use model::entities::entry;
+use percent_encoding::percent_decode_str;
+
+use crate::{
+    entries::find_entry,
+    master::{prompt_authenticate, AuthenticatedMaster},
+    util::PrintError,
+};
+
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
+pub enum Field {
+    Name,
+    Description,
+    Username,
+    Url,
+    Password,
+    Totp,
+}
+
+impl Field {
+    /// Whether reading the field requires the master password
+    pub fn is_secret(&self) -> bool {
+        matches!(self, Field::Password | Field::Totp)
+    }
+}
+
+impl FromStr for Field {
+    type Err = Error;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s.trim().to_lowercase().as_str() {
+            "name" => Ok(Field::Name),
+            "description" => Ok(Field::Description),
+            "username" => Ok(Field::Username),
+            "url" => Ok(Field::Url),
+            "password" => Ok(Field::Password),
+            "totp" => Ok(Field::Totp),
+            _ => Err(format!(
+                "Unknown field {}, expected one of name, description, username, url, password or totp",
+                s
+            )),
+        }
+    }
+}
+
+impl Display for Field {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        let name = match self {
+            Field::Name => "name",
+            Field::Description => "description",
+            Field::Username => "username",
+            Field::Url => "url",
+            Field::Password => "password",
+            Field::Totp => "totp",
+        };
+        write!(f, "{}", name)
+    }
+}
+
+/// A reference to one field of an entry. The entry is given the same way as
+/// with `-n` (number, name or ID).
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct Reference {
+    /// `None` refers to the current vault
+    pub vault: Option<String>,
+    pub entry: String,
+    pub field: Field,
+}
+
+pub const URI_SCHEME: &str = "mypass://";
+
+impl Reference {
+    /// Parses `<entry>:<field>`
+    pub fn parse(reference: &str) -> Result<Reference, Error> {
+        let (entry, field) = reference.rsplit_once(':').ok_or(format!(
+            "Invalid reference {}, expected <entry>:<field>",
+            reference
+        ))?;
+        if entry.is_empty() {
+            return Err(format!(
+                "Invalid reference {}, the entry is missing",
+                reference
+            ));
+        }
+        Ok(Reference {
+            vault: None,
+            entry: entry.to_owned(),
+            field: field.parse()?,
+        })
+    }
+
+    /// Parses `mypass://<vault>/<entry>/<field>`, or the shorthand `mypass://<entry>/<field>`
+    /// for the current vault. The segments are percent-decoded, so a `/` in a name must be
+    /// written `%2F` (and a `%` written `%25`).
+    pub fn parse_uri(uri: &str) -> Result<Reference, Error> {
+        let invalid = || {
+            format!(
+                "Invalid reference {}, expected {}<vault>/<entry>/<field> (with / in names written %2F)",
+                uri, URI_SCHEME
+            )
+        };
+        let path = uri.strip_prefix(URI_SCHEME).ok_or_else(invalid)?;
+        let segments = path
+            .split('/')
+            .map(|segment| {
+                percent_decode_str(segment)
+                    .decode_utf8()
+                    .map(|s| s.into_owned())
+                    .map_err(|_| invalid())
+            })
+            .collect::<Result<Vec<String>, Error>>()?;
+        let (vault, entry, field) = match segments.as_slice() {
+            [entry, field] => (None, entry, field),
+            [vault, entry, field] if !vault.is_empty() => (Some(vault), entry, field),
+            _ => return Err(invalid()),
+        };
+        if entry.is_empty() {
+            return Err(invalid());
+        }
+        Ok(Reference {
+            vault: vault.map(|v| v.to_owned()),
+            entry: entry.to_owned(),
+            field: field.parse()?,
+        })
+    }
+}
+
+/// Resolves the values of the references, prompting for the master password at most once.
+/// The references are resolved in the vault they name, or in the current vault.
+pub async fn resolve(references: &[Reference]) -> Result<Vec<String>, ()> {
+    let mut vaults: Vec<&String> = references.iter().filter_map(|r| r.vault.as_ref()).collect();
+    vaults.sort();
+    vaults.dedup();
+    match vaults.as_slice() {
+        [] => {}
+        [vault] => {
+            let known = configuration::list_vaults().print_err()?;
+            if !known.iter().any(|v| &&v.name == vault) {
+                eprintln!("Unknown vault {}", vault);
+                return Err(());
+            }
+            configuration::select_vault(vault.to_string());
+        }
+        _ => {
+            eprintln!("References to several vaults cannot be resolved together");
+            return Err(());
+        }
+    }
+
+    let master = if references.iter().any(|r| r.field.is_secret()) {
+        Some(prompt_authenticate().await?)
+    } else {
+        None
+    };
+
+    let mut values = Vec::new();
+    for reference in references {
+        let (_, entry) = find_entry(&reference.entry).await?;
+        values.push(field_value(&entry, reference.field, master.as_ref()).print_err()?);
+    }
+    Ok(values)
+}
+
+pub fn field_value(
+    entry: &entry::Model,
+    field: Field,
+    master: Option<&AuthenticatedMaster>,
+) -> Result<String, Error> {
+    let master = || master.ok_or("Master password is required".to_owned());
+    let value = match field {
+        Field::Name => Some(entry.name.to_owned()),
+        Field::Description => entry.description.to_owned(),
+        Field::Username => entry.username.to_owned(),
+        Field::Url => entry.url.to_owned(),
+        Field::Password => {
+            let master = master()?;
+            Some(crypto::decrypt_password(
+                master.password.to_owned(),
+                entry.password.to_owned(),
+                entry.id.to_owned(),
+                master.master.id.to_owned(),
+            )?)
+        }
+        Field::Totp => {
+            let master = master()?;
+            api::totp::decrypt_totp(entry, &master.master, master.password.to_owned())?
+                .map(|totp| api::totp::generate_code(&totp).map(|(code, _)| code))
+                .transpose()?
+        }
+    };
+    value.ok_or(format!("Entry {} has no {}", entry.name, field))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn reference(vault: Option<&str>, entry: &str, field: Field) -> Reference {
+        Reference {
+            vault: vault.map(|v| v.to_owned()),
+            entry: entry.to_owned(),
+            field,
+        }
+    }
+
+    #[test]
+    fn parse_reads_entry_and_field() {
+        assert_eq!(
+            Reference::parse("db:password").unwrap(),
+            reference(None, "db", Field::Password)
+        );
+        // The field follows the last colon, and is case-insensitive
+        assert_eq!(
+            Reference::parse("host:5432:URL").unwrap(),
+            reference(None, "host:5432", Field::Url)
+        );
+        assert_eq!(
+            Reference::parse("3:totp").unwrap(),
+            reference(None, "3", Field::Totp)
+        );
+        assert!(Reference::parse("db").is_err());
+        assert!(Reference::parse(":password").is_err());
+        assert_eq!(
+            Reference::parse("db:pin").unwrap_err(),
+            "Unknown field pin, expected one of name, description, username, url, password or totp"
+        );
+    }
+
+    #[test]
+    fn parse_uri_reads_both_forms() {
+        assert_eq!(
+            Reference::parse_uri("mypass://db/password").unwrap(),
+            reference(None, "db", Field::Password)
+        );
+        assert_eq!(
+            Reference::parse_uri("mypass://work/db/username").unwrap(),
+            reference(Some("work"), "db", Field::Username)
+        );
+        assert!(Reference::parse_uri("mypass://db/pin").is_err());
+        for invalid in [
+            "db/password",
+            "https://db/password",
+            "mypass://password",
+            "mypass:///password",
+            "mypass:///db/password",
+            "mypass://work//password",
+            "mypass://a/b/c/password",
+        ] {
+            assert!(Reference::parse_uri(invalid).is_err(), "{}", invalid);
+        }
+    }
+
+    #[test]
+    fn parse_uri_decodes_names() {
+        assert_eq!(
+            Reference::parse_uri("mypass://work/ci%2Fdeploy%20key/password").unwrap(),
+            reference(Some("work"), "ci/deploy key", Field::Password)
+        );
+        // A `/` in the name of an entry of the current vault is not taken for a vault
+        assert_eq!(
+            Reference::parse_uri("mypass://ci%2Fdeploy/password").unwrap(),
+            reference(None, "ci/deploy", Field::Password)
+        );
+        assert_eq!(
+            Reference::parse_uri("mypass://my%20vault/100%25/name").unwrap(),
+            reference(Some("my vault"), "100%", Field::Name)
+        );
+        assert!(Reference::parse_uri("mypass://db%FF/password").is_err());
+    }
+}
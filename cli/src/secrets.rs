@@ -0,0 +1,308 @@
+use std::{
+    fs,
+    io::{self, Read, Write},
+    process::ExitStatus,
+};
+
+use api::error::Error;
+
+use tokio::{
+    process::Command,
+    signal::unix::{signal, SignalKind},
+};
+
+use crate::{
+    reference::{self, Reference, URI_SCHEME},
+    util::PrintError,
+};
+
+/// Signals received by mypass that are passed on to the child process. The signals of the
+/// terminal (e.g. SIGINT on Ctrl-C) are not, as they already reach the child, which is in
+/// the same process group.
+const FORWARDED_SIGNALS: [i32; 4] = [libc::SIGTERM, libc::SIGHUP, libc::SIGUSR1, libc::SIGUSR2];
+
+/// Signals of the terminal that mypass ignores while the child runs, so that it exits
+/// with the child
+const TERMINAL_SIGNALS: [i32; 2] = [libc::SIGINT, libc::SIGQUIT];
+
+/// Runs `command` with the referenced secrets set as environment variables
+/// and returns the exit code of the command
+pub async fn run_with_secrets(env: Vec<String>, command: Vec<String>) -> Result<i32, ()> {
+    let (program, args) = command
+        .split_first()
+        .ok_or_else(|| eprintln!("A command to run is required"))?;
+
+    let mut names: Vec<String> = Vec::new();
+    let mut references: Vec<Reference> = Vec::new();
+    for var in env {
+        let (name, reference) = var
+            .split_once('=')
+            .filter(|(name, _)| !name.is_empty())
+            .ok_or(format!(
+                "Invalid variable {}, expected NAME=<entry>:<field>",
+                var
+            ))
+            .print_err()?;
+        names.push(name.to_owned());
+        references.push(Reference::parse(reference).print_err()?);
+    }
+    let values = reference::resolve(&references).await?;
+
+    let mut command = Command::new(program);
+    command.args(args).envs(names.into_iter().zip(values));
+    let status = spawn_forwarding_signals(command).await.print_err()?;
+    Ok(exit_code(status))
+}
+
+/// Runs the command until it exits, passing on the forwarded signals
+async fn spawn_forwarding_signals(mut command: Command) -> Result<ExitStatus, Error> {
+    let program = command
+        .as_std()
+        .get_program()
+        .to_string_lossy()
+        .into_owned();
+    let listen = |signum: i32| {
+        signal(SignalKind::from_raw(signum)).map_err(|_| "Failed to listen for signals".to_owned())
+    };
+    // Listening before spawning, so that a signal received in between is not fatal to mypass
+    // and is still passed on once the child runs
+    let mut streams = Vec::new();
+    for signum in FORWARDED_SIGNALS {
+        streams.push((signum, listen(signum)?));
+    }
+    // Listening is enough for them not to terminate mypass
+    let _terminal_signals = TERMINAL_SIGNALS
+        .into_iter()
+        .map(listen)
+        .collect::<Result<Vec<_>, _>>()?;
+
+    let mut child = command
+        .spawn()
+        .map_err(|e| format!("Failed to run {}: {}", program, e))?;
+    let pid = child
+        .id()
+        .ok_or_else(|| format!("Failed to run {}", program))? as i32;
+
+    let forwarders: Vec<_> = streams
+        .into_iter()
+        .map(|(signum, mut stream)| {
+            tokio::spawn(async move {
+                while stream.recv().await.is_some() {
+                    // SAFETY: kill has no memory safety requirements, the pid is our own child
+                    unsafe {
+                        libc::kill(pid, signum);
+                    }
+                }
+            })
+        })
+        .collect();
+
+    let status = child
+        .wait()
+        .await
+        .map_err(|_| "Failed to wait for the command to exit".to_owned());
+    forwarders.iter().for_each(|f| f.abort());
+    status
+}
+
+/// Exit code following the shell convention (128 + signal number when killed by a signal)
+fn exit_code(status: ExitStatus) -> i32 {
+    use std::os::unix::process::ExitStatusExt;
+    status
+        .code()
+        .or_else(|| status.signal().map(|s| 128 + s))
+        .unwrap_or(1)
+}
+
+/// Replaces every `{{ mypass://<entry>/<field> }}` in the template with the referenced value.
+/// Other `{{ ... }}` expressions are left untouched.
+pub async fn inject(input: Option<String>, output: Option<String>) -> Result<(), ()> {
+    let template = read_template(input).print_err()?;
+    let segments = parse_template(&template).print_err()?;
+    let references: Vec<Reference> = segments
+        .iter()
+        .filter_map(|s| match s {
+            Segment::Reference(r) => Some(r.to_owned()),
+            Segment::Text(_) => None,
+        })
+        .collect();
+    let mut values = reference::resolve(&references).await?.into_iter();
+
+    let mut rendered = String::new();
+    for segment in segments {
+        match segment {
+            Segment::Text(text) => rendered.push_str(text),
+            Segment::Reference(_) => rendered.push_str(&values.next().unwrap_or_default()),
+        }
+    }
+
+    match output {
+        Some(path) => {
+            api::entry_transfer::write_private_file(&path, rendered.as_bytes()).print_err()
+        }
+        None => io::stdout()
+            .write_all(rendered.as_bytes())
+            .map_err(|_| eprintln!("Failed to write output")),
+    }
+}
+
+#[derive(Debug, PartialEq)]
+enum Segment<'a> {
+    Text(&'a str),
+    Reference(Reference),
+}
+
+fn parse_template(template: &str) -> Result<Vec<Segment<'_>>, Error> {
+    let mut segments = Vec::new();
+    let mut rest = template;
+    while let Some(start) = rest.find("{{") {
+        let Some(len) = rest[start..].find("}}") else {
+            if rest[start + 2..].trim_start().starts_with(URI_SCHEME) {
+                return Err(format!(
+                    "Unterminated reference {}, expected }}}}",
+                    rest[start..].lines().next().unwrap_or_default()
+                ));
+            }
+            break;
+        };
+        let end = start + len + 2;
+        let expression = rest[start + 2..start + len].trim();
+        if expression.starts_with(URI_SCHEME) {
+            segments.push(Segment::Text(&rest[..start]));
+            segments.push(Segment::Reference(Reference::parse_uri(expression)?));
+        } else {
+            segments.push(Segment::Text(&rest[..end]));
+        }
+        rest = &rest[end..];
+    }
+    segments.push(Segment::Text(rest));
+    Ok(segments)
+}
+
+fn read_template(input: Option<String>) -> Result<String, Error> {
+    let mut template = String::new();
+    match input {
+        Some(path) => {
+            template = fs::read_to_string(&path).map_err(|_| format!("Failed to read {}", path))?
+        }
+        None => {
+            io::stdin()
+                .read_to_string(&mut template)
+                .map_err(|_| "Failed to read template from stdin")?;
+        }
+    }
+    Ok(template)
+}
+
+/// Prints the raw value of a `mypass://` reference, without any formatting or trailing newline
+pub async fn read(uri: String) -> Result<(), ()> {
+    let reference = Reference::parse_uri(&uri).print_err()?;
+    let value = reference::resolve(&[reference]).await?.concat();
+    let mut stdout = io::stdout();
+    stdout
+        .write_all(value.as_bytes())
+        .and_then(|_| stdout.flush())
+        .map_err(|_| eprintln!("Failed to write output"))
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::reference::Field;
+
+    use super::*;
+
+    fn reference(vault: Option<&str>, entry: &str, field: Field) -> Segment<'static> {
+        Segment::Reference(Reference {
+            vault: vault.map(|v| v.to_owned()),
+            entry: entry.to_owned(),
+            field,
+        })
+    }
+
+    fn shell(script: &str) -> Command {
+        let mut command = Command::new("sh");
+        command.args(["-c", script]);
+        command
+    }
+
+    // A single test, as the signals are sent to the test process, and would be passed on
+    // to the commands of every test running at the same time
+    #[tokio::test]
+    async fn signals_of_the_terminal_are_not_passed_on() {
+        // The commands signal mypass, their parent
+        let status = spawn_forwarding_signals(shell(
+            "trap 'exit 42' TERM; kill -TERM $PPID; for i in $(seq 100); do sleep 0.05; done; exit 1",
+        ))
+        .await
+        .unwrap();
+        assert_eq!(status.code(), Some(42));
+
+        // The terminal sends SIGINT to the whole process group, which the command then
+        // receives once. mypass outlives it, to exit with the command.
+        let status = spawn_forwarding_signals(shell(
+            "trap 'exit 42' INT; kill -INT $PPID; sleep 0.5; exit 3",
+        ))
+        .await
+        .unwrap();
+        assert_eq!(status.code(), Some(3));
+    }
+
+    #[test]
+    fn exit_code_follows_the_shell_convention() {
+        use std::os::unix::process::ExitStatusExt;
+        assert_eq!(exit_code(ExitStatus::from_raw(0)), 0);
+        assert_eq!(exit_code(ExitStatus::from_raw(3 << 8)), 3);
+        assert_eq!(exit_code(ExitStatus::from_raw(libc::SIGKILL)), 137);
+    }
+
+    #[test]
+    fn parse_template_finds_every_reference_of_a_line() {
+        let segments = parse_template(
+            "url: postgres://{{ mypass://db/username }}:{{mypass://prod/db/password}}@db\n",
+        )
+        .unwrap();
+        assert_eq!(
+            segments,
+            vec![
+                Segment::Text("url: postgres://"),
+                reference(None, "db", Field::Username),
+                Segment::Text(":"),
+                reference(Some("prod"), "db", Field::Password),
+                Segment::Text("@db\n"),
+            ]
+        );
+    }
+
+    #[test]
+    fn parse_template_keeps_text_without_references() {
+        let template = "name: app\nreplicas: 3\n";
+        assert_eq!(
+            parse_template(template).unwrap(),
+            vec![Segment::Text(template)]
+        );
+        // Other template expressions are left as they are
+        let template = "image: {{ .Values.image }}\nbraces: {{ and }}";
+        assert_eq!(
+            parse_template(template).unwrap(),
+            vec![
+                Segment::Text("image: {{ .Values.image }}"),
+                Segment::Text("\nbraces: {{ and }}"),
+                Segment::Text(""),
+            ]
+        );
+    }
+
+    #[test]
+    fn parse_template_refuses_unterminated_references() {
+        assert_eq!(
+            parse_template("password: {{ mypass://db/password\nuser: x").unwrap_err(),
+            "Unterminated reference {{ mypass://db/password, expected }}"
+        );
+        // An unterminated `{{` that is not a reference is kept as text
+        assert_eq!(
+            parse_template("a {{ b").unwrap(),
+            vec![Segment::Text("a {{ b")]
+        );
+        assert!(parse_template("{{ mypass://db/secret }}").is_err());
+    }
+}
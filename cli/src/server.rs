@@ -0,0 +1,553 @@
+use std::{
+    fs,
+    path::PathBuf,
+    sync::Arc,
+    time::{Duration, Instant},
+};
+
+use api::error::Error;
+use axum::{
+    extract::{Path, Query, State},
+    http::{header, HeaderMap, StatusCode},
+    response::{IntoResponse, Response},
+    routing::{get, post},
+    Json, Router,
+};
+use clap::ValueEnum;
+use model::entities::entry;
+use serde::{Deserialize, Serialize};
+use serde_json::{json, Value};
+use tokio::{net::TcpListener, sync::Mutex};
+
+use crate::{
+    entries::lookup_entry,
+    master::{prompt_authenticate, AuthenticatedMaster},
+    picker,
+    reference::{field_value, Field},
+    util::{self, PrintError},
+};
+
+/// What the clients of the server are allowed to do
+#[derive(Clone, Copy, PartialEq, Eq, Debug, ValueEnum, Serialize)]
+#[serde(rename_all = "lowercase")]
+pub enum Scope {
+    /// List, search and read the fields that are not secret
+    Read,
+    /// Read passwords and TOTP codes
+    Reveal,
+    /// Create and update entries
+    Write,
+}
+
+struct Server {
+    /// Required as bearer token when listening on localhost. A Unix socket is
+    /// protected by its file permissions instead.
+    token: Option<String>,
+    scopes: Vec<Scope>,
+    lock_after: Duration,
+    session: Mutex<Session>,
+}
+
+struct Session {
+    master: Option<AuthenticatedMaster>,
+    last_used: Instant,
+}
+
+type Shared = Arc<Server>;
+
+struct ApiError(StatusCode, Error);
+
+impl IntoResponse for ApiError {
+    fn into_response(self) -> Response {
+        (self.0, Json(json!({ "error": self.1 }))).into_response()
+    }
+}
+
+impl Server {
+    /// Checks the bearer token and that the server was started with `scope`
+    fn authorize(&self, headers: &HeaderMap, scope: Option<Scope>) -> Result<(), ApiError> {
+        if let Some(token) = &self.token {
+            let given = headers
+                .get(header::AUTHORIZATION)
+                .and_then(|h| h.to_str().ok())
+                .and_then(|h| h.strip_prefix("Bearer "));
+            if !given.is_some_and(|given| constant_time_eq(given.as_bytes(), token.as_bytes())) {
+                return Err(ApiError(
+                    StatusCode::UNAUTHORIZED,
+                    "Missing or invalid bearer token".to_owned(),
+                ));
+            }
+        }
+        match scope {
+            Some(scope) if !self.scopes.contains(&scope) => Err(ApiError(
+                StatusCode::FORBIDDEN,
+                format!(
+                    "The server does not grant the {} scope",
+                    scope
+                        .to_possible_value()
+                        .map_or(String::new(), |v| v.get_name().to_owned())
+                ),
+            )),
+            _ => Ok(()),
+        }
+    }
+
+    /// The master of the unlocked session. The session locks itself after
+    /// `lock_after` without a request that needed it.
+    async fn unlocked(&self) -> Result<AuthenticatedMaster, ApiError> {
+        let mut session = self.session.lock().await;
+        if session.last_used.elapsed() > self.lock_after {
+            session.master = None;
+        }
+        let master = session.master.clone().ok_or(ApiError(
+            StatusCode::LOCKED,
+            "The session is locked, unlock it with POST /v1/unlock".to_owned(),
+        ))?;
+        session.last_used = Instant::now();
+        Ok(master)
+    }
+}
+
+fn constant_time_eq(a: &[u8], b: &[u8]) -> bool {
+    a.len() == b.len() && a.iter().zip(b).fold(0, |acc, (x, y)| acc | (x ^ y)) == 0
+}
+
+pub async fn serve(
+    socket: Option<String>,
+    port: u16,
+    scopes: Vec<Scope>,
+    lock_after: Option<u64>,
+    locked: bool,
+) -> Result<(), ()> {
+    let lock_after = match lock_after {
+        Some(lock_after) => lock_after,
+        None => util::settings()?.lock.server,
+    };
+    let master = if locked {
+        None
+    } else {
+        Some(prompt_authenticate().await?)
+    };
+    let scopes = if scopes.is_empty() {
+        vec![Scope::Read, Scope::Reveal]
+    } else {
+        scopes
+    };
+    let token = socket.is_none().then(api::crypto::generate_password);
+    let server = Arc::new(Server {
+        token: token.to_owned(),
+        scopes,
+        lock_after: Duration::from_secs(lock_after * 60),
+        session: Mutex::new(Session {
+            master,
+            last_used: Instant::now(),
+        }),
+    });
+
+    let app = Router::new()
+        .route("/v1/status", get(status))
+        .route("/v1/unlock", post(unlock))
+        .route("/v1/lock", post(lock))
+        .route("/v1/entries", get(list).post(create))
+        .route("/v1/entries/{entry}", get(read).patch(update))
+        .route("/v1/entries/{entry}/{field}", get(read_field))
+        .with_state(server);
+    let shutdown = async {
+        tokio::signal::ctrl_c().await.ok();
+    };
+    let err = "The server stopped unexpectedly";
+
+    match socket {
+        Some(socket) => {
+            let path = PathBuf::from(socket);
+            let listener = util::bind_unix_socket(&path).print_err()?;
+            eprintln!(
+                "Listening on {}, press Ctrl-C to stop",
+                path.to_string_lossy()
+            );
+            let result = axum::serve(listener, app)
+                .with_graceful_shutdown(shutdown)
+                .await;
+            fs::remove_file(&path).ok();
+            result.map_err(|_| err).print_err()
+        }
+        None => {
+            let listener = TcpListener::bind(("127.0.0.1", port))
+                .await
+                .map_err(|_| format!("Failed to listen on port {}", port))
+                .print_err()?;
+            let address = listener.local_addr().map_err(|_| err).print_err()?;
+            println!(
+                "MYPASS_SERVER=http://{}; MYPASS_TOKEN={}; export MYPASS_SERVER MYPASS_TOKEN;",
+                address,
+                token.unwrap_or_default()
+            );
+            eprintln!("Listening on http://{}, press Ctrl-C to stop", address);
+            axum::serve(listener, app)
+                .with_graceful_shutdown(shutdown)
+                .await
+                .map_err(|_| err)
+                .print_err()
+        }
+    }
+}
+
+#[derive(Serialize)]
+struct EntryResponse {
+    id: String,
+    name: String,
+    description: Option<String>,
+    username: Option<String>,
+    url: Option<String>,
+    totp: bool,
+    ssh_public_key: Option<String>,
+    created_date: String,
+    modified_date: String,
+}
+
+impl From<entry::Model> for EntryResponse {
+    fn from(entry: entry::Model) -> Self {
+        EntryResponse {
+            id: entry.id,
+            name: entry.name,
+            description: entry.description,
+            username: entry.username,
+            url: entry.url,
+            totp: entry.totp.is_some(),
+            ssh_public_key: entry.ssh_public_key,
+            created_date: api::util::format_date(&entry.created_date),
+            modified_date: api::util::format_date(&entry.modified_date),
+        }
+    }
+}
+
+async fn status(State(server): State<Shared>, headers: HeaderMap) -> Result<Json<Value>, ApiError> {
+    server.authorize(&headers, None)?;
+    let session = server.session.lock().await;
+    let unlocked = session.master.is_some() && session.last_used.elapsed() <= server.lock_after;
+    Ok(Json(
+        json!({ "unlocked": unlocked, "scopes": server.scopes }),
+    ))
+}
+
+#[derive(Deserialize)]
+struct UnlockRequest {
+    password: String,
+}
+
+async fn unlock(
+    State(server): State<Shared>,
+    headers: HeaderMap,
+    Json(request): Json<UnlockRequest>,
+) -> Result<StatusCode, ApiError> {
+    server.authorize(&headers, None)?;
+    let master = api::master::authenticate_master(request.password.to_owned())
+        .await
+        .map_err(|e| ApiError(StatusCode::UNAUTHORIZED, e))?;
+    *server.session.lock().await = Session {
+        master: Some(AuthenticatedMaster::new(master, request.password)),
+        last_used: Instant::now(),
+    };
+    Ok(StatusCode::NO_CONTENT)
+}
+
+async fn lock(State(server): State<Shared>, headers: HeaderMap) -> Result<StatusCode, ApiError> {
+    server.authorize(&headers, None)?;
+    server.session.lock().await.master = None;
+    Ok(StatusCode::NO_CONTENT)
+}
+
+#[derive(Deserialize)]
+struct ListQuery {
+    /// Fuzzy search on the name, username and URL
+    query: Option<String>,
+}
+
+async fn list(
+    State(server): State<Shared>,
+    headers: HeaderMap,
+    Query(ListQuery { query }): Query<ListQuery>,
+) -> Result<Json<Vec<EntryResponse>>, ApiError> {
+    server.authorize(&headers, Some(Scope::Read))?;
+    server.unlocked().await?;
+    let entries = api::entries::get_all_entries()
+        .await
+        .map_err(|e| ApiError(StatusCode::INTERNAL_SERVER_ERROR, e))?;
+    let entries = match query {
+        Some(query) => {
+            let items: Vec<String> = entries
+                .iter()
+                .map(|e| {
+                    format!(
+                        "{} {} {}",
+                        e.name,
+                        e.username.as_deref().unwrap_or(""),
+                        e.url.as_deref().unwrap_or("")
+                    )
+                })
+                .collect();
+            picker::filter(&query, &items)
+                .into_iter()
+                .map(|i| entries[i].to_owned())
+                .collect()
+        }
+        None => entries,
+    };
+    Ok(Json(entries.into_iter().map(EntryResponse::from).collect()))
+}
+
+async fn find(reference: &str) -> Result<entry::Model, ApiError> {
+    lookup_entry(reference)
+        .await
+        .map(|(_, entry)| entry)
+        .map_err(|e| ApiError(StatusCode::NOT_FOUND, e))
+}
+
+async fn read(
+    State(server): State<Shared>,
+    headers: HeaderMap,
+    Path(reference): Path<String>,
+) -> Result<Json<EntryResponse>, ApiError> {
+    server.authorize(&headers, Some(Scope::Read))?;
+    server.unlocked().await?;
+    Ok(Json(find(&reference).await?.into()))
+}
+
+async fn read_field(
+    State(server): State<Shared>,
+    headers: HeaderMap,
+    Path((reference, field)): Path<(String, String)>,
+) -> Result<Json<Value>, ApiError> {
+    server.authorize(&headers, Some(Scope::Read))?;
+    let field: Field = field
+        .parse()
+        .map_err(|e| ApiError(StatusCode::BAD_REQUEST, e))?;
+    if field.is_secret() {
+        server.authorize(&headers, Some(Scope::Reveal))?;
+    }
+    let master = server.unlocked().await?;
+    let entry = find(&reference).await?;
+    let value = field_value(&entry, field, Some(&master))
+        .map_err(|e| ApiError(StatusCode::NOT_FOUND, e))?;
+    Ok(Json(json!({ "value": value })))
+}
+
+#[derive(Deserialize)]
+struct CreateRequest {
+    name: String,
+    description: Option<String>,
+    username: Option<String>,
+    url: Option<String>,
+    /// Generated when omitted
+    password: Option<String>,
+    totp: Option<String>,
+}
+
+async fn create(
+    State(server): State<Shared>,
+    headers: HeaderMap,
+    Json(request): Json<CreateRequest>,
+) -> Result<(StatusCode, Json<EntryResponse>), ApiError> {
+    server.authorize(&headers, Some(Scope::Write))?;
+    let master = server.unlocked().await?;
+    let bad_request = |e| ApiError(StatusCode::BAD_REQUEST, e);
+    if let Some(totp) = &request.totp {
+        api::totp::parse(totp).map_err(bad_request)?;
+    }
+
+    let mut entry = api::entries::create_entry(
+        master.password.to_owned(),
+        request.name,
+        request.description,
+        request.username,
+        match request.password {
+            Some(password) => password,
+            None => api::configuration::get_settings()
+                .map_err(bad_request)?
+                .generator
+                .generate(),
+        },
+        request.url,
+    )
+    .await
+    .map_err(bad_request)?;
+    if request.totp.is_some() {
+        entry = api::entries::set_totp(master.password, entry.id, request.totp)
+            .await
+            .map_err(bad_request)?;
+    }
+    Ok((StatusCode::CREATED, Json(entry.into())))
+}
+
+#[derive(Deserialize)]
+struct UpdateRequest {
+    name: Option<String>,
+    description: Option<String>,
+    username: Option<String>,
+    url: Option<String>,
+    password: Option<String>,
+    /// An empty string removes the TOTP secret
+    totp: Option<String>,
+}
+
+async fn update(
+    State(server): State<Shared>,
+    headers: HeaderMap,
+    Path(reference): Path<String>,
+    Json(request): Json<UpdateRequest>,
+) -> Result<Json<EntryResponse>, ApiError> {
+    server.authorize(&headers, Some(Scope::Write))?;
+    let master = server.unlocked().await?;
+    let bad_request = |e| ApiError(StatusCode::BAD_REQUEST, e);
+    let totp = request.totp.map(|t| Some(t).filter(|t| !t.is_empty()));
+    if let Some(Some(totp)) = &totp {
+        api::totp::parse(totp).map_err(bad_request)?;
+    }
+
+    let entry = find(&reference).await?;
+    let passwords = request
+        .password
+        .map(|password| (master.password.to_owned(), password));
+    let mut entry = api::entries::update_entry(
+        entry.id,
+        request.name,
+        request.description,
+        request.username,
+        request.url,
+        passwords,
+    )
+    .await
+    .map_err(bad_request)?;
+    if let Some(totp) = totp {
+        entry = api::entries::set_totp(master.password, entry.id, totp)
+            .await
+            .map_err(bad_request)?;
+    }
+    Ok(Json(entry.into()))
+}
+
+#[cfg(test)]
+mod tests {
+    use axum::http::HeaderValue;
+    use model::entities::master;
+
+    use super::*;
+
+    fn test_server(token: Option<&str>, scopes: Vec<Scope>, last_used: Instant) -> Server {
+        let master = master::Model {
+            id: "id".to_owned(),
+            name: "master".to_owned(),
+            description: None,
+            password: "hash".to_owned(),
+        };
+        Server {
+            token: token.map(|t| t.to_owned()),
+            scopes,
+            lock_after: Duration::from_secs(60),
+            session: Mutex::new(Session {
+                master: Some(AuthenticatedMaster::new(master, "password".to_owned())),
+                last_used,
+            }),
+        }
+    }
+
+    fn authorization(value: &str) -> HeaderMap {
+        let mut headers = HeaderMap::new();
+        headers.insert(header::AUTHORIZATION, HeaderValue::from_str(value).unwrap());
+        headers
+    }
+
+    fn status(result: Result<(), ApiError>) -> Option<StatusCode> {
+        result.err().map(|e| e.0)
+    }
+
+    #[test]
+    fn authorize_requires_the_bearer_token() {
+        let server = test_server(Some("secret"), vec![Scope::Read], Instant::now());
+        let unauthorized = Some(StatusCode::UNAUTHORIZED);
+
+        assert_eq!(
+            status(server.authorize(&HeaderMap::new(), None)),
+            unauthorized
+        );
+        for value in [
+            "Bearer wrong",
+            "Bearer secre",
+            "Bearer secret2",
+            "secret",
+            "Basic secret",
+        ] {
+            let headers = authorization(value);
+            assert_eq!(
+                status(server.authorize(&headers, None)),
+                unauthorized,
+                "{}",
+                value
+            );
+            // The token is checked before the scope
+            assert_eq!(
+                status(server.authorize(&headers, Some(Scope::Write))),
+                unauthorized
+            );
+        }
+        let headers = authorization("Bearer secret");
+        assert_eq!(status(server.authorize(&headers, None)), None);
+        assert_eq!(status(server.authorize(&headers, Some(Scope::Read))), None);
+    }
+
+    #[test]
+    fn authorize_requires_the_scope() {
+        let server = test_server(Some("secret"), vec![Scope::Read], Instant::now());
+        let headers = authorization("Bearer secret");
+        let forbidden = Some(StatusCode::FORBIDDEN);
+        assert_eq!(
+            status(server.authorize(&headers, Some(Scope::Reveal))),
+            forbidden
+        );
+        assert_eq!(
+            status(server.authorize(&headers, Some(Scope::Write))),
+            forbidden
+        );
+
+        // Without a token (on a Unix socket), only the scope is checked
+        let server = test_server(None, vec![Scope::Read, Scope::Reveal], Instant::now());
+        assert_eq!(
+            status(server.authorize(&HeaderMap::new(), Some(Scope::Reveal))),
+            None
+        );
+        assert_eq!(
+            status(server.authorize(&HeaderMap::new(), Some(Scope::Write))),
+            forbidden
+        );
+    }
+
+    #[tokio::test]
+    async fn unlocked_locks_after_inactivity() {
+        let server = test_server(None, vec![Scope::Read], Instant::now());
+        assert!(server.unlocked().await.is_ok());
+
+        let idle = Instant::now() - Duration::from_secs(61);
+        let server = test_server(None, vec![Scope::Read], idle);
+        let locked = Some(StatusCode::LOCKED);
+        assert_eq!(server.unlocked().await.err().map(|e| e.0), locked);
+        // The session stays locked once it expired
+        assert!(server.session.lock().await.master.is_none());
+        assert_eq!(server.unlocked().await.err().map(|e| e.0), locked);
+    }
+
+    #[tokio::test]
+    async fn unlocked_keeps_a_used_session_unlocked() {
+        let almost_idle = Instant::now() - Duration::from_secs(59);
+        let server = test_server(None, vec![Scope::Read], almost_idle);
+        assert!(server.unlocked().await.is_ok());
+        // Using the session restarts the countdown
+        assert!(server.session.lock().await.last_used.elapsed() < Duration::from_secs(1));
+        assert!(server.unlocked().await.is_ok());
+
+        server.session.lock().await.master = None;
+        assert_eq!(
+            server.unlocked().await.err().map(|e| e.0),
+            Some(StatusCode::LOCKED)
+        );
+    }
+}
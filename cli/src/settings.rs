@@ -0,0 +1,29 @@
+use api::{configuration, settings::Settings};
+
+use crate::{util::PrintError, view};
+
+/// Prints the effective value of a setting, with the environment overrides
+pub fn get_setting(key: String) -> Result<(), ()> {
+    let settings = configuration::get_settings().print_err()?;
+    println!("{}", settings.get(&key).print_err()?);
+    Ok(())
+}
+
+pub fn set_setting(key: String, value: String) -> Result<(), ()> {
+    configuration::set_setting(&key, &value).print_err()?;
+    println!("Set {} to {}", key, value);
+    Ok(())
+}
+
+pub fn list_settings() -> Result<(), ()> {
+    let settings = configuration::get_settings().print_err()?;
+    let settings = Settings::keys()
+        .into_iter()
+        .map(|key| {
+            let value = settings.get(&key)?;
+            Ok((key, value))
+        })
+        .collect::<Result<Vec<_>, String>>()
+        .print_err()?;
+    view::print_settings(settings).print_err()
+}
@@ -0,0 +1,61 @@
+use std::{fs, path::Path};
+
+use ssh_key::PublicKey;
+
+use crate::{
+    master::{prompt_authenticate, AuthenticatedMaster},
+    util::{self, PrintError},
+    view,
+};
+
+pub async fn add_ssh_key(
+    file: String,
+    name: Option<String>,
+    description: Option<String>,
+) -> Result<(), ()> {
+    let pem = fs::read_to_string(&file)
+        .map_err(|_| format!("Failed to read {}", file))
+        .print_err()?;
+    let mut key = api::ssh::parse_private_key(&pem).print_err()?;
+    if key.is_encrypted() {
+        let passphrase = util::get_password_with_prompt_print("Key Passphrase: ")?;
+        key = key
+            .decrypt(passphrase)
+            .map_err(|_| "Invalid passphrase")
+            .print_err()?;
+    }
+
+    // Default to the key comment (usually user@host), then to the file name
+    let name = name
+        .or_else(|| Some(key.comment().to_owned()).filter(|c| !c.is_empty()))
+        .or_else(|| {
+            Path::new(&file)
+                .file_name()
+                .map(|f| f.to_string_lossy().into_owned())
+        })
+        .unwrap_or(util::settings()?.entries.default_name);
+
+    let master: AuthenticatedMaster = prompt_authenticate().await?;
+    let entry = api::ssh::create_ssh_key(master.password, name, description, &key)
+        .await
+        .print_err()?;
+    view::print_entry(entry, 1, None, false).print_err()
+}
+
+/// Prints the public keys in `authorized_keys` format, with the entry name as comment
+pub async fn list_ssh_keys() -> Result<(), ()> {
+    let entries = api::ssh::get_ssh_keys().await.print_err()?;
+    if entries.is_empty() {
+        println!("No SSH keys. Add one with `mypass ssh-key add -f <private key>`");
+        return Ok(());
+    }
+    for entry in entries {
+        let Some(public_key) = entry.ssh_public_key else {
+            continue;
+        };
+        let mut public_key = PublicKey::from_openssh(&public_key).print_err()?;
+        public_key.set_comment(entry.name);
+        println!("{}", public_key.to_openssh().print_err()?);
+    }
+    Ok(())
+}
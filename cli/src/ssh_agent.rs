@@ -0,0 +1,345 @@
+use std::{
+    fs::{self, OpenOptions},
+    io::{self, BufRead, BufReader, Write},
+    path::PathBuf,
+    sync::Arc,
+};
+
+use api::error::Error;
+use model::entities::entry;
+use ssh_key::{Algorithm, HashAlg, PublicKey};
+use tokio::{
+    io::{AsyncRead, AsyncReadExt, AsyncWrite, AsyncWriteExt},
+    sync::Mutex,
+};
+
+use crate::{
+    master::{authenticate, prompt_authenticate, AuthenticatedMaster},
+    util::{self, PrintError},
+};
+
+// Message numbers of the OpenSSH agent protocol (draft-miller-ssh-agent)
+const SSH_AGENT_FAILURE: u8 = 5;
+const SSH_AGENT_SUCCESS: u8 = 6;
+const SSH_AGENTC_REQUEST_IDENTITIES: u8 = 11;
+const SSH_AGENT_IDENTITIES_ANSWER: u8 = 12;
+const SSH_AGENTC_SIGN_REQUEST: u8 = 13;
+const SSH_AGENT_SIGN_RESPONSE: u8 = 14;
+const SSH_AGENTC_LOCK: u8 = 22;
+const SSH_AGENTC_UNLOCK: u8 = 23;
+
+const SSH_AGENT_RSA_SHA2_256: u32 = 2;
+const SSH_AGENT_RSA_SHA2_512: u32 = 4;
+
+/// Same limit as OpenSSH's agent
+const MAX_MESSAGE_LEN: usize = 256 * 1024;
+
+struct Agent {
+    /// Set while the vault is unlocked. Keys are decrypted for each signature
+    /// and never kept in memory.
+    master: Option<AuthenticatedMaster>,
+    confirm: bool,
+}
+
+pub async fn ssh_agent(socket: Option<String>, confirm: bool, locked: bool) -> Result<(), ()> {
+    let path = match socket {
+        Some(socket) => PathBuf::from(socket),
+        None => default_socket_path().print_err()?,
+    };
+    let master = if locked {
+        None
+    } else {
+        Some(prompt_authenticate().await?)
+    };
+
+    let listener = util::bind_unix_socket(&path).print_err()?;
+    let agent = Arc::new(Mutex::new(Agent { master, confirm }));
+    println!(
+        "SSH_AUTH_SOCK={}; export SSH_AUTH_SOCK;",
+        path.to_string_lossy()
+    );
+    eprintln!("Agent listening, press Ctrl-C to stop");
+
+    loop {
+        tokio::select! {
+            _ = tokio::signal::ctrl_c() => break,
+            accepted = listener.accept() => {
+                let Ok((stream, _)) = accepted else {
+                    continue;
+                };
+                let agent = agent.clone();
+                tokio::spawn(async move {
+                    handle_connection(stream, agent).await.ok();
+                });
+            }
+        }
+    }
+    fs::remove_file(&path).ok();
+    Ok(())
+}
+
+fn default_socket_path() -> Result<PathBuf, Error> {
+    match std::env::var("XDG_RUNTIME_DIR") {
+        Ok(dir) if !dir.is_empty() => Ok(PathBuf::from(dir).join("mypass-agent.sock")),
+        _ => Ok(api::configuration::get_data_dir_path()?.join("agent.sock")),
+    }
+}
+
+/// Answers the requests of a client until it disconnects
+async fn handle_connection(
+    mut stream: impl AsyncRead + AsyncWrite + Unpin,
+    agent: Arc<Mutex<Agent>>,
+) -> io::Result<()> {
+    loop {
+        let mut len = [0u8; 4];
+        if stream.read_exact(&mut len).await.is_err() {
+            return Ok(());
+        }
+        let len = u32::from_be_bytes(len) as usize;
+        if len == 0 || len > MAX_MESSAGE_LEN {
+            return Err(io::ErrorKind::InvalidData.into());
+        }
+        let mut request = vec![0u8; len];
+        stream.read_exact(&mut request).await?;
+
+        // Requests are handled one at a time so that confirmations do not interleave
+        let response = {
+            let mut agent = agent.lock().await;
+            agent
+                .handle(&request)
+                .await
+                .unwrap_or_else(|| vec![SSH_AGENT_FAILURE])
+        };
+        stream
+            .write_all(&(response.len() as u32).to_be_bytes())
+            .await?;
+        stream.write_all(&response).await?;
+    }
+}
+
+impl Agent {
+    /// Returns the response to a request, or `None` to answer with a failure
+    async fn handle(&mut self, request: &[u8]) -> Option<Vec<u8>> {
+        let mut message = Message(&request[1..]);
+        match request[0] {
+            SSH_AGENTC_REQUEST_IDENTITIES => self.identities().await,
+            SSH_AGENTC_SIGN_REQUEST => {
+                let key_blob = message.string()?;
+                let data = message.string()?;
+                let flags = message.u32()?;
+                self.sign(key_blob, data, flags).await
+            }
+            SSH_AGENTC_LOCK => {
+                self.master = None;
+                eprintln!("Agent locked");
+                Some(vec![SSH_AGENT_SUCCESS])
+            }
+            // The unlock passphrase (`ssh-add -X`) is the master password
+            SSH_AGENTC_UNLOCK => {
+                let password = String::from_utf8(message.string()?.to_vec()).ok()?;
+                let master = authenticate(password.to_owned()).await.ok()?;
+                self.master = Some(AuthenticatedMaster::new(master, password));
+                eprintln!("Agent unlocked");
+                Some(vec![SSH_AGENT_SUCCESS])
+            }
+            _ => None,
+        }
+    }
+
+    async fn identities(&self) -> Option<Vec<u8>> {
+        let keys = ssh_keys().await.ok()?;
+        let mut response = vec![SSH_AGENT_IDENTITIES_ANSWER];
+        response.extend((keys.len() as u32).to_be_bytes());
+        for (entry, public_key) in keys {
+            put_string(&mut response, &public_key.to_bytes().ok()?);
+            put_string(&mut response, entry.name.as_bytes());
+        }
+        Some(response)
+    }
+
+    async fn sign(&self, key_blob: &[u8], data: &[u8], flags: u32) -> Option<Vec<u8>> {
+        let (entry, public_key) = ssh_keys()
+            .await
+            .ok()?
+            .into_iter()
+            .find(|(_, public_key)| public_key.to_bytes().is_ok_and(|b| b == key_blob))?;
+
+        let Some(master) = &self.master else {
+            eprintln!(
+                "Refused to sign with {}: the agent is locked (unlock with `ssh-add -X`)",
+                entry.name
+            );
+            return None;
+        };
+
+        let hash = if flags & SSH_AGENT_RSA_SHA2_256 != 0 {
+            HashAlg::Sha256
+        } else if flags & SSH_AGENT_RSA_SHA2_512 != 0 {
+            HashAlg::Sha512
+        } else if let Algorithm::Rsa { .. } = public_key.algorithm() {
+            eprintln!(
+                "Refused to sign with {}: SHA-1 RSA signatures are not supported",
+                entry.name
+            );
+            return None;
+        } else {
+            HashAlg::Sha512
+        };
+
+        if self.confirm && !confirm(&entry).await {
+            eprintln!("Refused to sign with {}: not confirmed", entry.name);
+            return None;
+        }
+
+        let key = api::ssh::decrypt_ssh_key(&entry, &master.master, master.password.to_owned())
+            .print_err()
+            .ok()?;
+        let signature = api::ssh::sign(&key, data, hash).print_err().ok()?;
+        let signature: Vec<u8> = signature.try_into().ok()?;
+        eprintln!("Signed with {}", entry.name);
+
+        let mut response = vec![SSH_AGENT_SIGN_RESPONSE];
+        put_string(&mut response, &signature);
+        Some(response)
+    }
+}
+
+async fn ssh_keys() -> Result<Vec<(entry::Model, PublicKey)>, Error> {
+    Ok(api::ssh::get_ssh_keys()
+        .await?
+        .into_iter()
+        .filter_map(|entry| {
+            let public_key = PublicKey::from_openssh(entry.ssh_public_key.as_deref()?).ok()?;
+            Some((entry, public_key))
+        })
+        .collect())
+}
+
+/// Asks on the controlling terminal whether the key may be used.
+/// Denies when there is no terminal to ask on.
+async fn confirm(entry: &entry::Model) -> bool {
+    let prompt = format!("Allow use of SSH key {}? [y/N] ", entry.name);
+    tokio::task::spawn_blocking(move || -> io::Result<bool> {
+        let mut tty = OpenOptions::new().read(true).write(true).open("/dev/tty")?;
+        tty.write_all(prompt.as_bytes())?;
+        let mut answer = String::new();
+        BufReader::new(tty).read_line(&mut answer)?;
+        Ok(matches!(answer.trim(), "y" | "Y" | "yes"))
+    })
+    .await
+    .is_ok_and(|answer| answer.unwrap_or(false))
+}
+
+/// Reader over the body of an agent message
+struct Message<'a>(&'a [u8]);
+
+impl<'a> Message<'a> {
+    fn u32(&mut self) -> Option<u32> {
+        let (value, rest) = self.0.split_first_chunk::<4>()?;
+        self.0 = rest;
+        Some(u32::from_be_bytes(*value))
+    }
+
+    fn string(&mut self) -> Option<&'a [u8]> {
+        let len = self.u32()? as usize;
+        if len > self.0.len() {
+            return None;
+        }
+        let (value, rest) = self.0.split_at(len);
+        self.0 = rest;
+        Some(value)
+    }
+}
+
+fn put_string(buf: &mut Vec<u8>, value: &[u8]) {
+    buf.extend((value.len() as u32).to_be_bytes());
+    buf.extend(value);
+}
+
+#[cfg(test)]
+mod tests {
+    use tokio::io::{duplex, DuplexStream};
+
+    use super::*;
+
+    fn agent() -> Arc<Mutex<Agent>> {
+        Arc::new(Mutex::new(Agent {
+            master: None,
+            confirm: false,
+        }))
+    }
+
+    async fn send(client: &mut DuplexStream, request: &[u8]) {
+        client
+            .write_all(&(request.len() as u32).to_be_bytes())
+            .await
+            .unwrap();
+        client.write_all(request).await.unwrap();
+    }
+
+    async fn receive(client: &mut DuplexStream) -> Vec<u8> {
+        let mut len = [0u8; 4];
+        client.read_exact(&mut len).await.unwrap();
+        let mut response = vec![0u8; u32::from_be_bytes(len) as usize];
+        client.read_exact(&mut response).await.unwrap();
+        response
+    }
+
+    #[tokio::test]
+    async fn requests_are_answered_in_order() {
+        let (mut client, server) = duplex(1024);
+        let connection = tokio::spawn(handle_connection(server, agent()));
+
+        send(&mut client, &[SSH_AGENTC_LOCK]).await;
+        assert_eq!(receive(&mut client).await, vec![SSH_AGENT_SUCCESS]);
+
+        // Unsupported messages, e.g. adding a key, fail without closing the connection
+        send(&mut client, &[17, 0, 0, 0, 1]).await;
+        assert_eq!(receive(&mut client).await, vec![SSH_AGENT_FAILURE]);
+
+        // A sign request whose data is shorter than its length
+        let mut request = vec![SSH_AGENTC_SIGN_REQUEST];
+        put_string(&mut request, b"key");
+        request.extend(100u32.to_be_bytes());
+        request.extend(b"short");
+        send(&mut client, &request).await;
+        assert_eq!(receive(&mut client).await, vec![SSH_AGENT_FAILURE]);
+
+        send(&mut client, &[SSH_AGENTC_UNLOCK, 0, 0]).await;
+        assert_eq!(receive(&mut client).await, vec![SSH_AGENT_FAILURE]);
+
+        drop(client);
+        assert!(connection.await.unwrap().is_ok());
+    }
+
+    #[tokio::test]
+    async fn empty_and_oversized_messages_close_the_connection() {
+        for len in [0, MAX_MESSAGE_LEN as u32 + 1] {
+            let (mut client, server) = duplex(1024);
+            client.write_all(&len.to_be_bytes()).await.unwrap();
+            let result = handle_connection(server, agent()).await;
+            assert_eq!(result.unwrap_err().kind(), io::ErrorKind::InvalidData);
+        }
+    }
+
+    #[test]
+    fn message_fields_are_read_in_order() {
+        let mut body = Vec::new();
+        put_string(&mut body, b"key blob");
+        put_string(&mut body, b"");
+        body.extend(SSH_AGENT_RSA_SHA2_512.to_be_bytes());
+        let mut message = Message(&body);
+        assert_eq!(message.string(), Some(&b"key blob"[..]));
+        assert_eq!(message.string(), Some(&b""[..]));
+        assert_eq!(message.u32(), Some(SSH_AGENT_RSA_SHA2_512));
+        assert_eq!(message.u32(), None);
+        assert_eq!(message.string(), None);
+    }
+
+    #[test]
+    fn truncated_fields_are_rejected() {
+        assert_eq!(Message(&[0, 0, 1]).u32(), None);
+        assert_eq!(Message(&[0, 0, 0, 4, 1, 2]).string(), None);
+        assert_eq!(Message(&[0xff, 0xff, 0xff, 0xff]).string(), None);
+    }
+}
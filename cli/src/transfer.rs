@@ -0,0 +1,428 @@
+use std::fs;
+
+use api::{
+    container,
+    csv_profiles::{self, ColumnMapping, CsvProfile},
+    entry_transfer::{ExportField, ExportSelection, ImportOptions, ImportSummary, MergeStrategy},
+    keepass::Cipher,
+    vault_file::VaultFormat,
+};
+use clap::ValueEnum;
+
+use crate::{
+    entries::find_entry,
+    master::prompt_authenticate,
+    util::{self, PrintError},
+};
+
+#[derive(Debug, Clone, Copy, Default, ValueEnum)]
+pub enum Format {
+    /// The MyPass csv format, encrypted with a passphrase unless `--unencrypted` is given
+    #[default]
+    Csv,
+    /// A KeePass 2 (KDBX 4) database, as used by KeePassXC
+    Keepass,
+    /// A Bitwarden JSON export, password protected unless `--unencrypted` is given
+    Bitwarden,
+    /// A password store of `pass` (import only), decrypted with gpg
+    Pass,
+    /// Every field of the entries as JSON, encrypted with a passphrase unless `--unencrypted` is given
+    Json,
+    /// Every field of the entries as YAML, encrypted with a passphrase unless `--unencrypted` is given
+    Yaml,
+}
+
+#[derive(Debug, Clone, Copy, Default, ValueEnum)]
+pub enum KeePassCipher {
+    #[default]
+    Aes,
+    Chacha20,
+}
+
+impl From<KeePassCipher> for Cipher {
+    fn from(cipher: KeePassCipher) -> Self {
+        match cipher {
+            KeePassCipher::Aes => Cipher::Aes256,
+            KeePassCipher::Chacha20 => Cipher::ChaCha20,
+        }
+    }
+}
+
+/// The application that exported a csv file
+#[derive(Debug, Clone, Copy, ValueEnum)]
+pub enum Profile {
+    Mypass,
+    Chrome,
+    Firefox,
+    Lastpass,
+    #[value(name = "1password")]
+    Onepassword,
+    Dashlane,
+}
+
+impl From<Profile> for CsvProfile {
+    fn from(profile: Profile) -> Self {
+        match profile {
+            Profile::Mypass => CsvProfile::Mypass,
+            Profile::Chrome => CsvProfile::Chrome,
+            Profile::Firefox => CsvProfile::Firefox,
+            Profile::Lastpass => CsvProfile::Lastpass,
+            Profile::Onepassword => CsvProfile::Onepassword,
+            Profile::Dashlane => CsvProfile::Dashlane,
+        }
+    }
+}
+
+/// A field of the exported entries
+#[derive(Debug, Clone, Copy, ValueEnum)]
+pub enum Field {
+    Description,
+    Username,
+    Url,
+    Password,
+    Totp,
+    Folder,
+    Tags,
+    CustomFields,
+}
+
+impl From<Field> for ExportField {
+    fn from(field: Field) -> Self {
+        match field {
+            Field::Description => ExportField::Description,
+            Field::Username => ExportField::Username,
+            Field::Url => ExportField::Url,
+            Field::Password => ExportField::Password,
+            Field::Totp => ExportField::Totp,
+            Field::Folder => ExportField::Folder,
+            Field::Tags => ExportField::Tags,
+            Field::CustomFields => ExportField::CustomFields,
+        }
+    }
+}
+
+/// What to do with an imported entry that duplicates an existing one
+#[derive(Debug, Clone, Copy, Default, ValueEnum)]
+pub enum Merge {
+    /// Keep the existing entry
+    #[default]
+    Skip,
+    /// Replace the existing entry with the imported one
+    Overwrite,
+    /// Import the entry as a new one
+    KeepBoth,
+}
+
+impl From<Merge> for MergeStrategy {
+    fn from(merge: Merge) -> Self {
+        match merge {
+            Merge::Skip => MergeStrategy::Skip,
+            Merge::Overwrite => MergeStrategy::Overwrite,
+            Merge::KeepBoth => MergeStrategy::KeepBoth,
+        }
+    }
+}
+
+/// Exports the entries matching the selection. Its `ids` are entry references (number,
+/// name or ID) as given on the command line.
+pub async fn export_entries(
+    path: Option<String>,
+    format: Format,
+    keyfile: Option<String>,
+    cipher: KeePassCipher,
+    unencrypted: bool,
+    passphrase_file: Option<String>,
+    mut selection: ExportSelection,
+) -> Result<(), ()> {
+    let mut ids = Vec::new();
+    for reference in &selection.filter.ids {
+        let (_, entry) = find_entry(reference).await?;
+        ids.push(entry.id);
+    }
+    selection.filter.ids = ids;
+
+    let count = match format {
+        Format::Csv => {
+            let path = util::unwrap_or_input(
+                path,
+                "Export path (default is entries.mypass in the data directory, or entries.csv when unencrypted): ",
+            );
+            let master = prompt_authenticate().await?;
+            let passphrase = export_passphrase(unencrypted, &passphrase_file)?;
+            api::entry_transfer::export_entries(master.password, path, passphrase, &selection)
+                .await
+                .print_err()?
+        }
+        Format::Keepass => {
+            let path = require_path(path, "Export path: ")?;
+            let master = prompt_authenticate().await?;
+            let password = keepass_password(&keyfile)?;
+            api::keepass::export_keepass(
+                master.password,
+                path,
+                password,
+                keyfile,
+                cipher.into(),
+                &selection,
+            )
+            .await
+            .print_err()?
+        }
+        Format::Bitwarden => {
+            let path = require_path(path, "Export path: ")?;
+            let master = prompt_authenticate().await?;
+            let password = export_passphrase(unencrypted, &passphrase_file)?;
+            api::bitwarden::export_bitwarden(master.password, path, password, &selection)
+                .await
+                .print_err()?
+        }
+        Format::Pass => {
+            eprintln!("Exporting to a pass store is not supported");
+            return Err(());
+        }
+        Format::Json | Format::Yaml => {
+            let path = require_path(path, "Export path: ")?;
+            let master = prompt_authenticate().await?;
+            let passphrase = export_passphrase(unencrypted, &passphrase_file)?;
+            api::vault_file::export_vault(
+                master.password,
+                path,
+                vault_format(format),
+                passphrase,
+                &selection,
+            )
+            .await
+            .print_err()?
+        }
+    };
+    println!("Exported {} entries", count);
+    println!("Export finished");
+    Ok(())
+}
+
+pub async fn import_entries(
+    path: Option<String>,
+    format: Format,
+    keyfile: Option<String>,
+    profile: Option<Profile>,
+    passphrase_file: Option<String>,
+    options: ImportOptions,
+) -> Result<(), ()> {
+    let summary = match format {
+        Format::Csv => {
+            let path = util::unwrap_or_input(
+                path,
+                "Import path (default is entries.mypass or entries.csv in the data directory): ",
+            );
+            let path = match path {
+                Some(path) => path,
+                None => api::entry_transfer::default_import_path().print_err()?,
+            };
+            let data = read_export(&path, "csv", &passphrase_file)?;
+            let headers = csv_profiles::read_headers(&data).print_err()?;
+            let profile = profile
+                .map(CsvProfile::from)
+                .or_else(|| csv_profiles::detect_profile(&headers));
+            let mapping = match profile {
+                Some(profile) => {
+                    println!("Importing a {} csv file", profile.name());
+                    profile.mapping(&headers)
+                }
+                None => prompt_mapping(&headers)?,
+            };
+            let master = prompt_authenticate().await?;
+            api::entry_transfer::import_entries(master.password, data, mapping, profile, options)
+                .await
+                .print_err()?
+        }
+        Format::Keepass => {
+            let path = require_path(path, "Import path: ")?;
+            let password = keepass_password(&keyfile)?;
+            let master = prompt_authenticate().await?;
+            api::keepass::import_keepass(master.password, path, password, keyfile, options)
+                .await
+                .print_err()?
+        }
+        Format::Bitwarden => {
+            let path = require_path(path, "Import path: ")?;
+            let password = if api::bitwarden::is_password_protected(path.to_owned()).print_err()? {
+                Some(util::get_password_with_prompt_print("Export Password: ")?)
+            } else {
+                None
+            };
+            let master = prompt_authenticate().await?;
+            api::bitwarden::import_bitwarden(master.password, path, password, options)
+                .await
+                .print_err()?
+        }
+        Format::Pass => {
+            let dir = match path {
+                Some(path) => path,
+                None => api::pass::default_store_dir().print_err()?,
+            };
+            let master = prompt_authenticate().await?;
+            api::pass::import_pass(master.password, dir, options)
+                .await
+                .print_err()?
+        }
+        Format::Json | Format::Yaml => {
+            let path = require_path(path, "Import path: ")?;
+            let format = vault_format(format);
+            let data = read_export(&path, format.content_type(), &passphrase_file)?;
+            let master = prompt_authenticate().await?;
+            api::vault_file::import_vault(master.password, data, format, options)
+                .await
+                .print_err()?
+        }
+    };
+    print_summary(&summary, options.dry_run);
+    Ok(())
+}
+
+/// Lists the entries an import created (+), overwrote (~) and skipped as duplicates (=)
+fn print_summary(summary: &ImportSummary, dry_run: bool) {
+    for name in &summary.created {
+        println!("+ {}", name);
+    }
+    for name in &summary.overwritten {
+        println!("~ {}", name);
+    }
+    for name in &summary.skipped {
+        println!("= {}", name);
+    }
+    let (created, overwritten) = if dry_run {
+        ("to create", "to overwrite")
+    } else {
+        ("created", "overwritten")
+    };
+    println!(
+        "{} {}, {} {}, {} duplicates skipped",
+        summary.created.len(),
+        created,
+        summary.overwritten.len(),
+        overwritten,
+        summary.skipped.len()
+    );
+    if dry_run {
+        println!("Dry run, the vault was not changed");
+    } else {
+        println!("Import finished");
+    }
+}
+
+/// Asks which column holds each field, for a file that matches no known profile
+fn prompt_mapping(headers: &[String]) -> Result<ColumnMapping, ()> {
+    if !util::is_interactive() {
+        eprintln!("Unknown csv columns, choose a profile with `--profile` or import interactively to map them");
+        return Err(());
+    }
+    println!("Unknown csv columns:");
+    for (index, header) in headers.iter().enumerate() {
+        println!("  {}. {}", index + 1, header);
+    }
+    let column = |field: &str| -> Result<Option<usize>, ()> {
+        loop {
+            let prompt = format!("Column number of the {} (skip if none): ", field);
+            let Some(input) = util::input(&prompt) else {
+                return Ok(None);
+            };
+            match input.parse::<usize>() {
+                Ok(number) if number >= 1 && number <= headers.len() => {
+                    return Ok(Some(number - 1))
+                }
+                _ => eprintln!("Invalid column number"),
+            }
+        }
+    };
+    Ok(ColumnMapping {
+        name: column("name")?,
+        description: column("description")?,
+        username: column("username")?,
+        url: column("url")?,
+        password: column("password")?,
+        totp: column("TOTP secret")?,
+        folder: column("folder")?,
+        tags: column("tags")?,
+    })
+}
+
+/// The passphrase protecting an export, or `None` for an unencrypted export
+fn export_passphrase(
+    unencrypted: bool,
+    passphrase_file: &Option<String>,
+) -> Result<Option<String>, ()> {
+    if unencrypted {
+        eprintln!("Warning: the export is not encrypted, anyone who can read it can read your passwords. Delete it once imported");
+        return Ok(None);
+    }
+    if let Some(file) = passphrase_file {
+        return read_passphrase_file(file).map(Some);
+    }
+    let passphrase = util::get_password_with_prompt_print("Export Passphrase: ")?;
+    let retyped = util::get_password_with_prompt_print("Retype Export Passphrase: ")?;
+    if retyped != passphrase {
+        eprintln!("Passphrases must be the same");
+        return Err(());
+    }
+    Ok(Some(passphrase))
+}
+
+/// Reads an export, decrypting it when it is encrypted with a passphrase
+fn read_export(
+    path: &str,
+    content_type: &str,
+    passphrase_file: &Option<String>,
+) -> Result<Vec<u8>, ()> {
+    let data = fs::read(path)
+        .map_err(|_| format!("Failed to read {}", path))
+        .print_err()?;
+    if !container::is_container(&data) {
+        return Ok(data);
+    }
+    let passphrase = match passphrase_file {
+        Some(file) => read_passphrase_file(file)?,
+        None => util::get_password_with_prompt_print("Export Passphrase: ")?,
+    };
+    let (found, content) = container::open(&data, &passphrase).print_err()?;
+    if found != content_type {
+        eprintln!("Unsupported encrypted export content {}", found);
+        return Err(());
+    }
+    Ok(content)
+}
+
+fn vault_format(format: Format) -> VaultFormat {
+    match format {
+        Format::Yaml => VaultFormat::Yaml,
+        _ => VaultFormat::Json,
+    }
+}
+
+/// Reads a passphrase from the first line of a file, for scripts
+fn read_passphrase_file(file: &str) -> Result<String, ()> {
+    let contents = fs::read_to_string(file)
+        .map_err(|_| format!("Failed to read {}", file))
+        .print_err()?;
+    let passphrase = contents.lines().next().unwrap_or_default().to_owned();
+    if passphrase.is_empty() {
+        eprintln!("The passphrase file is empty");
+        return Err(());
+    }
+    Ok(passphrase)
+}
+
+fn require_path(path: Option<String>, prompt: &str) -> Result<String, ()> {
+    util::unwrap_or_input(path, prompt).ok_or_else(|| eprintln!("A path is required"))
+}
+
+/// The password of the KeePass database, which may be left empty when a keyfile is used
+fn keepass_password(keyfile: &Option<String>) -> Result<Option<String>, ()> {
+    match util::get_password_with_prompt("KeePass Password: ") {
+        Ok(password) => Ok(Some(password)),
+        Err(_) if keyfile.is_some() => Ok(None),
+        Err(_) => {
+            eprintln!("Password is required");
+            Err(())
+        }
+    }
+}
@@ -0,0 +1,502 @@
+use std::time::{Duration, Instant};
+
+use api::{crypto, error::Error, settings::Settings};
+use crossterm::event::{self, Event, KeyCode, KeyEvent, KeyEventKind, KeyModifiers};
+use model::entities::entry;
+use ratatui::{widgets::ListState, DefaultTerminal};
+
+use crate::{master::AuthenticatedMaster, picker, util};
+
+use super::ui;
+
+const TICK: Duration = Duration::from_millis(250);
+
+pub enum Mode {
+    Locked,
+    Browse,
+    Search,
+    Form(Form),
+    ConfirmDelete,
+}
+
+pub struct FormField {
+    pub label: &'static str,
+    pub value: String,
+    pub secret: bool,
+    /// Whether the field is optional, and can then be cleared when editing an entry
+    pub clearable: bool,
+    /// Set by the clear action, as an empty value keeps the current one when editing
+    pub cleared: bool,
+}
+
+pub struct Form {
+    /// `None` when creating a new entry
+    pub entry_id: Option<String>,
+    pub fields: Vec<FormField>,
+    pub focus: usize,
+}
+
+impl Form {
+    fn new(entry: Option<&entry::Model>) -> Form {
+        let field = |label: &'static str, value: Option<String>, secret: bool| FormField {
+            label,
+            value: value.unwrap_or_default(),
+            secret,
+            clearable: entry.is_some() && !matches!(label, "Name" | "Password"),
+            cleared: false,
+        };
+        Form {
+            entry_id: entry.map(|e| e.id.to_owned()),
+            fields: vec![
+                field("Name", entry.map(|e| e.name.to_owned()), false),
+                field(
+                    "Description",
+                    entry.and_then(|e| e.description.to_owned()),
+                    false,
+                ),
+                field("Username", entry.and_then(|e| e.username.to_owned()), false),
+                field("URL", entry.and_then(|e| e.url.to_owned()), false),
+                field("Password", None, true),
+                field("TOTP secret", None, true),
+            ],
+            focus: 0,
+        }
+    }
+
+    /// The value of a field, with the name, username and URL trimmed. Secrets are kept as typed.
+    fn value(&self, index: usize) -> Option<String> {
+        let value = self.fields[index].value.as_str();
+        let value = match index {
+            0 | 2 | 3 => value.trim(),
+            _ => value,
+        };
+        if value.is_empty() {
+            None
+        } else {
+            Some(value.to_owned())
+        }
+    }
+
+    /// The new value of a field when editing an entry, an empty string when it was cleared
+    fn changed(&self, index: usize, current: Option<String>) -> Option<String> {
+        if self.fields[index].cleared {
+            return current.map(|_| String::new());
+        }
+        self.value(index).filter(|v| Some(v) != current.as_ref())
+    }
+}
+
+/// Decrypted values of the selected entry, shown in the detail pane on demand
+pub struct Revealed {
+    pub entry_id: String,
+    pub password: String,
+    pub totp: Option<String>,
+}
+
+pub struct App {
+    pub mode: Mode,
+    pub entries: Vec<entry::Model>,
+    /// Indices into `entries` matching the search query
+    pub filtered: Vec<usize>,
+    pub query: String,
+    pub list_state: ListState,
+    pub input: String,
+    pub status: Option<String>,
+    pub revealed: Option<Revealed>,
+    master: Option<AuthenticatedMaster>,
+    last_activity: Instant,
+    /// The vault locks itself (and forgets the master password) after this much inactivity
+    lock_after: Duration,
+    settings: Settings,
+    quit: bool,
+}
+
+impl App {
+    pub fn new(settings: Settings) -> App {
+        App {
+            mode: Mode::Locked,
+            entries: Vec::new(),
+            filtered: Vec::new(),
+            query: String::new(),
+            list_state: ListState::default(),
+            input: String::new(),
+            status: None,
+            revealed: None,
+            master: None,
+            last_activity: Instant::now(),
+            lock_after: Duration::from_secs(settings.lock.tui * 60),
+            settings,
+            quit: false,
+        }
+    }
+
+    pub async fn run(mut self, terminal: &mut DefaultTerminal) -> Result<(), Error> {
+        let err = "Failed to draw the terminal interface";
+        while !self.quit {
+            if self.master.is_some() && self.last_activity.elapsed() >= self.lock_after {
+                self.lock();
+                self.status = Some("Locked after inactivity".to_owned());
+            }
+
+            terminal
+                .draw(|frame| ui::draw(frame, &mut self))
+                .map_err(|_| err)?;
+
+            if !event::poll(TICK).map_err(|_| err)? {
+                continue;
+            }
+            if let Event::Key(key) = event::read().map_err(|_| err)? {
+                if key.kind == KeyEventKind::Press {
+                    self.last_activity = Instant::now();
+                    self.handle_key(key).await;
+                }
+            }
+        }
+        Ok(())
+    }
+
+    pub fn selected(&self) -> Option<&entry::Model> {
+        self.list_state
+            .selected()
+            .and_then(|i| self.filtered.get(i))
+            .map(|i| &self.entries[*i])
+    }
+
+    pub fn is_locked(&self) -> bool {
+        self.master.is_none()
+    }
+
+    fn lock(&mut self) {
+        self.master = None;
+        self.revealed = None;
+        self.entries.clear();
+        self.filtered.clear();
+        self.input.clear();
+        self.mode = Mode::Locked;
+    }
+
+    async fn unlock(&mut self) {
+        let password = std::mem::take(&mut self.input);
+        match api::master::authenticate_master(password.to_owned()).await {
+            Ok(master) => {
+                self.master = Some(AuthenticatedMaster::new(master, password));
+                self.mode = Mode::Browse;
+                self.status = None;
+                self.reload().await;
+            }
+            Err(e) => self.status = Some(e),
+        }
+    }
+
+    async fn reload(&mut self) {
+        match api::entries::get_all_entries().await {
+            Ok(entries) => self.entries = entries,
+            Err(e) => self.status = Some(e),
+        }
+        self.refilter();
+    }
+
+    fn refilter(&mut self) {
+        let labels: Vec<String> = self
+            .entries
+            .iter()
+            .map(|e| {
+                [Some(&e.name), e.username.as_ref(), e.url.as_ref()]
+                    .into_iter()
+                    .flatten()
+                    .cloned()
+                    .collect::<Vec<String>>()
+                    .join(" ")
+            })
+            .collect();
+        self.filtered = picker::filter(&self.query, &labels);
+        let selected = self.list_state.selected().unwrap_or(0);
+        self.list_state.select(if self.filtered.is_empty() {
+            None
+        } else {
+            Some(selected.min(self.filtered.len() - 1))
+        });
+        self.revealed = None;
+    }
+
+    fn select_offset(&mut self, offset: isize) {
+        if self.filtered.is_empty() {
+            return;
+        }
+        let current = self.list_state.selected().unwrap_or(0) as isize;
+        let next = (current + offset).clamp(0, self.filtered.len() as isize - 1);
+        self.list_state.select(Some(next as usize));
+        self.revealed = None;
+    }
+
+    async fn handle_key(&mut self, key: KeyEvent) {
+        if key.modifiers.contains(KeyModifiers::CONTROL) && key.code == KeyCode::Char('c') {
+            self.quit = true;
+            return;
+        }
+        match self.mode {
+            Mode::Locked => self.handle_locked(key).await,
+            Mode::Browse => self.handle_browse(key).await,
+            Mode::Search => self.handle_search(key),
+            Mode::Form(_) => self.handle_form(key).await,
+            Mode::ConfirmDelete => self.handle_confirm_delete(key).await,
+        }
+    }
+
+    async fn handle_locked(&mut self, key: KeyEvent) {
+        match key.code {
+            KeyCode::Enter => self.unlock().await,
+            KeyCode::Esc => self.quit = true,
+            KeyCode::Backspace => {
+                self.input.pop();
+            }
+            KeyCode::Char(c) => self.input.push(c),
+            _ => {}
+        }
+    }
+
+    async fn handle_browse(&mut self, key: KeyEvent) {
+        self.status = None;
+        match key.code {
+            KeyCode::Char('q') => self.quit = true,
+            KeyCode::Char('/') => self.mode = Mode::Search,
+            KeyCode::Esc => {
+                self.query.clear();
+                self.refilter();
+            }
+            KeyCode::Up | KeyCode::Char('k') => self.select_offset(-1),
+            KeyCode::Down | KeyCode::Char('j') => self.select_offset(1),
+            KeyCode::PageUp => self.select_offset(-10),
+            KeyCode::PageDown => self.select_offset(10),
+            KeyCode::Home | KeyCode::Char('g') => self.select_offset(isize::MIN / 2),
+            KeyCode::End | KeyCode::Char('G') => self.select_offset(isize::MAX / 2),
+            KeyCode::Char('u') => self.copy_username(),
+            KeyCode::Char('p') => self.copy_password(),
+            KeyCode::Char('t') => self.copy_totp(),
+            KeyCode::Char('r') => self.toggle_reveal(),
+            KeyCode::Char('n') => self.mode = Mode::Form(Form::new(None)),
+            KeyCode::Char('e') if self.selected().is_some() => {
+                self.mode = Mode::Form(Form::new(self.selected()));
+            }
+            KeyCode::Char('d') if self.selected().is_some() => self.mode = Mode::ConfirmDelete,
+            KeyCode::Char('L') => self.lock(),
+            _ => {}
+        }
+    }
+
+    fn handle_search(&mut self, key: KeyEvent) {
+        match key.code {
+            KeyCode::Enter => self.mode = Mode::Browse,
+            KeyCode::Esc => {
+                self.query.clear();
+                self.mode = Mode::Browse;
+            }
+            KeyCode::Up => return self.select_offset(-1),
+            KeyCode::Down => return self.select_offset(1),
+            KeyCode::Backspace => {
+                self.query.pop();
+            }
+            KeyCode::Char(c) => self.query.push(c),
+            _ => return,
+        }
+        self.list_state.select(Some(0));
+        self.refilter();
+    }
+
+    async fn handle_form(&mut self, key: KeyEvent) {
+        let Mode::Form(form) = &mut self.mode else {
+            return;
+        };
+        let last = form.fields.len() - 1;
+        match key.code {
+            KeyCode::Esc => self.mode = Mode::Browse,
+            KeyCode::Tab | KeyCode::Down => form.focus = (form.focus + 1).min(last),
+            KeyCode::BackTab | KeyCode::Up => form.focus = form.focus.saturating_sub(1),
+            KeyCode::Enter if form.focus < last => form.focus += 1,
+            KeyCode::Enter => self.save_form().await,
+            KeyCode::Char('s') if key.modifiers.contains(KeyModifiers::CONTROL) => {
+                self.save_form().await
+            }
+            KeyCode::Char('d') if key.modifiers.contains(KeyModifiers::CONTROL) => {
+                let field = &mut form.fields[form.focus];
+                if field.clearable {
+                    field.value.clear();
+                    field.cleared = true;
+                }
+            }
+            KeyCode::Backspace => {
+                form.fields[form.focus].value.pop();
+            }
+            KeyCode::Char(c) => {
+                let field = &mut form.fields[form.focus];
+                field.value.push(c);
+                field.cleared = false;
+            }
+            _ => {}
+        }
+    }
+
+    async fn save_form(&mut self) {
+        let (Mode::Form(form), Some(master)) = (&self.mode, &self.master) else {
+            return;
+        };
+        let master_password = master.password.to_owned();
+        let result = match &form.entry_id {
+            None => create(form, master_password, &self.settings).await,
+            Some(id) => {
+                let current = self.entries.iter().find(|e| &e.id == id).cloned();
+                update(form, id.to_owned(), current, master_password).await
+            }
+        };
+        match result {
+            Ok(entry) => {
+                self.status = Some(format!("Saved {}", entry.name));
+                self.mode = Mode::Browse;
+                self.reload().await;
+                if let Some(position) = self
+                    .filtered
+                    .iter()
+                    .position(|i| self.entries[*i].id == entry.id)
+                {
+                    self.list_state.select(Some(position));
+                }
+            }
+            Err(e) => self.status = Some(e),
+        }
+    }
+
+    async fn handle_confirm_delete(&mut self, key: KeyEvent) {
+        self.mode = Mode::Browse;
+        if key.code != KeyCode::Char('y') {
+            return;
+        }
+        let Some(entry) = self.selected().cloned() else {
+            return;
+        };
+        match api::entries::delete_entry(entry.id).await {
+            Ok(_) => self.status = Some(format!("Deleted {}", entry.name)),
+            Err(e) => self.status = Some(e),
+        }
+        self.reload().await;
+    }
+
+    fn decrypt_password(&self) -> Result<String, Error> {
+        let (Some(entry), Some(master)) = (self.selected(), &self.master) else {
+            return Err("No entry selected".to_owned());
+        };
+        crypto::decrypt_password(
+            master.password.to_owned(),
+            entry.password.to_owned(),
+            entry.id.to_owned(),
+            master.master.id.to_owned(),
+        )
+    }
+
+    fn decrypt_totp(&self) -> Result<Option<String>, Error> {
+        let (Some(entry), Some(master)) = (self.selected(), &self.master) else {
+            return Err("No entry selected".to_owned());
+        };
+        api::totp::decrypt_totp(entry, &master.master, master.password.to_owned())
+    }
+
+    fn copy(&mut self, label: &str, item: Result<Option<String>, Error>) {
+        let result = item
+            .and_then(|item| item.ok_or(format!("This entry has no {}", label)))
+            .and_then(util::copy_to_clipboard);
+        self.status = Some(match result {
+            Ok(_) => format!("Copied {} to clipboard", label),
+            Err(e) => e,
+        });
+    }
+
+    fn copy_username(&mut self) {
+        let username = self.selected().and_then(|e| e.username.to_owned());
+        self.copy("username", Ok(username));
+    }
+
+    fn copy_password(&mut self) {
+        let password = self.decrypt_password().map(Some);
+        self.copy("password", password);
+    }
+
+    fn copy_totp(&mut self) {
+        let code = self
+            .decrypt_totp()
+            .and_then(|totp| totp.map(|t| api::totp::generate_code(&t)).transpose())
+            .map(|code| code.map(|(code, _)| code));
+        self.copy("TOTP code", code);
+    }
+
+    fn toggle_reveal(&mut self) {
+        if self.revealed.take().is_some() {
+            return;
+        }
+        let Some(entry_id) = self.selected().map(|e| e.id.to_owned()) else {
+            return;
+        };
+        match self.decrypt_password().and_then(|password| {
+            self.decrypt_totp().map(|totp| Revealed {
+                entry_id,
+                password,
+                totp,
+            })
+        }) {
+            Ok(revealed) => self.revealed = Some(revealed),
+            Err(e) => self.status = Some(e),
+        }
+    }
+}
+
+async fn create(
+    form: &Form,
+    master_password: String,
+    settings: &Settings,
+) -> Result<entry::Model, Error> {
+    // Checked first, so that an invalid secret does not leave the entry half created
+    if let Some(totp) = form.value(5) {
+        api::totp::parse(&totp)?;
+    }
+    let entry = api::entries::create_entry(
+        master_password.to_owned(),
+        form.value(0)
+            .unwrap_or(settings.entries.default_name.to_owned()),
+        form.value(1),
+        form.value(2),
+        form.value(4)
+            .unwrap_or_else(|| settings.generator.generate()),
+        form.value(3),
+    )
+    .await?;
+    match form.value(5) {
+        Some(totp) => api::entries::set_totp(master_password, entry.id, Some(totp)).await,
+        None => Ok(entry),
+    }
+}
+
+async fn update(
+    form: &Form,
+    entry_id: String,
+    current: Option<entry::Model>,
+    master_password: String,
+) -> Result<entry::Model, Error> {
+    let current = current.ok_or("Entry no longer exists")?;
+    if let Some(totp) = form.value(5) {
+        api::totp::parse(&totp)?;
+    }
+    let entry = api::entries::update_entry(
+        entry_id.to_owned(),
+        form.changed(0, Some(current.name)),
+        form.changed(1, current.description),
+        form.changed(2, current.username),
+        form.changed(3, current.url),
+        form.value(4).map(|p| (master_password.to_owned(), p)),
+    )
+    .await?;
+    if form.fields[5].cleared {
+        return api::entries::set_totp(master_password, entry_id, None).await;
+    }
+    match form.value(5) {
+        Some(totp) => api::entries::set_totp(master_password, entry_id, Some(totp)).await,
+        None => Ok(entry),
+    }
+}
@@ -0,0 +1,12 @@
+mod app;
+mod ui;
+
+use crate::util::{self, PrintError};
+
+pub async fn run() -> Result<(), ()> {
+    let settings = util::settings()?;
+    let mut terminal = ratatui::init();
+    let result = app::App::new(settings).run(&mut terminal).await;
+    ratatui::restore();
+    result.print_err()
+}
@@ -0,0 +1,235 @@
+use ratatui::{
+    layout::{Constraint, Flex, Layout, Rect},
+    style::{Color, Modifier, Style, Stylize},
+    text::{Line, Span},
+    widgets::{Block, Clear, List, ListItem, Paragraph, Wrap},
+    Frame,
+};
+
+use super::app::{App, Form, Mode, Revealed};
+
+const ACCENT: Color = Color::Rgb(136, 192, 205);
+
+pub fn draw(frame: &mut Frame, app: &mut App) {
+    if app.is_locked() {
+        draw_locked(frame, app);
+        return;
+    }
+
+    let [main, status] =
+        Layout::vertical([Constraint::Min(1), Constraint::Length(1)]).areas(frame.area());
+    let [list, detail] =
+        Layout::horizontal([Constraint::Percentage(40), Constraint::Percentage(60)]).areas(main);
+
+    draw_list(frame, app, list);
+    draw_detail(frame, app, detail);
+    draw_status(frame, app, status);
+
+    match &app.mode {
+        Mode::Form(form) => draw_form(frame, form),
+        Mode::ConfirmDelete => {
+            let name = app
+                .selected()
+                .map(|e| e.name.to_owned())
+                .unwrap_or_default();
+            let area = popup(frame.area(), 50, 3);
+            frame.render_widget(Clear, area);
+            frame.render_widget(
+                Paragraph::new(format!("Delete {}? (y/n)", name)).block(block(" Confirm ")),
+                area,
+            );
+        }
+        _ => {}
+    }
+}
+
+fn block(title: &str) -> Block<'_> {
+    Block::bordered()
+        .title(title)
+        .border_style(Style::default().fg(ACCENT))
+}
+
+fn popup(area: Rect, width: u16, height: u16) -> Rect {
+    let [area] = Layout::horizontal([Constraint::Length(width.min(area.width))])
+        .flex(Flex::Center)
+        .areas(area);
+    let [area] = Layout::vertical([Constraint::Length(height.min(area.height))])
+        .flex(Flex::Center)
+        .areas(area);
+    area
+}
+
+fn draw_locked(frame: &mut Frame, app: &App) {
+    let area = popup(frame.area(), 50, 6);
+    let mut lines = vec![
+        Line::from("Master Password:"),
+        Line::from("*".repeat(app.input.chars().count())),
+    ];
+    if let Some(status) = &app.status {
+        lines.push(Line::from(status.to_owned().red()));
+    }
+    frame.render_widget(
+        Paragraph::new(lines).block(block(" MyPass is locked ")),
+        area,
+    );
+    frame.set_cursor_position((area.x + 1 + app.input.chars().count() as u16, area.y + 2));
+}
+
+fn draw_list(frame: &mut Frame, app: &mut App, area: Rect) {
+    let title = format!(" Entries ({}/{}) ", app.filtered.len(), app.entries.len());
+    let [search, list] = Layout::vertical([Constraint::Length(3), Constraint::Min(1)]).areas(area);
+
+    let searching = matches!(app.mode, Mode::Search);
+    let query = if app.query.is_empty() && !searching {
+        Span::from("Press / to search").dark_gray()
+    } else {
+        Span::from(app.query.to_owned())
+    };
+    frame.render_widget(Paragraph::new(query).block(block(" Search ")), search);
+    if searching {
+        frame.set_cursor_position((
+            search.x + 1 + app.query.chars().count() as u16,
+            search.y + 1,
+        ));
+    }
+
+    let items: Vec<ListItem> = app
+        .filtered
+        .iter()
+        .map(|i| {
+            let entry = &app.entries[*i];
+            let mut line = vec![Span::from(entry.name.to_owned())];
+            if let Some(username) = &entry.username {
+                line.push(Span::from(format!("  {}", username)).dark_gray());
+            }
+            ListItem::new(Line::from(line))
+        })
+        .collect();
+    let list_widget = List::new(items)
+        .block(block(&title))
+        .highlight_style(Style::default().add_modifier(Modifier::REVERSED))
+        .highlight_symbol("> ");
+    frame.render_stateful_widget(list_widget, list, &mut app.list_state);
+}
+
+fn draw_detail(frame: &mut Frame, app: &App, area: Rect) {
+    let Some(entry) = app.selected() else {
+        let message = if app.entries.is_empty() {
+            "No password entries. Press n to create one"
+        } else {
+            "No matching entries"
+        };
+        frame.render_widget(Paragraph::new(message).block(block(" Entry ")), area);
+        return;
+    };
+
+    let revealed = app.revealed.as_ref().filter(|r| r.entry_id == entry.id);
+    let none = || "None".to_owned();
+    let row = |label: &str, value: String| {
+        Line::from(vec![
+            Span::from(format!("{:<14}", label)).fg(ACCENT),
+            Span::from(value),
+        ])
+    };
+
+    let password = revealed.map_or("**********".to_owned(), |r| r.password.to_owned());
+    let totp = match (revealed, &entry.totp) {
+        (_, None) => none(),
+        (Some(Revealed { totp: Some(t), .. }), _) => api::totp::generate_code(t)
+            .map(|(code, ttl)| format!("{} ({}s)", code, ttl))
+            .unwrap_or_else(|e| e),
+        _ => "******".to_owned(),
+    };
+
+    let lines = vec![
+        row("Name", entry.name.to_owned()),
+        row("ID", entry.id.to_owned()),
+        row("Username", entry.username.to_owned().unwrap_or_else(none)),
+        row("URL", entry.url.to_owned().unwrap_or_else(none)),
+        row("Password", password),
+        row("TOTP", totp),
+        row("Created", api::util::format_local_date(&entry.created_date)),
+        row(
+            "Modified",
+            api::util::format_local_date(&entry.modified_date),
+        ),
+        Line::from(""),
+        row(
+            "Description",
+            entry.description.to_owned().unwrap_or_else(none),
+        ),
+    ];
+    frame.render_widget(
+        Paragraph::new(lines)
+            .wrap(Wrap { trim: false })
+            .block(block(" Entry ")),
+        area,
+    );
+}
+
+fn draw_status(frame: &mut Frame, app: &App, area: Rect) {
+    let line = match (&app.status, &app.mode) {
+        (Some(status), _) => Line::from(status.to_owned()),
+        (None, Mode::Search) => Line::from("enter: done  esc: clear  ↑/↓: move").dark_gray(),
+        (None, Mode::Form(_)) => {
+            Line::from(
+                "tab/↑/↓: field  enter: next/save  ctrl-d: clear field  ctrl-s: save  esc: cancel",
+            )
+            .dark_gray()
+        }
+        (None, _) => Line::from(
+            "/: search  u: copy user  p: copy pass  t: copy totp  r: reveal  n: new  e: edit  d: delete  L: lock  q: quit",
+        )
+        .dark_gray(),
+    };
+    frame.render_widget(Paragraph::new(line), area);
+}
+
+fn draw_form(frame: &mut Frame, form: &Form) {
+    let title = if form.entry_id.is_some() {
+        " Edit entry "
+    } else {
+        " New entry "
+    };
+    let area = popup(frame.area(), 70, form.fields.len() as u16 + 2);
+    let lines: Vec<Line> = form
+        .fields
+        .iter()
+        .enumerate()
+        .map(|(i, field)| {
+            let value = if field.secret {
+                "*".repeat(field.value.chars().count())
+            } else {
+                field.value.to_owned()
+            };
+            let mut line = vec![
+                Span::from(format!("{:<14}", field.label)).fg(ACCENT),
+                Span::from(value),
+            ];
+            let hint = match (field.secret, form.entry_id.is_some(), field.label) {
+                _ if field.cleared => Some("(cleared)"),
+                (_, true, _) if field.clearable => Some("(leave blank to keep, ctrl-d to clear)"),
+                (true, true, _) => Some("(leave blank to keep)"),
+                (true, false, "Password") => Some("(leave blank to generate)"),
+                _ => None,
+            };
+            if let Some(hint) = hint.filter(|_| field.value.is_empty()) {
+                line.push(Span::from(hint).dark_gray());
+            }
+            let line = Line::from(line);
+            if i == form.focus {
+                line.add_modifier(Modifier::BOLD)
+            } else {
+                line
+            }
+        })
+        .collect();
+
+    frame.render_widget(Clear, area);
+    frame.render_widget(Paragraph::new(lines).block(block(title)), area);
+    let focused = &form.fields[form.focus];
+    frame.set_cursor_position((
+        area.x + 15 + focused.value.chars().count() as u16,
+        area.y + 1 + form.focus as u16,
+    ));
+}
@@ -1,13 +1,25 @@
-use std::{fmt::Display, io::Write};
+use std::{
+    fmt::Display,
+    fs,
+    io::{IsTerminal, Read, Write},
+    os::unix::{
+        fs::{FileTypeExt, PermissionsExt},
+        process::CommandExt,
+    },
+    path::Path,
+    process::{Command, Stdio},
+    time::Duration,
+};
 
-use api::error::Error;
+use api::{error::Error, settings::Settings};
+use tokio::net::UnixListener;
 
 pub fn get_master_password() -> Result<String, ()> {
     get_password_with_prompt_print("Master Password: ")
 }
 
 pub fn get_password_with_prompt_print(prompt: &str) -> Result<String, ()> {
-    get_password_with_prompt(prompt).map_err(|_| println!("Password is required"))
+    get_password_with_prompt(prompt).map_err(|_| eprintln!("Password is required"))
 }
 
 pub fn get_password_with_prompt(prompt: &str) -> Result<String, Error> {
@@ -46,23 +58,119 @@ pub fn unwrap_or_input(item: Option<String>, prompt: &str) -> Option<String> {
     item.or_else(|| input(prompt))
 }
 
-pub fn unwrap_or_input_number(
-    item: Option<usize>,
-    prompt: &str,
-    err_msg: &str,
-) -> Result<usize, ()> {
-    item.or_else(|| {
-        prompt_input(prompt)
-            .print_err()
-            .ok()
-            .and_then(|val| val.parse::<usize>().ok())
-            .and_then(|val| if val > 0 { Some(val) } else { None })
-    })
-    .ok_or_else(|| println!("{}", err_msg))
+pub fn is_interactive() -> bool {
+    std::io::stdin().is_terminal() && std::io::stdout().is_terminal()
+}
+
+/// The settings of the configuration file, with the environment overrides
+pub fn settings() -> Result<Settings, ()> {
+    api::configuration::get_settings().print_err()
 }
 
+/// Copies to the clipboard, which is cleared after `clipboard.clear_after` seconds by a
+/// background `mypass clear-clipboard` process
 pub fn copy_to_clipboard(item: String) -> Result<(), Error> {
-    cli_clipboard::set_contents(item).map_err(|_| "Failed to copy to clipboard".to_owned())
+    let clear_after = api::configuration::get_settings()?.clipboard.clear_after;
+    cli_clipboard::set_contents(item.to_owned())
+        .map_err(|_| "Failed to copy to clipboard".to_owned())?;
+    if clear_after == 0 {
+        return Ok(());
+    }
+    let err = "Failed to schedule clearing the clipboard";
+    let exe = std::env::current_exe().map_err(|_| err)?;
+    // The copied value is given on stdin so that it does not show in the process list,
+    // and the process has its own group so that it outlives an interrupted command
+    let mut child = Command::new(exe)
+        .args(["clear-clipboard", "--after", &clear_after.to_string()])
+        .stdin(Stdio::piped())
+        .stdout(Stdio::null())
+        .stderr(Stdio::null())
+        .process_group(0)
+        .spawn()
+        .map_err(|_| err)?;
+    child
+        .stdin
+        .take()
+        .ok_or(err)?
+        .write_all(item.as_bytes())
+        .map_err(|_| err.to_owned())
+}
+
+/// Clears the clipboard after a delay, unless the value read from stdin was replaced since
+pub fn clear_clipboard(after: u64) -> Result<(), ()> {
+    let mut copied = String::new();
+    std::io::stdin()
+        .read_to_string(&mut copied)
+        .map_err(|_| ())?;
+    std::thread::sleep(Duration::from_secs(after));
+    if cli_clipboard::get_contents().is_ok_and(|current| current == copied) {
+        cli_clipboard::set_contents(String::new()).map_err(|_| ())?;
+    }
+    Ok(())
+}
+
+/// Listens on a Unix socket only accessible to the current user, replacing a stale socket
+pub fn bind_unix_socket(path: &Path) -> Result<UnixListener, Error> {
+    let err = format!("Failed to listen on {}", path.to_string_lossy());
+    if let Ok(metadata) = fs::symlink_metadata(path) {
+        if !metadata.file_type().is_socket() {
+            return Err(format!(
+                "{} exists and is not a socket",
+                path.to_string_lossy()
+            ));
+        }
+        // A socket left behind by a process that did not shut down cleanly
+        if std::os::unix::net::UnixStream::connect(path).is_ok() {
+            return Err(format!(
+                "Another process is already listening on {}",
+                path.to_string_lossy()
+            ));
+        }
+        fs::remove_file(path).map_err(|_| err.to_owned())?;
+    }
+    if let Some(parent) = path.parent() {
+        fs::create_dir_all(parent).map_err(|_| err.to_owned())?;
+    }
+    let listener = UnixListener::bind(path).map_err(|_| err.to_owned())?;
+    fs::set_permissions(path, fs::Permissions::from_mode(0o600)).map_err(|_| err)?;
+    Ok(listener)
+}
+
+/// Prints the warnings logged by the migrations of the data store (e.g. dates that could not be
+/// converted), the only events logged by the crates that are meant for the user
+pub fn print_migration_warnings() {
+    use tracing_subscriber::layer::SubscriberExt;
+    tracing::subscriber::set_global_default(tracing_subscriber::registry().with(MigrationWarnings))
+        .ok();
+}
+
+struct MigrationWarnings;
+
+impl<S: tracing::Subscriber> tracing_subscriber::Layer<S> for MigrationWarnings {
+    fn enabled(
+        &self,
+        metadata: &tracing::Metadata<'_>,
+        _: tracing_subscriber::layer::Context<'_, S>,
+    ) -> bool {
+        metadata.target().split("::").next() == Some("migration")
+            && *metadata.level() <= tracing::Level::WARN
+    }
+
+    fn on_event(&self, event: &tracing::Event<'_>, _: tracing_subscriber::layer::Context<'_, S>) {
+        let mut message = Message(String::new());
+        event.record(&mut message);
+        eprintln!("Warning: {}", message.0);
+    }
+}
+
+struct Message(String);
+
+impl tracing::field::Visit for Message {
+    fn record_debug(&mut self, field: &tracing::field::Field, value: &dyn std::fmt::Debug) {
+        if field.name() == "message" {
+            self.0 = format!("{:?}", value);
+        }
+    }
 }
 
 pub trait PrintError<T, E> {
@@ -71,6 +179,6 @@ pub trait PrintError<T, E> {
 
 impl<T, E: Display> PrintError<T, E> for Result<T, E> {
     fn print_err(self) -> Result<T, ()> {
-        self.map_err(|e| println!("{}", e))
+        self.map_err(|e| eprintln!("{}", e))
     }
 }
@@ -0,0 +1,38 @@
+use api::configuration;
+
+use crate::{master::create_master, util::PrintError, view};
+
+pub fn list_vaults() -> Result<(), ()> {
+    let vaults = configuration::list_vaults().print_err()?;
+    let current = configuration::current_vault().print_err()?;
+    view::print_vaults(vaults, &current).print_err()
+}
+
+/// Adds a vault and creates its master, unless its database already has one
+pub async fn add_vault(name: String, path: Option<String>) -> Result<(), ()> {
+    let db_path = configuration::add_vault(name.to_owned(), path).print_err()?;
+    println!("Added vault {} at {}", name, db_path.to_string_lossy());
+
+    configuration::select_vault(name.to_owned());
+    if api::master::is_master_configured().await.print_err()? {
+        return Ok(());
+    }
+    println!("Create the master of vault {}", name);
+    create_master().await
+}
+
+pub fn remove_vault(name: String) -> Result<(), ()> {
+    let db_path = configuration::remove_vault(name.to_owned()).print_err()?;
+    println!(
+        "Removed vault {}, its database was kept at {}",
+        name,
+        db_path.to_string_lossy()
+    );
+    Ok(())
+}
+
+pub fn use_vault(name: String) -> Result<(), ()> {
+    configuration::set_default_vault(name.to_owned()).print_err()?;
+    println!("Vault {} is now the default vault", name);
+    Ok(())
+}
@@ -1,8 +1,14 @@
+use api::configuration::VaultInfo;
+use api::custom_fields::CustomField;
 use api::error::Error;
+use api::migrations::SchemaStatus;
+use api::settings::OutputFormat;
+use api::util;
 use cli_table::{
     format::Justify, print_stdout, Cell, CellStruct, Color, Style, Table, TableStruct,
 };
 use model::entities::{entry, master};
+use serde_json::{json, Value};
 
 pub fn print_entry(
     entry: entry::Model,
@@ -10,22 +16,39 @@ pub fn print_entry(
     password: Option<String>,
     verbose: bool,
 ) -> Result<(), Error> {
+    if output_format()? == OutputFormat::Json {
+        return print_json(&format_entry_json(entry, number, password));
+    }
     let table = vec![format_entry(entry, number, password, verbose)]
         .table()
         .title(format_entry_title(verbose));
     print_table(table)
 }
 
-pub fn print_entries(entries: Vec<entry::Model>, verbose: bool) -> Result<(), Error> {
+/// Lists entries with their number, `filtered` when they are those matching the list options
+pub fn print_entries(
+    entries: Vec<(usize, entry::Model)>,
+    verbose: bool,
+    filtered: bool,
+) -> Result<(), Error> {
+    if output_format()? == OutputFormat::Json {
+        let entries = entries
+            .into_iter()
+            .map(|(number, item)| format_entry_json(item, number, None))
+            .collect();
+        return print_json(&Value::Array(entries));
+    }
+    if entries.is_empty() && filtered {
+        println!("No password entries match the filters");
+        return Ok(());
+    }
     if entries.is_empty() {
         println!("No password entries. Create one with `mypass create`");
         return Ok(());
     }
     let table = entries
-        .clone()
-        .iter()
-        .enumerate()
-        .map(|(index, item)| format_entry(item.to_owned(), index + 1, None, verbose))
+        .into_iter()
+        .map(|(number, item)| format_entry(item, number, None, verbose))
         .table()
         .title(format_entry_title(verbose));
 
@@ -33,6 +56,18 @@ pub fn print_entries(entries: Vec<entry::Model>, verbose: bool) -> Result<(), Er
     Ok(())
 }
 
+pub fn print_custom_fields(fields: Vec<CustomField>) -> Result<(), Error> {
+    if fields.is_empty() {
+        return Ok(());
+    }
+    let table = fields
+        .into_iter()
+        .map(|field| vec![field.name.cell(), field.value.cell()])
+        .table()
+        .title(vec!["Field".to_owned().cell(), "Value".to_owned().cell()]);
+    print_table(table)
+}
+
 pub fn print_master(master: master::Model) -> Result<(), Error> {
     let table = vec![format_master(master)]
         .table()
@@ -40,6 +75,70 @@ pub fn print_master(master: master::Model) -> Result<(), Error> {
     print_table(table)
 }
 
+pub fn print_settings(settings: Vec<(String, String)>) -> Result<(), Error> {
+    let table = settings
+        .into_iter()
+        .map(|(key, value)| vec![key.cell(), value.cell()])
+        .table()
+        .title(vec!["Setting".to_owned().cell(), "Value".to_owned().cell()]);
+    print_table(table)
+}
+
+/// Lists the migrations of a data store, including those of a newer version of MyPass
+pub fn print_migrations(status: &SchemaStatus) -> Result<(), Error> {
+    println!("Data store: {}", status.path.to_string_lossy());
+    let mut rows: Vec<Vec<CellStruct>> = status
+        .migrations
+        .iter()
+        .map(|migration| {
+            let state = if migration.applied {
+                "applied"
+            } else {
+                "pending"
+            };
+            vec![migration.name.to_owned().cell(), state.cell()]
+        })
+        .collect();
+    rows.extend(
+        status
+            .unknown
+            .iter()
+            .map(|name| vec![name.to_owned().cell(), "unknown (newer version)".cell()]),
+    );
+    let table = rows.table().title(vec![
+        "Migration".to_owned().cell(),
+        "Status".to_owned().cell(),
+    ]);
+    print_table(table)
+}
+
+/// Lists the vaults, marking the default vault and the vault in use
+pub fn print_vaults(vaults: Vec<VaultInfo>, current: &str) -> Result<(), Error> {
+    let table = vaults
+        .into_iter()
+        .map(|vault| {
+            let mut status = Vec::new();
+            if vault.is_default {
+                status.push("default");
+            }
+            if vault.name == current {
+                status.push("in use");
+            }
+            vec![
+                vault.name.cell(),
+                vault.path.to_string_lossy().into_owned().cell(),
+                status.join(", ").cell(),
+            ]
+        })
+        .table()
+        .title(vec![
+            "Vault".to_owned().cell(),
+            "Path to data store".to_owned().cell(),
+            "Status".to_owned().cell(),
+        ]);
+    print_table(table)
+}
+
 pub fn print_path(path: String) -> Result<(), Error> {
     let table = vec![vec![path.cell()]]
         .table()
@@ -63,8 +162,8 @@ fn format_entry(
     entry_row.push(entry.name.to_owned().cell());
     if verbose {
         entry_row.push(entry.id.to_owned().cell());
-        entry_row.push(entry.created_date.cell());
-        entry_row.push(entry.modified_date.cell());
+        entry_row.push(util::format_local_date(&entry.created_date).cell());
+        entry_row.push(util::format_local_date(&entry.modified_date).cell());
         entry_row.push(
             entry
                 .description
@@ -73,6 +172,8 @@ fn format_entry(
                 .cell(),
         );
         entry_row.push(entry.url.to_owned().unwrap_or("None".to_owned()).cell());
+        entry_row.push(entry.folder.to_owned().unwrap_or("None".to_owned()).cell());
+        entry_row.push(entry.tags.to_owned().unwrap_or("None".to_owned()).cell());
     }
     entry_row.push(
         entry
@@ -101,6 +202,8 @@ fn format_entry_title(verbose: bool) -> Vec<CellStruct> {
         title.push("Last Modified Date".to_owned().cell());
         title.push("Description".to_owned().cell());
         title.push("URL".to_owned().cell());
+        title.push("Folder".to_owned().cell());
+        title.push("Tags".to_owned().cell());
     }
     title.push("Username".to_owned().cell());
     title.push("Password".to_owned().cell());
@@ -122,10 +225,41 @@ fn format_master_title() -> Vec<CellStruct> {
     vec!["Name".to_owned().cell(), "Description".to_owned().cell()]
 }
 
-fn format_table(table: TableStruct) -> TableStruct {
-    table.foreground_color(Some(Color::Rgb(136, 192, 205)))
+/// An entry as listed with `output.format = "json"`, the password only when revealed
+fn format_entry_json(entry: entry::Model, number: usize, password: Option<String>) -> Value {
+    let mut value = json!({
+        "number": number,
+        "id": entry.id,
+        "name": entry.name,
+        "description": entry.description,
+        "username": entry.username,
+        "url": entry.url,
+        "folder": entry.folder,
+        "tags": entry.tags,
+        "created_date": util::format_date(&entry.created_date),
+        "modified_date": util::format_date(&entry.modified_date),
+    });
+    if let Some(password) = password {
+        value["password"] = Value::String(password);
+    }
+    value
+}
+
+fn output_format() -> Result<OutputFormat, Error> {
+    Ok(api::configuration::get_settings()?.output.format)
+}
+
+fn print_json(value: &Value) -> Result<(), Error> {
+    let json = serde_json::to_string_pretty(value).map_err(|_| "Error displaying".to_owned())?;
+    println!("{}", json);
+    Ok(())
+}
+
+fn format_table(table: TableStruct) -> Result<TableStruct, Error> {
+    let (r, g, b) = api::configuration::get_settings()?.output.rgb()?;
+    Ok(table.foreground_color(Some(Color::Rgb(r, g, b))))
 }
 
 fn print_table(table: TableStruct) -> Result<(), Error> {
-    print_stdout(format_table(table)).map_err(|_| "Error displaying".to_owned())
+    print_stdout(format_table(table)?).map_err(|_| "Error displaying".to_owned())
 }
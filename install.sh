@@ -17,3 +17,5 @@ tag_without_v=$(echo "$latest_tag" | tr -d "v")
 cd "src/mypass-${tag_without_v}"
 cargo build -r
 cp ./target/release/mypass ../..
+cp ./target/release/git-credential-mypass ../..
+cp ./target/release/mypass-native-host ../..
@@ -0,0 +1,105 @@
+use chrono::{DateTime, Local, NaiveDate, NaiveDateTime, SecondsFormat, TimeZone, Utc};
+use sea_orm_migration::prelude::*;
+use sea_orm_migration::sea_orm::ConnectionTrait;
+
+/// Dates were stored as the local time of the machine, without its offset
+const LOCAL_FORMAT: &str = "%Y-%m-%d %H:%M:%S";
+
+#[derive(DeriveMigrationName)]
+pub struct Migration;
+
+#[async_trait::async_trait]
+impl MigrationTrait for Migration {
+    /// Converts the dates from local time to UTC, in the format of the `DateTimeUtc` columns.
+    /// Dates that cannot be read (e.g. free text from an import) are replaced with the current
+    /// time, and logged as warnings.
+    async fn up(&self, manager: &SchemaManager) -> Result<(), DbErr> {
+        convert(manager, |date| {
+            parse_local(date).map(|date| date.to_rfc3339_opts(SecondsFormat::AutoSi, false))
+        })
+        .await
+    }
+
+    async fn down(&self, manager: &SchemaManager) -> Result<(), DbErr> {
+        convert(manager, |date| {
+            DateTime::parse_from_rfc3339(date)
+                .ok()
+                .map(|date| date.with_timezone(&Local).format(LOCAL_FORMAT).to_string())
+        })
+        .await
+    }
+}
+
+/// Reads the dates written by earlier versions and by imports, see `api::util::parse_date`
+fn parse_local(date: &str) -> Option<DateTime<Utc>> {
+    let date = date.trim();
+    if let Ok(date) = DateTime::parse_from_rfc3339(date) {
+        return Some(date.with_timezone(&Utc));
+    }
+    let date = NaiveDateTime::parse_from_str(date, LOCAL_FORMAT)
+        .or_else(|_| NaiveDateTime::parse_from_str(date, "%Y-%m-%dT%H:%M:%S"))
+        .ok()
+        .or_else(|| {
+            NaiveDate::parse_from_str(date, "%Y-%m-%d")
+                .ok()
+                .and_then(|day| day.and_hms_opt(0, 0, 0))
+        })?;
+    Local
+        .from_local_datetime(&date)
+        .earliest()
+        .map(|date| date.with_timezone(&Utc))
+}
+
+/// Converts the dates of every entry. A date that cannot be converted is replaced with the
+/// current time, and logged with the entry, since only the backup taken before migrating
+/// keeps it.
+async fn convert(
+    manager: &SchemaManager<'_>,
+    convert: impl Fn(&str) -> Option<String>,
+) -> Result<(), DbErr> {
+    let conn = manager.get_connection();
+    let builder = conn.get_database_backend();
+    let select = Query::select()
+        .columns([Entry::Id, Entry::CreatedDate, Entry::ModifiedDate])
+        .from(Entry::Table)
+        .to_owned();
+    // The current time, in the format the dates are converted to
+    let now =
+        convert(&Utc::now().to_rfc3339_opts(SecondsFormat::AutoSi, false)).unwrap_or_default();
+    for row in conn.query_all(builder.build(&select)).await? {
+        let id: String = row.try_get("", "id")?;
+        let created_date: String = row.try_get("", "created_date")?;
+        let modified_date: String = row.try_get("", "modified_date")?;
+        let replace = |column: &str, date: &str| {
+            convert(date).unwrap_or_else(|| {
+                tracing::warn!(
+                    "The {} date {:?} of entry {} cannot be read, it is replaced with the current time",
+                    column,
+                    date,
+                    id
+                );
+                now.to_owned()
+            })
+        };
+        let created_date = replace("created", &created_date);
+        let modified_date = replace("modified", &modified_date);
+        let update = Query::update()
+            .table(Entry::Table)
+            .values([
+                (Entry::CreatedDate, created_date.into()),
+                (Entry::ModifiedDate, modified_date.into()),
+            ])
+            .and_where(Expr::col(Entry::Id).eq(id))
+            .to_owned();
+        manager.exec_stmt(update).await?;
+    }
+    Ok(())
+}
+
+#[derive(DeriveIden)]
+enum Entry {
+    Table,
+    Id,
+    CreatedDate,
+    ModifiedDate,
+}
@@ -0,0 +1,65 @@
+use sea_orm_migration::prelude::*;
+
+#[derive(DeriveMigrationName)]
+pub struct Migration;
+
+#[async_trait::async_trait]
+impl MigrationTrait for Migration {
+    async fn up(&self, manager: &SchemaManager) -> Result<(), DbErr> {
+        manager
+            .create_table(
+                Table::create()
+                    .table(CustomField::Table)
+                    .if_not_exists()
+                    .col(
+                        ColumnDef::new(CustomField::Id)
+                            .uuid()
+                            .primary_key()
+                            .not_null(),
+                    )
+                    .col(ColumnDef::new(CustomField::EntryId).uuid().not_null())
+                    .col(ColumnDef::new(CustomField::Name).string().not_null())
+                    .col(
+                        ColumnDef::new(CustomField::Value)
+                            .blob(BlobSize::Tiny)
+                            .not_null(),
+                    )
+                    .col(
+                        ColumnDef::new(CustomField::Protected)
+                            .boolean()
+                            .not_null()
+                            .default(false),
+                    )
+                    .foreign_key(
+                        ForeignKey::create()
+                            .from(CustomField::Table, CustomField::EntryId)
+                            .to(Entry::Table, Entry::Id)
+                            .on_delete(ForeignKeyAction::Cascade),
+                    )
+                    .to_owned(),
+            )
+            .await
+    }
+
+    async fn down(&self, manager: &SchemaManager) -> Result<(), DbErr> {
+        manager
+            .drop_table(Table::drop().table(CustomField::Table).to_owned())
+            .await
+    }
+}
+
+#[derive(DeriveIden)]
+enum CustomField {
+    Table,
+    Id,
+    EntryId,
+    Name,
+    Value,
+    Protected,
+}
+
+#[derive(DeriveIden)]
+enum Entry {
+    Table,
+    Id,
+}
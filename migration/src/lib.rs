@@ -1,5 +1,11 @@
 pub use sea_orm_migration::prelude::*;
 
+mod add_entry_folder_column;
+mod add_entry_ssh_public_key_column;
+mod add_entry_tags_column;
+mod add_entry_totp_column;
+mod convert_entry_dates_to_utc;
+mod create_custom_field_table;
 mod create_entry_table;
 mod create_master_table;
 
@@ -11,6 +17,12 @@ impl MigratorTrait for Migrator {
         vec![
             Box::new(create_entry_table::Migration),
             Box::new(create_master_table::Migration),
+            Box::new(add_entry_totp_column::Migration),
+            Box::new(add_entry_ssh_public_key_column::Migration),
+            Box::new(add_entry_folder_column::Migration),
+            Box::new(create_custom_field_table::Migration),
+            Box::new(add_entry_tags_column::Migration),
+            Box::new(convert_entry_dates_to_utc::Migration),
         ]
     }
 }
@@ -13,11 +13,25 @@ pub struct Model {
     #[sea_orm(column_type = "Binary(BlobSize::Blob(None))")]
     pub password: Vec<u8>,
     pub url: Option<String>,
-    pub created_date: String,
-    pub modified_date: String,
+    pub created_date: DateTimeUtc,
+    pub modified_date: DateTimeUtc,
+    #[sea_orm(column_type = "Binary(BlobSize::Blob(None))", nullable)]
+    pub totp: Option<Vec<u8>>,
+    pub ssh_public_key: Option<String>,
+    pub folder: Option<String>,
+    pub tags: Option<String>,
 }
 
 #[derive(Copy, Clone, Debug, EnumIter, DeriveRelation)]
-pub enum Relation {}
+pub enum Relation {
+    #[sea_orm(has_many = "super::custom_field::Entity")]
+    CustomField,
+}
+
+impl Related<super::custom_field::Entity> for Entity {
+    fn to() -> RelationDef {
+        Relation::CustomField.def()
+    }
+}
 
 impl ActiveModelBehavior for ActiveModel {}
@@ -2,5 +2,6 @@
 
 pub mod prelude;
 
+pub mod custom_field;
 pub mod entry;
 pub mod master;
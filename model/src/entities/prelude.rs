@@ -1,4 +1,5 @@
 //! `SeaORM` Entity. Generated by sea-orm-codegen 0.12.14
 
+pub use super::custom_field::Entity as CustomField;
 pub use super::entry::Entity as Entry;
 pub use super::master::Entity as Master;
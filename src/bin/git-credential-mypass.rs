@@ -0,0 +1,6 @@
+use cli::run_git_credential;
+
+#[tokio::main]
+async fn main() {
+    run_git_credential().await
+}
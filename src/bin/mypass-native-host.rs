@@ -0,0 +1,6 @@
+use cli::run_native_host;
+
+#[tokio::main]
+async fn main() {
+    run_native_host().await
+}